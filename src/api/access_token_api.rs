@@ -0,0 +1,122 @@
+use actix_web::{delete, get, HttpResponse, post, put};
+use actix_web::web::{Data, Json, Path, ReqData};
+use chrono::{Duration, Utc};
+use mongodb::bson::DateTime;
+use mongodb::bson::oid::ObjectId;
+
+use crate::dto::access_token_preview::{AccessTokenPreview, CreatedAccessToken};
+use crate::dto::create_access_token::CreateAccessToken;
+use crate::dto::update_access_token::UpdateAccessToken;
+use crate::model::access_token_model::{PersonalAccessToken, TokenScope};
+use crate::model::user_model::User;
+use crate::repository::access_token_repository::AccessTokenRepository;
+use crate::service::token_service::{ACCESS_TOKEN_PREFIX, generate_token, hash_token};
+use crate::validator::request_validators::validate_request_body;
+
+#[post("/user/tokens")]
+pub async fn create_access_token(token_repo: Data<AccessTokenRepository>, logged_user_data: Option<ReqData<User>>,
+                                 body: Json<CreateAccessToken>) -> HttpResponse {
+    let logged_user = match logged_user_data {
+        Some(user) => user,
+        _ => return HttpResponse::Unauthorized().finish()
+    };
+
+    let request = match validate_request_body(body).await {
+        Ok(request) => request,
+        Err(bad_request) => return bad_request
+    };
+
+    let requested_scopes = request.scopes.unwrap_or_else(|| vec![TokenScope::TasksRead, TokenScope::TasksWrite]);
+    let mut scopes: Vec<TokenScope> = Vec::new();
+    for scope in requested_scopes {
+        if !scopes.contains(&scope) {
+            scopes.push(scope);
+        }
+    }
+    if scopes.is_empty() {
+        return HttpResponse::BadRequest().json("At least one scope is required");
+    }
+
+    let token = format!("{}{}", ACCESS_TOKEN_PREFIX, generate_token());
+    let now = Utc::now();
+    let mut new_token = PersonalAccessToken {
+        id: None,
+        user_id: logged_user.id.unwrap(),
+        name: request.name,
+        token_hash: hash_token(&token),
+        scopes,
+        created_at: DateTime::from_chrono(now),
+        expires_at: request.expires_in_days.map(|days| DateTime::from_chrono(now + Duration::days(days))),
+        last_used_at: None,
+    };
+
+    match token_repo.create_token(&new_token).await {
+        Ok(result) => {
+            new_token.id = result.inserted_id.as_object_id();
+            HttpResponse::Created().json(CreatedAccessToken { token, details: new_token.into() })
+        }
+        Err(e) => HttpResponse::InternalServerError().body(e.to_string())
+    }
+}
+
+#[get("/user/tokens")]
+pub async fn get_access_tokens(token_repo: Data<AccessTokenRepository>,
+                               logged_user_data: Option<ReqData<User>>) -> HttpResponse {
+    let logged_user = match logged_user_data {
+        Some(user) => user,
+        _ => return HttpResponse::Unauthorized().finish()
+    };
+
+    match token_repo.find_all_for_user(&logged_user.id.unwrap()).await {
+        Ok(tokens) => {
+            let tokens: Vec<AccessTokenPreview> = tokens.into_iter().map(AccessTokenPreview::from).collect();
+            HttpResponse::Ok().json(tokens)
+        }
+        Err(e) => HttpResponse::InternalServerError().body(e.to_string())
+    }
+}
+
+#[put("/user/tokens/{id}")]
+pub async fn update_access_token(token_repo: Data<AccessTokenRepository>, logged_user_data: Option<ReqData<User>>,
+                                 token_id: Path<String>, body: Json<UpdateAccessToken>) -> HttpResponse {
+    let logged_user = match logged_user_data {
+        Some(user) => user,
+        _ => return HttpResponse::Unauthorized().finish()
+    };
+
+    let request = match validate_request_body(body).await {
+        Ok(request) => request,
+        Err(bad_request) => return bad_request
+    };
+
+    let token_id = match ObjectId::parse_str(token_id.as_str()) {
+        Ok(id) => id,
+        Err(_) => return HttpResponse::NotFound().json("Token not found")
+    };
+
+    match token_repo.rename(&token_id, &logged_user.id.unwrap(), &request.name).await {
+        Ok(Some(token)) => HttpResponse::Ok().json(AccessTokenPreview::from(token)),
+        Ok(None) => HttpResponse::NotFound().json("Token not found"),
+        Err(e) => HttpResponse::InternalServerError().body(e.to_string())
+    }
+}
+
+#[delete("/user/tokens/{id}")]
+pub async fn delete_access_token(token_repo: Data<AccessTokenRepository>, logged_user_data: Option<ReqData<User>>,
+                                 token_id: Path<String>) -> HttpResponse {
+    let logged_user = match logged_user_data {
+        Some(user) => user,
+        _ => return HttpResponse::Unauthorized().finish()
+    };
+
+    let token_id = match ObjectId::parse_str(token_id.as_str()) {
+        Ok(id) => id,
+        Err(_) => return HttpResponse::NotFound().json("Token not found")
+    };
+
+    match token_repo.delete(&token_id, &logged_user.id.unwrap()).await {
+        Ok(result) if result.deleted_count == 0 => HttpResponse::NotFound().json("Token not found"),
+        Ok(_) => HttpResponse::NoContent().finish(),
+        Err(e) => HttpResponse::InternalServerError().body(e.to_string())
+    }
+}
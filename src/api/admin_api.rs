@@ -0,0 +1,202 @@
+use actix_web::{get, HttpResponse, post, put};
+use actix_web::web::{Data, Json, Path, Query, ReqData};
+use mongodb::bson::oid::ObjectId;
+
+use crate::dto::mail_delivery::MailDelivery;
+use crate::dto::mail_search::MailSearch;
+use crate::dto::update_role::UpdateRole;
+use crate::dto::user_search::UserSearch;
+use crate::dto::user_summary::UserSummary;
+use crate::model::user_model::User;
+use crate::repository::one_time_token_repository::OneTimeTokenRepository;
+use crate::repository::refresh_token_repository::RefreshTokenRepository;
+use crate::repository::task_repository::TaskRepository;
+use crate::repository::user_repository::UserRepository;
+use crate::service::email_service::start_password_reset;
+use crate::service::outbox_service::Outbox;
+use crate::service::scheduler_service::Scheduler;
+use crate::validator::request_validators::{validate_query, validate_request_body};
+
+const DEFAULT_PAGE_SIZE: i64 = 20;
+
+#[get("/users")]
+pub async fn get_users(user_repo: Data<UserRepository>, task_repo: Data<TaskRepository>,
+                       query: Query<UserSearch>) -> HttpResponse {
+    let search = match validate_query(query).await {
+        Ok(search) => search,
+        Err(bad_request) => return bad_request
+    };
+
+    let per_page = search.per_page.unwrap_or(DEFAULT_PAGE_SIZE);
+    let skip = (search.page.unwrap_or(1) - 1) * per_page as u64;
+    let users = match user_repo.search(search.q.as_deref(), skip, per_page).await {
+        Ok(users) => users,
+        Err(e) => return HttpResponse::InternalServerError().body(e.to_string())
+    };
+
+    let user_ids: Vec<ObjectId> = users.iter().map(|user| user.id.unwrap()).collect();
+    let task_counts = match task_repo.count_by_users(&user_ids).await {
+        Ok(counts) => counts,
+        Err(e) => return HttpResponse::InternalServerError().body(e.to_string())
+    };
+
+    let summaries: Vec<UserSummary> = users.into_iter()
+        .map(|user| {
+            let task_count = task_counts.get(&user.id.unwrap()).copied().unwrap_or(0);
+            UserSummary::new(user, task_count)
+        })
+        .collect();
+
+    HttpResponse::Ok().json(summaries)
+}
+
+#[get("/users/{id}")]
+pub async fn get_user(user_repo: Data<UserRepository>, task_repo: Data<TaskRepository>,
+                      user_id: Path<String>) -> HttpResponse {
+    let user_id = match ObjectId::parse_str(user_id.as_str()) {
+        Ok(id) => id,
+        Err(_) => return HttpResponse::NotFound().json("User not found")
+    };
+
+    let user = match user_repo.find_by_id(&user_id).await {
+        Ok(Some(user)) => user,
+        Ok(None) => return HttpResponse::NotFound().json("User not found"),
+        Err(e) => return HttpResponse::InternalServerError().body(e.to_string())
+    };
+
+    match task_repo.count_by_users(&[user_id]).await {
+        Ok(counts) => {
+            let task_count = counts.get(&user_id).copied().unwrap_or(0);
+            HttpResponse::Ok().json(UserSummary::new(user, task_count))
+        }
+        Err(e) => HttpResponse::InternalServerError().body(e.to_string())
+    }
+}
+
+#[put("/users/{id}/disable")]
+pub async fn disable_user(user_repo: Data<UserRepository>, refresh_repo: Data<RefreshTokenRepository>,
+                          logged_user_data: Option<ReqData<User>>, user_id: Path<String>) -> HttpResponse {
+    let logged_user = match logged_user_data {
+        Some(user) => user,
+        _ => return HttpResponse::Unauthorized().finish()
+    };
+
+    let user_id = match ObjectId::parse_str(user_id.as_str()) {
+        Ok(id) => id,
+        Err(_) => return HttpResponse::NotFound().json("User not found")
+    };
+
+    if logged_user.id == Some(user_id) {
+        return HttpResponse::BadRequest().json("You can't disable your own account");
+    }
+
+    match user_repo.set_disabled(&user_id, true).await {
+        Ok(Some(_)) => (),
+        Ok(None) => return HttpResponse::NotFound().json("User not found"),
+        Err(e) => return HttpResponse::InternalServerError().body(e.to_string())
+    };
+
+    match refresh_repo.revoke_all_for_user(&user_id).await {
+        Ok(_) => HttpResponse::NoContent().finish(),
+        Err(e) => HttpResponse::InternalServerError().body(e.to_string())
+    }
+}
+
+#[put("/users/{id}/enable")]
+pub async fn enable_user(user_repo: Data<UserRepository>, scheduler: Data<Scheduler>,
+                         user_id: Path<String>) -> HttpResponse {
+    let user_id = match ObjectId::parse_str(user_id.as_str()) {
+        Ok(id) => id,
+        Err(_) => return HttpResponse::NotFound().json("User not found")
+    };
+
+    match user_repo.set_disabled(&user_id, false).await {
+        Ok(Some(_)) => {
+            scheduler.reschedule();
+            HttpResponse::NoContent().finish()
+        }
+        Ok(None) => HttpResponse::NotFound().json("User not found"),
+        Err(e) => HttpResponse::InternalServerError().body(e.to_string())
+    }
+}
+
+/// Makes a user an admin or takes the role away again. Admins can't demote themselves,
+/// so there is always one left.
+#[put("/users/{id}/role")]
+pub async fn set_user_role(user_repo: Data<UserRepository>, logged_user_data: Option<ReqData<User>>,
+                           user_id: Path<String>, body: Json<UpdateRole>) -> HttpResponse {
+    let logged_user = match logged_user_data {
+        Some(user) => user,
+        _ => return HttpResponse::Unauthorized().finish()
+    };
+
+    let request = match validate_request_body(body).await {
+        Ok(request) => request,
+        Err(bad_request) => return bad_request
+    };
+
+    let user_id = match ObjectId::parse_str(user_id.as_str()) {
+        Ok(id) => id,
+        Err(_) => return HttpResponse::NotFound().json("User not found")
+    };
+
+    if logged_user.id == Some(user_id) {
+        return HttpResponse::BadRequest().json("You can't change your own role");
+    }
+
+    match user_repo.set_role(&user_id, request.role).await {
+        Ok(Some(_)) => HttpResponse::NoContent().finish(),
+        Ok(None) => HttpResponse::NotFound().json("User not found"),
+        Err(e) => HttpResponse::InternalServerError().body(e.to_string())
+    }
+}
+
+#[post("/users/{id}/password-reset")]
+pub async fn force_password_reset(user_repo: Data<UserRepository>, refresh_repo: Data<RefreshTokenRepository>,
+                                  token_repo: Data<OneTimeTokenRepository>, outbox: Data<Outbox>,
+                                  user_id: Path<String>) -> HttpResponse {
+    let user_id = match ObjectId::parse_str(user_id.as_str()) {
+        Ok(id) => id,
+        Err(_) => return HttpResponse::NotFound().json("User not found")
+    };
+
+    let user = match user_repo.require_password_reset(&user_id).await {
+        Ok(Some(user)) => user,
+        Ok(None) => return HttpResponse::NotFound().json("User not found"),
+        Err(e) => return HttpResponse::InternalServerError().body(e.to_string())
+    };
+
+    if let Err(e) = refresh_repo.revoke_all_for_user(&user_id).await {
+        return HttpResponse::InternalServerError().body(e.to_string());
+    }
+
+    match start_password_reset(&outbox, &token_repo, &user_id, user.email).await {
+        Ok(_) => HttpResponse::Accepted().finish(),
+        Err(e) => HttpResponse::InternalServerError().body(e.to_string())
+    }
+}
+
+/// The delivery log of all mails, newest first, optionally of one user or in one state.
+#[get("/outbox")]
+pub async fn get_outbox(outbox: Data<Outbox>, query: Query<MailSearch>) -> HttpResponse {
+    let search = match validate_query(query).await {
+        Ok(search) => search,
+        Err(bad_request) => return bad_request
+    };
+
+    let user_id = match search.user_id.as_deref().map(ObjectId::parse_str) {
+        Some(Ok(id)) => Some(id),
+        Some(Err(_)) => return HttpResponse::BadRequest().json("Invalid user_id"),
+        None => None
+    };
+
+    let per_page = search.per_page.unwrap_or(DEFAULT_PAGE_SIZE);
+    let skip = (search.page.unwrap_or(1) - 1) * per_page as u64;
+    match outbox.delivery_log(user_id.as_ref(), search.status, skip, per_page).await {
+        Ok(messages) => {
+            let deliveries: Vec<MailDelivery> = messages.into_iter().map(MailDelivery::from).collect();
+            HttpResponse::Ok().json(deliveries)
+        }
+        Err(e) => HttpResponse::InternalServerError().body(e.to_string())
+    }
+}
@@ -1,72 +1,392 @@
-use actix_web::{HttpResponse, post};
-use actix_web::web::{Data, Json};
+use actix_web::{get, HttpRequest, HttpResponse, post};
+use actix_web::web::{Data, Json, Query, ReqData};
 use actix_web_httpauth::extractors::basic::BasicAuth;
-use argonautica::{Hasher, Verifier};
-use hmac::digest::KeyInit;
-use hmac::Hmac;
-use jwt::SignWithKey;
-use sha2::Sha256;
+use chrono::Utc;
+use log::warn;
+use mongodb::bson::DateTime;
+use mongodb::bson::oid::ObjectId;
 
 use crate::dto::create_user::CreateUser;
+use crate::dto::forgot_password::ForgotPassword;
+use crate::dto::mfa_challenge::MfaChallenge;
+use crate::dto::mfa_sign_in::MfaSignIn;
+use crate::dto::refresh_token_request::RefreshTokenRequest;
+use crate::dto::reset_password::ResetPassword;
+use crate::dto::resend_verification::ResendVerification;
 use crate::dto::token_claims::TokenClaims;
+use crate::dto::verify_email::VerifyEmail;
+use crate::model::one_time_token_model::TokenPurpose;
+use crate::model::revoked_token_model::RevokedToken;
+use crate::model::user_model::User;
+use crate::repository::is_duplicate_key;
+use crate::repository::login_attempt_repository::LoginAttemptRepository;
+use crate::repository::one_time_token_repository::OneTimeTokenRepository;
+use crate::repository::rate_limit_repository::RateLimitRepository;
+use crate::repository::refresh_token_repository::RefreshTokenRepository;
+use crate::repository::revoked_token_repository::RevokedTokenRepository;
 use crate::repository::user_repository::UserRepository;
+use crate::service::email_service::{start_email_verification, start_password_reset};
+use crate::service::outbox_service::Outbox;
+use crate::service::mfa_service::verify_second_factor;
+use crate::service::password_service::{hash_password, verify_dummy_password, verify_password};
+use crate::service::signing_key_service::SigningKeys;
+use crate::service::throttle_service::{account_key, check_rate_limit, clear_failed_sign_ins, client_ip, ip_key,
+                                       register_failed_sign_in, remaining_lockout, SIGN_IN_RATE_LIMIT,
+                                       SIGN_UP_RATE_LIMIT, too_many_requests};
+use crate::service::token_service::{hash_token, issue_tokens, sign_mfa_token, verify_mfa_token};
+use crate::validator::request_validators::validate_request_body;
 
 #[post("/auth/sign-up")]
-pub async fn sign_up(db: Data<UserRepository>, body: Json<CreateUser>) -> HttpResponse {
-    let new_user: CreateUser = body.into_inner();
-    let hash_secret = std::env::var("HASH_SECRET").expect("HASH_SECRET not provided");
-    let mut hasher = Hasher::default();
-
-    let password_hash = hasher
-        .with_password(new_user.password)
-        .with_secret_key(hash_secret)
-        .hash()
-        .unwrap();
-
-    let user_details = db.create_user(new_user.email, password_hash).await;
-    match user_details {
+pub async fn sign_up(req: HttpRequest, db: Data<UserRepository>, token_repo: Data<OneTimeTokenRepository>,
+                     rate_limit_repo: Data<RateLimitRepository>, outbox: Data<Outbox>,
+                     body: Json<CreateUser>) -> HttpResponse {
+    match check_rate_limit(&rate_limit_repo, &SIGN_UP_RATE_LIMIT, &client_ip(&req)).await {
+        Ok(None) => (),
+        Ok(Some(retry_after)) => return too_many_requests(retry_after),
+        Err(e) => return HttpResponse::InternalServerError().body(e.to_string())
+    };
+
+    let new_user: CreateUser = match validate_request_body(body).await {
+        Ok(new_user) => new_user,
+        Err(bad_request) => return bad_request
+    };
+    let password_hash = hash_password(&new_user.password).unwrap();
+
+    // the response is the same whether the address is taken or not
+    let user_details = match db.create_user(new_user.email.clone(), password_hash, new_user.time_zone).await {
+        Ok(result) => result,
+        Err(err) if is_duplicate_key(&err) => return HttpResponse::Created().finish(),
+        Err(err) => return HttpResponse::InternalServerError().body(err.to_string()),
+    };
+
+    let user_id = user_details.inserted_id.as_object_id().unwrap();
+    match start_email_verification(&outbox, &token_repo, &user_id, new_user.email).await {
         Ok(_) => HttpResponse::Created().finish(),
         Err(err) => HttpResponse::InternalServerError().body(err.to_string()),
     }
 }
 
+#[get("/auth/verify")]
+pub async fn verify_email(user_repo: Data<UserRepository>, token_repo: Data<OneTimeTokenRepository>,
+                          query: Query<VerifyEmail>) -> HttpResponse {
+    let token = match token_repo.consume(&hash_token(&query.token), &TokenPurpose::EmailVerification).await {
+        Ok(Some(token)) => token,
+        Ok(None) => return HttpResponse::BadRequest().json("Invalid or expired token"),
+        Err(e) => return HttpResponse::InternalServerError().body(e.to_string())
+    };
+
+    let user = match user_repo.find_by_id(&token.user_id).await {
+        Ok(Some(user)) => user,
+        Ok(None) => return HttpResponse::BadRequest().json("Invalid or expired token"),
+        Err(e) => return HttpResponse::InternalServerError().body(e.to_string())
+    };
+
+    // the address must still be the current or the pending one of the account
+    let email = match token.email {
+        Some(email) if email == user.email || Some(&email) == user.pending_email.as_ref() => email,
+        _ => return HttpResponse::BadRequest().json("Invalid or expired token")
+    };
+
+    match user_repo.verify_email(&token.user_id, &email).await {
+        Ok(true) => HttpResponse::Ok().json("Email address confirmed"),
+        Ok(false) => HttpResponse::Conflict().json("Email address is already in use"),
+        Err(e) => HttpResponse::InternalServerError().body(e.to_string())
+    }
+}
+
+#[post("/auth/verify/resend")]
+pub async fn resend_verification(user_repo: Data<UserRepository>, token_repo: Data<OneTimeTokenRepository>,
+                                 outbox: Data<Outbox>, body: Json<ResendVerification>) -> HttpResponse {
+    let request = match validate_request_body(body).await {
+        Ok(request) => request,
+        Err(bad_request) => return bad_request
+    };
+
+    // the response is the same whether the account exists or not
+    let user = match user_repo.find_by_email(&request.email).await {
+        Ok(Some(user)) if !user.verified => user,
+        Ok(_) => return HttpResponse::Accepted().finish(),
+        Err(e) => return HttpResponse::InternalServerError().body(e.to_string())
+    };
+
+    match start_email_verification(&outbox, &token_repo, &user.id.unwrap(), user.email).await {
+        Ok(_) => HttpResponse::Accepted().finish(),
+        Err(e) => HttpResponse::InternalServerError().body(e.to_string())
+    }
+}
+
 #[post("/auth/sign-in")]
-pub async fn sign_in(db: Data<UserRepository>, credentials: BasicAuth) -> HttpResponse {
+pub async fn sign_in(req: HttpRequest, db: Data<UserRepository>, refresh_repo: Data<RefreshTokenRepository>,
+                     login_attempt_repo: Data<LoginAttemptRepository>, rate_limit_repo: Data<RateLimitRepository>,
+                     signing_keys: Data<SigningKeys>, credentials: BasicAuth) -> HttpResponse {
+    let client_ip = client_ip(&req);
+    match check_rate_limit(&rate_limit_repo, &SIGN_IN_RATE_LIMIT, &client_ip).await {
+        Ok(None) => (),
+        Ok(Some(retry_after)) => return too_many_requests(retry_after),
+        Err(e) => return HttpResponse::InternalServerError().body(e.to_string())
+    };
+
     let email = credentials.user_id();
     let request_password = match credentials.password() {
         Some(pwd) => pwd,
         None => return HttpResponse::Unauthorized().finish()
     };
 
+    // locked keys are rejected before the password is even looked at
+    let keys = [account_key(email), ip_key(&client_ip)];
+    match remaining_lockout(&login_attempt_repo, &keys).await {
+        Ok(None) => (),
+        Ok(Some(retry_after)) => return too_many_requests(retry_after),
+        Err(e) => return HttpResponse::InternalServerError().body(e.to_string())
+    };
+
+    // unknown accounts and wrong passwords get the same answer
     let user = match db.find_by_email(&String::from(email)).await {
-        Ok(user_option) => {
-            match user_option {
-                Some(user) => user,
-                None => return HttpResponse::Unauthorized().json("User not found")
+        Ok(user_option) => user_option,
+        Err(e) => return HttpResponse::Unauthorized().json(format!("Something went wrong while signing in: {}", e))
+    };
+    let password_valid = match &user {
+        Some(user) => match verify_password(&user.password, request_password) {
+            Ok(valid) => valid,
+            Err(e) => return HttpResponse::InternalServerError().body(e.to_string())
+        },
+        None => {
+            verify_dummy_password(request_password);
+            false
+        }
+    };
+
+    match user {
+        Some(user) if password_valid => {
+            if let Err(e) = clear_failed_sign_ins(&login_attempt_repo, email).await {
+                return HttpResponse::InternalServerError().body(e.to_string());
+            }
+
+            if user.disabled {
+                return HttpResponse::Forbidden().json("Account is disabled");
+            }
+            if user.password_reset_required {
+                return HttpResponse::Forbidden().json("Password reset required");
+            }
+
+            // the second factor is checked in a separate request
+            if user.mfa.enabled {
+                let (mfa_token, expires_in) = sign_mfa_token(&signing_keys, &user);
+                return HttpResponse::Ok().json(MfaChallenge { mfa_required: true, mfa_token, expires_in });
+            }
+
+            match issue_tokens(&refresh_repo, &signing_keys, &user, None).await {
+                Ok(tokens) => HttpResponse::Ok().json(tokens),
+                Err(e) => HttpResponse::InternalServerError().body(e.to_string())
             }
         }
-        Err(e) => return HttpResponse::Unauthorized().json(format!("Something went wrong while signing in: {}", e))
+        _ => {
+            if let Err(e) = register_failed_sign_in(&login_attempt_repo, email, &client_ip).await {
+                return HttpResponse::InternalServerError().body(e.to_string());
+            }
+            HttpResponse::Unauthorized().json("Incorrect username or password")
+        }
+    }
+}
+
+#[post("/auth/sign-in/mfa")]
+pub async fn sign_in_mfa(req: HttpRequest, user_repo: Data<UserRepository>, refresh_repo: Data<RefreshTokenRepository>,
+                         login_attempt_repo: Data<LoginAttemptRepository>, signing_keys: Data<SigningKeys>,
+                         body: Json<MfaSignIn>) -> HttpResponse {
+    let request = match validate_request_body(body).await {
+        Ok(request) => request,
+        Err(bad_request) => return bad_request
     };
 
-    let hash_secret = std::env::var("HASH_SECRET").expect("HASH_SECRET not provided");
-    let mut verifier = Verifier::default();
+    let claims = match verify_mfa_token(&signing_keys, &request.mfa_token) {
+        Some(claims) => claims,
+        None => return HttpResponse::Unauthorized().json("Invalid or expired sign-in")
+    };
 
-    let password_valid = verifier
-        .with_hash(user.password)
-        .with_password(request_password)
-        .with_secret_key(hash_secret)
-        .verify()
-        .unwrap();
+    let user = match ObjectId::parse_str(&claims.sub) {
+        Ok(user_id) => match user_repo.find_by_id(&user_id).await {
+            Ok(Some(user)) if !user.disabled => user,
+            Ok(_) => return HttpResponse::Unauthorized().json("Invalid or expired sign-in"),
+            Err(e) => return HttpResponse::InternalServerError().body(e.to_string())
+        },
+        Err(_) => return HttpResponse::Unauthorized().json("Invalid or expired sign-in")
+    };
 
-    if password_valid {
-        let jwt_secret = std::env::var("JWT_SECRET").expect("JWT_SECRET not provided!");
-        let key: Hmac<Sha256> = Hmac::new_from_slice(jwt_secret.as_bytes()).unwrap();
+    // codes are short, so failures count towards the same lockout as passwords
+    let client_ip = client_ip(&req);
+    let keys = [account_key(&user.email), ip_key(&client_ip)];
+    match remaining_lockout(&login_attempt_repo, &keys).await {
+        Ok(None) => (),
+        Ok(Some(retry_after)) => return too_many_requests(retry_after),
+        Err(e) => return HttpResponse::InternalServerError().body(e.to_string())
+    };
 
-        let claims = TokenClaims { email: user.email };
-        let token_str = claims.sign_with_key(&key).unwrap();
+    match verify_second_factor(&user_repo, &user, &request.code).await {
+        Ok(true) => (),
+        Ok(false) => {
+            if let Err(e) = register_failed_sign_in(&login_attempt_repo, &user.email, &client_ip).await {
+                return HttpResponse::InternalServerError().body(e.to_string());
+            }
+            return HttpResponse::Unauthorized().json("Invalid code");
+        }
+        Err(e) => return HttpResponse::InternalServerError().body(e.to_string())
+    };
 
-        HttpResponse::Ok().json(token_str)
-    } else {
-        HttpResponse::Unauthorized().json("Incorrect username or password")
+    if let Err(e) = clear_failed_sign_ins(&login_attempt_repo, &user.email).await {
+        return HttpResponse::InternalServerError().body(e.to_string());
     }
-}
\ No newline at end of file
+
+    match issue_tokens(&refresh_repo, &signing_keys, &user, None).await {
+        Ok(tokens) => HttpResponse::Ok().json(tokens),
+        Err(e) => HttpResponse::InternalServerError().body(e.to_string())
+    }
+}
+
+#[post("/auth/refresh")]
+pub async fn refresh(user_repo: Data<UserRepository>, refresh_repo: Data<RefreshTokenRepository>,
+                     signing_keys: Data<SigningKeys>, body: Json<RefreshTokenRequest>) -> HttpResponse {
+    let token_hash = hash_token(&body.refresh_token);
+    let stored_token = match refresh_repo.find_by_hash(&token_hash).await {
+        Ok(Some(token)) => token,
+        Ok(None) => return HttpResponse::Unauthorized().json("Invalid refresh token"),
+        Err(e) => return HttpResponse::InternalServerError().body(e.to_string())
+    };
+
+    // a rotated token being presented again means it leaked, so the whole family goes
+    if stored_token.used || stored_token.revoked {
+        warn!("Refresh token reuse detected for family {}", stored_token.family_id);
+        if let Err(e) = refresh_repo.revoke_family(&stored_token.family_id).await {
+            return HttpResponse::InternalServerError().body(e.to_string());
+        }
+        return HttpResponse::Unauthorized().json("Invalid refresh token");
+    }
+
+    if stored_token.expires_at.to_chrono() <= Utc::now() {
+        return HttpResponse::Unauthorized().json("Refresh token expired");
+    }
+
+    match refresh_repo.mark_used(&stored_token.id.unwrap()).await {
+        Ok(Some(_)) => (),
+        Ok(None) => {
+            warn!("Refresh token reuse detected for family {}", stored_token.family_id);
+            if let Err(e) = refresh_repo.revoke_family(&stored_token.family_id).await {
+                return HttpResponse::InternalServerError().body(e.to_string());
+            }
+            return HttpResponse::Unauthorized().json("Invalid refresh token");
+        }
+        Err(e) => return HttpResponse::InternalServerError().body(e.to_string())
+    };
+
+    let user = match user_repo.find_by_id(&stored_token.user_id).await {
+        Ok(Some(user)) if !user.disabled => user,
+        Ok(Some(_)) => return HttpResponse::Unauthorized().json("Invalid refresh token"),
+        Ok(None) => return HttpResponse::Unauthorized().json("Invalid refresh token"),
+        Err(e) => return HttpResponse::InternalServerError().body(e.to_string())
+    };
+
+    match issue_tokens(&refresh_repo, &signing_keys, &user, Some(stored_token.family_id)).await {
+        Ok(tokens) => HttpResponse::Ok().json(tokens),
+        Err(e) => HttpResponse::InternalServerError().body(e.to_string())
+    }
+}
+
+#[post("/auth/logout")]
+pub async fn logout(refresh_repo: Data<RefreshTokenRepository>, revoked_repo: Data<RevokedTokenRepository>,
+                    logged_user_data: Option<ReqData<User>>,
+                    token_claims_data: Option<ReqData<TokenClaims>>) -> HttpResponse {
+    let (logged_user, token_claims) = match (logged_user_data, token_claims_data) {
+        (Some(user), Some(claims)) => (user, claims),
+        _ => return HttpResponse::Unauthorized().finish()
+    };
+
+    if let Err(e) = revoke_access_token(&revoked_repo, &logged_user, &token_claims).await {
+        return HttpResponse::InternalServerError().body(e.to_string());
+    }
+
+    match refresh_repo.revoke_family(&token_claims.sid).await {
+        Ok(_) => HttpResponse::NoContent().finish(),
+        Err(e) => HttpResponse::InternalServerError().body(e.to_string())
+    }
+}
+
+#[post("/auth/logout-all")]
+pub async fn logout_all(refresh_repo: Data<RefreshTokenRepository>, revoked_repo: Data<RevokedTokenRepository>,
+                        logged_user_data: Option<ReqData<User>>,
+                        token_claims_data: Option<ReqData<TokenClaims>>) -> HttpResponse {
+    let (logged_user, token_claims) = match (logged_user_data, token_claims_data) {
+        (Some(user), Some(claims)) => (user, claims),
+        _ => return HttpResponse::Unauthorized().finish()
+    };
+
+    if let Err(e) = revoke_access_token(&revoked_repo, &logged_user, &token_claims).await {
+        return HttpResponse::InternalServerError().body(e.to_string());
+    }
+
+    // access tokens of the other sessions die together with their refresh token family
+    match refresh_repo.revoke_all_for_user(&logged_user.id.unwrap()).await {
+        Ok(_) => HttpResponse::NoContent().finish(),
+        Err(e) => HttpResponse::InternalServerError().body(e.to_string())
+    }
+}
+
+async fn revoke_access_token(revoked_repo: &RevokedTokenRepository, user: &User,
+                             claims: &TokenClaims) -> Result<(), mongodb::error::Error> {
+    let revoked_token = RevokedToken {
+        jti: claims.jti.clone(),
+        user_id: user.id.unwrap(),
+        expires_at: DateTime::from_millis(claims.exp * 1000),
+    };
+
+    revoked_repo.revoke(revoked_token).await.map(|_| ())
+}
+
+#[post("/auth/forgot-password")]
+pub async fn forgot_password(user_repo: Data<UserRepository>, token_repo: Data<OneTimeTokenRepository>,
+                             outbox: Data<Outbox>, body: Json<ForgotPassword>) -> HttpResponse {
+    let request = match validate_request_body(body).await {
+        Ok(request) => request,
+        Err(bad_request) => return bad_request
+    };
+
+    // the response is the same whether the account exists or not
+    let user = match user_repo.find_by_email(&request.email).await {
+        Ok(Some(user)) => user,
+        Ok(None) => return HttpResponse::Accepted().finish(),
+        Err(e) => return HttpResponse::InternalServerError().body(e.to_string())
+    };
+
+    match start_password_reset(&outbox, &token_repo, &user.id.unwrap(), user.email).await {
+        Ok(_) => HttpResponse::Accepted().finish(),
+        Err(e) => HttpResponse::InternalServerError().body(e.to_string())
+    }
+}
+
+#[post("/auth/reset-password")]
+pub async fn reset_password(user_repo: Data<UserRepository>, token_repo: Data<OneTimeTokenRepository>,
+                            refresh_repo: Data<RefreshTokenRepository>, body: Json<ResetPassword>) -> HttpResponse {
+    let request = match validate_request_body(body).await {
+        Ok(request) => request,
+        Err(bad_request) => return bad_request
+    };
+
+    // hashed first, so the link still works if hashing fails
+    let password_hash = match hash_password(&request.new_password) {
+        Ok(password_hash) => password_hash,
+        Err(e) => return HttpResponse::InternalServerError().body(e.to_string())
+    };
+
+    let token = match token_repo.consume(&hash_token(&request.token), &TokenPurpose::PasswordReset).await {
+        Ok(Some(token)) => token,
+        Ok(None) => return HttpResponse::BadRequest().json("Invalid or expired token"),
+        Err(e) => return HttpResponse::InternalServerError().body(e.to_string())
+    };
+
+    if let Err(e) = user_repo.update_password(&token.user_id, password_hash).await {
+        return HttpResponse::InternalServerError().body(e.to_string());
+    }
+
+    match refresh_repo.revoke_all_for_user(&token.user_id).await {
+        Ok(_) => HttpResponse::NoContent().finish(),
+        Err(e) => HttpResponse::InternalServerError().body(e.to_string())
+    }
+}
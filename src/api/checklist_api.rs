@@ -0,0 +1,147 @@
+use std::str::FromStr;
+
+use actix_web::{delete, HttpResponse, patch, post, put};
+use actix_web::web::{Data, Json, Path, ReqData};
+use mongodb::bson::oid::ObjectId;
+
+use crate::dto::create_checklist_item::{CreateChecklistItem, MAX_CHECKLIST_ITEMS};
+use crate::dto::reorder_checklist::ReorderChecklist;
+use crate::dto::task_preview::TaskPreview;
+use crate::dto::update_checklist_item::UpdateChecklistItem;
+use crate::model::task_model::ChecklistItem;
+use crate::model::user_model::User;
+use crate::repository::task_repository::TaskRepository;
+use crate::service::scheduler_service::Scheduler;
+use crate::service::task_service::complete_if_checked;
+use crate::service::time_zone_service::user_time_zone;
+use crate::validator::request_validators::validate_request_body;
+
+#[post("/task/{task_id}/checklist")]
+pub async fn add_checklist_item(task_repo: Data<TaskRepository>, logged_user_data: Option<ReqData<User>>,
+                                task_id: Path<String>, body: Json<CreateChecklistItem>) -> HttpResponse {
+    let logged_user = match logged_user_data {
+        Some(claims) => claims,
+        _ => return HttpResponse::Unauthorized().finish()
+    };
+
+    let request = match validate_request_body(body).await {
+        Ok(request) => request,
+        Err(bad_request) => return bad_request
+    };
+
+    let user_id = logged_user.id.unwrap();
+    let item = ChecklistItem { id: ObjectId::new(), text: request.text, done: false };
+    match task_repo.add_checklist_item(&task_id, &user_id, &item).await {
+        Ok(Some(task)) => return HttpResponse::Created().json(TaskPreview::from(task)),
+        Ok(None) => (),
+        Err(e) => return HttpResponse::InternalServerError().body(e.to_string())
+    };
+
+    // nothing was added, either because the task doesn't exist or its checklist is full
+    match task_repo.find_by_id(&task_id, &user_id).await {
+        Ok(Some(_)) => HttpResponse::BadRequest()
+            .json(format!("A checklist can have at most {} items", MAX_CHECKLIST_ITEMS)),
+        Ok(None) => HttpResponse::NotFound().json("Task not found"),
+        Err(e) => HttpResponse::InternalServerError().body(e.to_string())
+    }
+}
+
+#[patch("/task/{task_id}/checklist/{item_id}")]
+pub async fn update_checklist_item(task_repo: Data<TaskRepository>, scheduler: Data<Scheduler>,
+                                   logged_user_data: Option<ReqData<User>>, path: Path<(String, String)>,
+                                   body: Json<UpdateChecklistItem>) -> HttpResponse {
+    let logged_user = match logged_user_data {
+        Some(claims) => claims,
+        _ => return HttpResponse::Unauthorized().finish()
+    };
+
+    let request = match validate_request_body(body).await {
+        Ok(request) => request,
+        Err(bad_request) => return bad_request
+    };
+
+    let (task_id, item_id) = path.into_inner();
+    let item_id = match ObjectId::from_str(&item_id) {
+        Ok(item_id) => item_id,
+        Err(_) => return HttpResponse::NotFound().json("Checklist item not found")
+    };
+
+    let task = match task_repo.update_checklist_item(&task_id, &logged_user.id.unwrap(), &item_id,
+                                                     request.text.as_deref(), request.done).await {
+        Ok(Some(task)) => task,
+        Ok(None) => return HttpResponse::NotFound().json("Checklist item not found"),
+        Err(e) => return HttpResponse::InternalServerError().body(e.to_string())
+    };
+
+    match complete_if_checked(&task_repo, task, user_time_zone(&logged_user)).await {
+        Ok(task) => {
+            // completing a recurring task creates its next occurrence
+            scheduler.reschedule();
+            HttpResponse::Ok().json(TaskPreview::from(task))
+        }
+        Err(e) => HttpResponse::InternalServerError().body(e.to_string())
+    }
+}
+
+#[put("/task/{task_id}/checklist/order")]
+pub async fn reorder_checklist(task_repo: Data<TaskRepository>, logged_user_data: Option<ReqData<User>>,
+                               task_id: Path<String>, body: Json<ReorderChecklist>) -> HttpResponse {
+    let logged_user = match logged_user_data {
+        Some(claims) => claims,
+        _ => return HttpResponse::Unauthorized().finish()
+    };
+
+    // the new order has to name every item exactly once; the update checks that the ids
+    // are those of the checklist
+    let mut item_ids: Vec<ObjectId> = Vec::with_capacity(body.item_ids.len());
+    for item_id in &body.item_ids {
+        match ObjectId::from_str(item_id) {
+            Ok(item_id) if !item_ids.contains(&item_id) => item_ids.push(item_id),
+            _ => return HttpResponse::BadRequest().json("Unknown or repeated checklist item id")
+        }
+    }
+
+    let user_id = logged_user.id.unwrap();
+    match task_repo.reorder_checklist(&task_id, &user_id, &item_ids).await {
+        Ok(Some(task)) => return HttpResponse::Ok().json(TaskPreview::from(task)),
+        Ok(None) => (),
+        Err(e) => return HttpResponse::InternalServerError().body(e.to_string())
+    };
+
+    match task_repo.find_by_id(&task_id, &user_id).await {
+        Ok(Some(_)) => HttpResponse::BadRequest().json("The order has to name every checklist item exactly once"),
+        Ok(None) => HttpResponse::NotFound().json("Task not found"),
+        Err(e) => HttpResponse::InternalServerError().body(e.to_string())
+    }
+}
+
+#[delete("/task/{task_id}/checklist/{item_id}")]
+pub async fn delete_checklist_item(task_repo: Data<TaskRepository>, scheduler: Data<Scheduler>,
+                                   logged_user_data: Option<ReqData<User>>, path: Path<(String, String)>) -> HttpResponse {
+    let logged_user = match logged_user_data {
+        Some(claims) => claims,
+        _ => return HttpResponse::Unauthorized().finish()
+    };
+
+    let (task_id, item_id) = path.into_inner();
+    let item_id = match ObjectId::from_str(&item_id) {
+        Ok(item_id) => item_id,
+        Err(_) => return HttpResponse::NotFound().json("Checklist item not found")
+    };
+
+    let task = match task_repo.remove_checklist_item(&task_id, &logged_user.id.unwrap(), &item_id).await {
+        Ok(Some(task)) => task,
+        Ok(None) => return HttpResponse::NotFound().json("Checklist item not found"),
+        Err(e) => return HttpResponse::InternalServerError().body(e.to_string())
+    };
+
+    // removing the last open item completes the checklist as well
+    match complete_if_checked(&task_repo, task, user_time_zone(&logged_user)).await {
+        Ok(task) => {
+            // completing a recurring task creates its next occurrence
+            scheduler.reschedule();
+            HttpResponse::Ok().json(TaskPreview::from(task))
+        }
+        Err(e) => HttpResponse::InternalServerError().body(e.to_string())
+    }
+}
@@ -0,0 +1,123 @@
+use std::str::FromStr;
+
+use actix_web::{delete, get, HttpResponse, post};
+use actix_web::web::{Data, Json, Path, ReqData};
+use mongodb::bson::oid::ObjectId;
+
+use crate::dto::add_blocker::AddBlocker;
+use crate::dto::dependency_graph::{DependencyGraph, DependencyNode};
+use crate::dto::task_preview::TaskPreview;
+use crate::model::user_model::User;
+use crate::repository::task_repository::TaskRepository;
+use crate::service::task_service::{creates_cycle, topological_order};
+use crate::validator::request_validators::validate_request_body;
+
+#[post("/task/{task_id}/blockers")]
+pub async fn add_blocker(task_repo: Data<TaskRepository>, logged_user_data: Option<ReqData<User>>,
+                         task_id: Path<String>, body: Json<AddBlocker>) -> HttpResponse {
+    let logged_user = match logged_user_data {
+        Some(claims) => claims,
+        _ => return HttpResponse::Unauthorized().finish()
+    };
+
+    let request = match validate_request_body(body).await {
+        Ok(request) => request,
+        Err(bad_request) => return bad_request
+    };
+
+    let user_id = logged_user.id.unwrap();
+    let task = match task_repo.find_by_id(&task_id, &user_id).await {
+        Ok(Some(task)) => task,
+        Ok(None) => return HttpResponse::NotFound().json("Task not found"),
+        Err(e) => return HttpResponse::InternalServerError().body(e.to_string())
+    };
+
+    let blocker_id = match ObjectId::from_str(&request.blocker_id) {
+        Ok(blocker_id) => blocker_id,
+        Err(_) => return HttpResponse::NotFound().json("Blocking task not found")
+    };
+    // only tasks of the same user can block each other
+    match task_repo.find_by_id(&request.blocker_id, &user_id).await {
+        Ok(Some(_)) => (),
+        Ok(None) => return HttpResponse::NotFound().json("Blocking task not found"),
+        Err(e) => return HttpResponse::InternalServerError().body(e.to_string())
+    };
+
+    let nodes = match task_repo.find_nodes(&user_id).await {
+        Ok(nodes) => nodes,
+        Err(e) => return HttpResponse::InternalServerError().body(e.to_string())
+    };
+    if task.id == Some(blocker_id) || creates_cycle(&nodes, &task.id.unwrap(), &blocker_id) {
+        return HttpResponse::Conflict().json("The blocking task already depends on this task");
+    }
+    if task.blocked_by.contains(&blocker_id) {
+        return HttpResponse::Ok().json(TaskPreview::from(task));
+    }
+
+    let task = match task_repo.add_blocker(&task_id, &user_id, &blocker_id).await {
+        Ok(Some(task)) => task,
+        Ok(None) => return HttpResponse::NotFound().json("Task not found"),
+        Err(e) => return HttpResponse::InternalServerError().body(e.to_string())
+    };
+
+    // a concurrent request linking the tasks the other way round can pass the check above as
+    // well, so it is repeated with the link in place and the link taken back if needed
+    let nodes = match task_repo.find_nodes(&user_id).await {
+        Ok(nodes) => nodes,
+        Err(e) => return HttpResponse::InternalServerError().body(e.to_string())
+    };
+    if creates_cycle(&nodes, &task.id.unwrap(), &blocker_id) {
+        return match task_repo.remove_blocker(&task_id, &user_id, &blocker_id).await {
+            Ok(_) => HttpResponse::Conflict().json("The blocking task already depends on this task"),
+            Err(e) => HttpResponse::InternalServerError().body(e.to_string())
+        };
+    }
+
+    HttpResponse::Ok().json(TaskPreview::from(task))
+}
+
+#[delete("/task/{task_id}/blockers/{blocker_id}")]
+pub async fn remove_blocker(task_repo: Data<TaskRepository>, logged_user_data: Option<ReqData<User>>,
+                            path: Path<(String, String)>) -> HttpResponse {
+    let logged_user = match logged_user_data {
+        Some(claims) => claims,
+        _ => return HttpResponse::Unauthorized().finish()
+    };
+
+    let (task_id, blocker_id) = path.into_inner();
+    let blocker_id = match ObjectId::from_str(&blocker_id) {
+        Ok(blocker_id) => blocker_id,
+        Err(_) => return HttpResponse::NotFound().json("Blocking task not found")
+    };
+
+    match task_repo.remove_blocker(&task_id, &logged_user.id.unwrap(), &blocker_id).await {
+        Ok(Some(task)) => HttpResponse::Ok().json(TaskPreview::from(task)),
+        Ok(None) => HttpResponse::NotFound().json("Task not found"),
+        Err(e) => HttpResponse::InternalServerError().body(e.to_string())
+    }
+}
+
+#[get("/task/dependencies")]
+pub async fn get_dependencies(task_repo: Data<TaskRepository>, logged_user_data: Option<ReqData<User>>) -> HttpResponse {
+    let logged_user = match logged_user_data {
+        Some(claims) => claims,
+        _ => return HttpResponse::Unauthorized().finish()
+    };
+
+    let nodes = match task_repo.find_nodes(&logged_user.id.unwrap()).await {
+        Ok(nodes) => nodes,
+        Err(e) => return HttpResponse::InternalServerError().body(e.to_string())
+    };
+
+    let order = topological_order(&nodes).iter().map(|id| id.to_hex()).collect();
+    let tasks = nodes.into_iter()
+        .map(|node| DependencyNode {
+            id: node.id.to_hex(),
+            title: node.title,
+            status: node.status,
+            blocked_by: node.blocked_by.iter().map(|id| id.to_hex()).collect(),
+        })
+        .collect();
+
+    HttpResponse::Ok().json(DependencyGraph { tasks, order })
+}
@@ -0,0 +1,13 @@
+use actix_web::{get, HttpResponse};
+use actix_web::http::header::CACHE_CONTROL;
+use actix_web::web::Data;
+
+use crate::service::signing_key_service::SigningKeys;
+
+/// Public keys other services can verify our access tokens with.
+#[get("/.well-known/jwks.json")]
+pub async fn jwks(signing_keys: Data<SigningKeys>) -> HttpResponse {
+    HttpResponse::Ok()
+        .insert_header((CACHE_CONTROL, "public, max-age=300"))
+        .json(signing_keys.jwks())
+}
@@ -0,0 +1,121 @@
+use actix_web::{delete, HttpResponse, post};
+use actix_web::web::{Data, Json, ReqData};
+
+use crate::dto::mfa_code::MfaCode;
+use crate::dto::mfa_enrollment::MfaEnrollment;
+use crate::dto::recovery_codes::RecoveryCodes;
+use crate::model::user_model::User;
+use crate::repository::user_repository::UserRepository;
+use crate::service::mfa_service::{generate_recovery_codes, generate_secret, otpauth_uri, verify_second_factor,
+                                  verify_totp};
+use crate::validator::request_validators::validate_request_body;
+
+#[post("/user/mfa/enroll")]
+pub async fn enroll_mfa(user_repo: Data<UserRepository>, logged_user_data: Option<ReqData<User>>) -> HttpResponse {
+    let logged_user = match logged_user_data {
+        Some(user) => user,
+        _ => return HttpResponse::Unauthorized().finish()
+    };
+
+    if logged_user.mfa.enabled {
+        return HttpResponse::Conflict().json("Two-factor authentication is already enabled");
+    }
+
+    let secret = generate_secret();
+    match user_repo.start_mfa_enrollment(&logged_user.id.unwrap(), &secret).await {
+        Ok(_) => HttpResponse::Ok().json(MfaEnrollment {
+            otpauth_uri: otpauth_uri(&secret, &logged_user.email),
+            secret,
+        }),
+        Err(e) => HttpResponse::InternalServerError().body(e.to_string())
+    }
+}
+
+#[post("/user/mfa/confirm")]
+pub async fn confirm_mfa(user_repo: Data<UserRepository>, logged_user_data: Option<ReqData<User>>,
+                         body: Json<MfaCode>) -> HttpResponse {
+    let logged_user = match logged_user_data {
+        Some(user) => user,
+        _ => return HttpResponse::Unauthorized().finish()
+    };
+
+    let request = match validate_request_body(body).await {
+        Ok(request) => request,
+        Err(bad_request) => return bad_request
+    };
+
+    let secret = match &logged_user.mfa.pending_secret {
+        Some(secret) if !logged_user.mfa.enabled => secret,
+        _ => return HttpResponse::Conflict().json("No two-factor enrollment in progress")
+    };
+
+    let step = match verify_totp(secret, &request.code) {
+        Some(step) => step,
+        None => return HttpResponse::BadRequest().json("Invalid code")
+    };
+
+    let (codes, hashes) = generate_recovery_codes();
+    match user_repo.enable_mfa(&logged_user.id.unwrap(), secret, hashes, step).await {
+        Ok(_) => HttpResponse::Ok().json(RecoveryCodes { recovery_codes: codes }),
+        Err(e) => HttpResponse::InternalServerError().body(e.to_string())
+    }
+}
+
+#[post("/user/mfa/recovery-codes")]
+pub async fn regenerate_recovery_codes(user_repo: Data<UserRepository>, logged_user_data: Option<ReqData<User>>,
+                                       body: Json<MfaCode>) -> HttpResponse {
+    let logged_user = match logged_user_data {
+        Some(user) => user,
+        _ => return HttpResponse::Unauthorized().finish()
+    };
+
+    let request = match validate_request_body(body).await {
+        Ok(request) => request,
+        Err(bad_request) => return bad_request
+    };
+
+    if !logged_user.mfa.enabled {
+        return HttpResponse::Conflict().json("Two-factor authentication is not enabled");
+    }
+
+    match verify_second_factor(&user_repo, &logged_user, &request.code).await {
+        Ok(true) => (),
+        Ok(false) => return HttpResponse::BadRequest().json("Invalid code"),
+        Err(e) => return HttpResponse::InternalServerError().body(e.to_string())
+    };
+
+    let (codes, hashes) = generate_recovery_codes();
+    match user_repo.set_recovery_codes(&logged_user.id.unwrap(), hashes).await {
+        Ok(_) => HttpResponse::Ok().json(RecoveryCodes { recovery_codes: codes }),
+        Err(e) => HttpResponse::InternalServerError().body(e.to_string())
+    }
+}
+
+#[delete("/user/mfa")]
+pub async fn disable_mfa(user_repo: Data<UserRepository>, logged_user_data: Option<ReqData<User>>,
+                         body: Json<MfaCode>) -> HttpResponse {
+    let logged_user = match logged_user_data {
+        Some(user) => user,
+        _ => return HttpResponse::Unauthorized().finish()
+    };
+
+    let request = match validate_request_body(body).await {
+        Ok(request) => request,
+        Err(bad_request) => return bad_request
+    };
+
+    if !logged_user.mfa.enabled {
+        return HttpResponse::Conflict().json("Two-factor authentication is not enabled");
+    }
+
+    match verify_second_factor(&user_repo, &logged_user, &request.code).await {
+        Ok(true) => (),
+        Ok(false) => return HttpResponse::BadRequest().json("Invalid code"),
+        Err(e) => return HttpResponse::InternalServerError().body(e.to_string())
+    };
+
+    match user_repo.disable_mfa(&logged_user.id.unwrap()).await {
+        Ok(_) => HttpResponse::NoContent().finish(),
+        Err(e) => HttpResponse::InternalServerError().body(e.to_string())
+    }
+}
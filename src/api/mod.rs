@@ -1,3 +1,12 @@
 pub mod user_api;
 pub mod auth_api;
 pub mod task_api;
+pub mod mfa_api;
+pub mod access_token_api;
+pub mod admin_api;
+pub mod oidc_api;
+pub mod jwks_api;
+pub mod tag_api;
+pub mod checklist_api;
+pub mod dependency_api;
+pub mod notification_api;
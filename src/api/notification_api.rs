@@ -0,0 +1,91 @@
+use actix_web::{get, HttpResponse, post, put};
+use actix_web::http::header::ContentType;
+use actix_web::web::{Data, Form, Json, Query, ReqData};
+use chrono::Utc;
+use mongodb::bson::oid::ObjectId;
+
+use crate::dto::unsubscribe::{OneClickUnsubscribe, Unsubscribe};
+use crate::dto::update_notification_settings::UpdateNotificationSettings;
+use crate::model::user_model::User;
+use crate::repository::user_repository::UserRepository;
+use crate::service::reminder_service::next_digest_at;
+use crate::service::scheduler_service::Scheduler;
+use crate::service::time_zone_service::user_time_zone;
+use crate::service::unsubscribe_service::UnsubscribeLinks;
+use crate::validator::request_validators::validate_request_body;
+
+#[get("/user/notifications")]
+pub async fn get_notification_settings(logged_user_data: Option<ReqData<User>>) -> HttpResponse {
+    match logged_user_data {
+        Some(logged_user) => HttpResponse::Ok().json(&logged_user.notifications),
+        None => HttpResponse::Unauthorized().finish()
+    }
+}
+
+#[put("/user/notifications")]
+pub async fn update_notification_settings(user_repo: Data<UserRepository>, scheduler: Data<Scheduler>,
+                                          logged_user_data: Option<ReqData<User>>,
+                                          body: Json<UpdateNotificationSettings>) -> HttpResponse {
+    let logged_user = match logged_user_data {
+        Some(user) => user,
+        _ => return HttpResponse::Unauthorized().finish()
+    };
+
+    let update = match validate_request_body(body).await {
+        Ok(update) => update,
+        Err(bad_request) => return bad_request
+    };
+
+    let next_digest = next_digest_at(&logged_user.reminders, user_time_zone(&logged_user), Utc::now());
+    let user = match user_repo.update_notifications(&logged_user.id.unwrap(), &update, next_digest).await {
+        Ok(Some(user)) => user,
+        Ok(None) => return HttpResponse::NotFound().json("User not found"),
+        Err(e) => return HttpResponse::InternalServerError().body(e.to_string())
+    };
+
+    scheduler.reschedule();
+    HttpResponse::Ok().json(user.notifications)
+}
+
+/// Target of the link in notification mails. Following it only shows a confirmation page,
+/// as link scanners fetch every link in a mail; the page POSTs back to the link.
+#[get("/notifications/unsubscribe")]
+pub async fn confirm_unsubscribe(unsubscribe_links: Data<UnsubscribeLinks>, query: Query<Unsubscribe>) -> HttpResponse {
+    if !unsubscribe_links.verify(&query.user, query.list, &query.sig) {
+        return HttpResponse::BadRequest().json("Invalid unsubscribe link");
+    }
+
+    // all parts of a correctly signed link are url-safe, so they need no escaping
+    let page = format!(
+        "<!DOCTYPE html>\n<html><head><meta charset=\"utf-8\"><title>Unsubscribe</title></head><body>\n\
+         <form method=\"post\" action=\"/notifications/unsubscribe?user={}&amp;list={}&amp;sig={}\">\n\
+         <input type=\"hidden\" name=\"List-Unsubscribe\" value=\"One-Click\">\n\
+         <button type=\"submit\">Stop receiving {} emails</button>\n\
+         </form>\n</body></html>\n",
+        query.user, query.list, query.sig, query.list.to_string().replace('_', " "));
+    HttpResponse::Ok().content_type(ContentType::html()).body(page)
+}
+
+/// Unsubscribes the user. Mail clients POST here when the user clicks their unsubscribe
+/// button (RFC 8058), and so does the confirmation page.
+#[post("/notifications/unsubscribe")]
+pub async fn unsubscribe(user_repo: Data<UserRepository>, unsubscribe_links: Data<UnsubscribeLinks>,
+                         query: Query<Unsubscribe>, body: Form<OneClickUnsubscribe>) -> HttpResponse {
+    if body.list_unsubscribe != "One-Click" {
+        return HttpResponse::BadRequest().json("Expected List-Unsubscribe=One-Click");
+    }
+    if !unsubscribe_links.verify(&query.user, query.list, &query.sig) {
+        return HttpResponse::BadRequest().json("Invalid unsubscribe link");
+    }
+
+    let user_id = match ObjectId::parse_str(&query.user) {
+        Ok(id) => id,
+        Err(_) => return HttpResponse::BadRequest().json("Invalid unsubscribe link")
+    };
+
+    match user_repo.unsubscribe(&user_id, query.list).await {
+        Ok(true) => HttpResponse::Ok().json("You have been unsubscribed"),
+        Ok(false) => HttpResponse::NotFound().json("User not found"),
+        Err(e) => HttpResponse::InternalServerError().body(e.to_string())
+    }
+}
@@ -0,0 +1,154 @@
+use std::error::Error;
+
+use actix_web::{get, HttpResponse};
+use actix_web::http::header::LOCATION;
+use actix_web::web::{Data, Query};
+use chrono::{Duration, Utc};
+use log::warn;
+use mongodb::bson::DateTime;
+
+use crate::dto::mfa_challenge::MfaChallenge;
+use crate::dto::oidc_callback::OidcCallback;
+use crate::model::oidc_login_model::OidcLogin;
+use crate::model::user_model::{OidcIdentity, User};
+use crate::repository::oidc_login_repository::OidcLoginRepository;
+use crate::repository::refresh_token_repository::RefreshTokenRepository;
+use crate::repository::user_repository::UserRepository;
+use crate::service::oidc_service::OidcClient;
+use crate::service::password_service::hash_password;
+use crate::service::signing_key_service::SigningKeys;
+use crate::service::token_service::{generate_token, issue_tokens, sign_mfa_token};
+
+/// Time the user has to finish signing in at the identity provider.
+const OIDC_LOGIN_TTL_MINUTES: i64 = 10;
+
+#[get("/auth/oidc/login")]
+pub async fn oidc_login(oidc_client: Data<OidcClient>, login_repo: Data<OidcLoginRepository>) -> HttpResponse {
+    let login = OidcLogin {
+        state: generate_token(),
+        nonce: generate_token(),
+        code_verifier: generate_token(),
+        expires_at: DateTime::from_chrono(Utc::now() + Duration::minutes(OIDC_LOGIN_TTL_MINUTES)),
+    };
+
+    let authorization_url = match oidc_client.authorization_url(&login.state, &login.nonce, &login.code_verifier).await {
+        Ok(url) => url,
+        Err(e) => return HttpResponse::BadGateway().body(e.to_string())
+    };
+
+    match login_repo.create(login).await {
+        Ok(_) => HttpResponse::Found().insert_header((LOCATION, authorization_url)).finish(),
+        Err(e) => HttpResponse::InternalServerError().body(e.to_string())
+    }
+}
+
+#[get("/auth/oidc/callback")]
+pub async fn oidc_callback(oidc_client: Data<OidcClient>, login_repo: Data<OidcLoginRepository>,
+                           user_repo: Data<UserRepository>, refresh_repo: Data<RefreshTokenRepository>,
+                           signing_keys: Data<SigningKeys>, query: Query<OidcCallback>) -> HttpResponse {
+    let callback = query.into_inner();
+    if let Some(error) = callback.error {
+        warn!("Identity provider rejected the sign-in: {} {}", error, callback.error_description.unwrap_or_default());
+        return HttpResponse::Unauthorized().json("Sign-in was cancelled or rejected");
+    }
+
+    let (code, state) = match (callback.code, callback.state) {
+        (Some(code), Some(state)) => (code, state),
+        _ => return HttpResponse::BadRequest().json("Missing code or state")
+    };
+
+    let login = match login_repo.consume(&state).await {
+        Ok(Some(login)) => login,
+        Ok(None) => return HttpResponse::BadRequest().json("Invalid or expired sign-in"),
+        Err(e) => return HttpResponse::InternalServerError().body(e.to_string())
+    };
+
+    let claims = match oidc_client.exchange_code(&code, &login.code_verifier, &login.nonce).await {
+        Ok(claims) => claims,
+        Err(e) => {
+            warn!("OpenID Connect code exchange failed: {}", e);
+            return HttpResponse::Unauthorized().json("Invalid or expired sign-in");
+        }
+    };
+
+    // accounts are only matched on addresses the provider vouches for
+    let email = match claims.email {
+        Some(email) if claims.email_verified => email,
+        _ => return HttpResponse::Forbidden().json("The identity provider did not return a verified email address")
+    };
+    let identity = OidcIdentity { issuer: oidc_client.config.issuer.clone(), subject: claims.sub };
+
+    let user = match find_or_provision_user(&user_repo, &oidc_client, email, identity).await {
+        Ok(SsoAccount::Found(user)) => *user,
+        Ok(SsoAccount::Unverified) => return HttpResponse::Forbidden()
+            .json("Confirm your email address before signing in with single sign-on"),
+        Ok(SsoAccount::Missing) => return HttpResponse::Forbidden().json("No account exists for this email address"),
+        Err(e) => return HttpResponse::InternalServerError().body(e.to_string())
+    };
+
+    if user.disabled {
+        return HttpResponse::Forbidden().json("Account is disabled");
+    }
+
+    if user.mfa.enabled {
+        let (mfa_token, expires_in) = sign_mfa_token(&signing_keys, &user);
+        return HttpResponse::Ok().json(MfaChallenge { mfa_required: true, mfa_token, expires_in });
+    }
+
+    match issue_tokens(&refresh_repo, &signing_keys, &user, None).await {
+        Ok(tokens) => HttpResponse::Ok().json(tokens),
+        Err(e) => HttpResponse::InternalServerError().body(e.to_string())
+    }
+}
+
+enum SsoAccount {
+    Found(Box<User>),
+    /// A local account with the address exists but was never confirmed, so whoever
+    /// registered it may not own the address.
+    Unverified,
+    Missing,
+}
+
+/// Looks the user up by the external identity first and falls back to linking the verified
+/// account with the same email. Unknown users are created if auto-provisioning is enabled.
+async fn find_or_provision_user(user_repo: &UserRepository, oidc_client: &OidcClient, email: String,
+                                identity: OidcIdentity) -> Result<SsoAccount, Box<dyn Error>> {
+    if let Some(user) = user_repo.find_by_oidc_identity(&identity).await? {
+        return Ok(SsoAccount::Found(Box::new(user)));
+    }
+
+    if let Some(user) = user_repo.find_by_email(&email).await? {
+        // an account already tied to another external identity is never taken over
+        if user.oidc.is_some() {
+            return Ok(SsoAccount::Missing);
+        }
+        if !user.verified {
+            return Ok(SsoAccount::Unverified);
+        }
+        return match user_repo.link_oidc_identity(&user.id.unwrap(), &identity).await? {
+            Some(user) => Ok(SsoAccount::Found(Box::new(user))),
+            None => Ok(SsoAccount::Unverified),
+        };
+    }
+
+    if !oidc_client.config.auto_provision {
+        return Ok(SsoAccount::Missing);
+    }
+
+    // nobody knows this password, the account can only be used through single sign-on
+    // until the user sets one with the password reset
+    let password_hash = hash_password(&generate_token()).map_err(|e| e.to_string())?;
+    let result = match user_repo.create_oidc_user(email, password_hash, identity.clone()).await? {
+        Some(result) => result,
+        // a concurrent sign-in with the same identity created the account, or somebody signed
+        // up with the address and hasn't confirmed it yet
+        None => return match user_repo.find_by_oidc_identity(&identity).await? {
+            Some(user) => Ok(SsoAccount::Found(Box::new(user))),
+            None => Ok(SsoAccount::Unverified),
+        },
+    };
+    match user_repo.find_by_id(&result.inserted_id.as_object_id().unwrap()).await? {
+        Some(user) => Ok(SsoAccount::Found(Box::new(user))),
+        None => Ok(SsoAccount::Missing),
+    }
+}
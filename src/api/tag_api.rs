@@ -0,0 +1,94 @@
+use actix_web::{delete, get, HttpResponse, post, put};
+use actix_web::web::{Data, Json, Path, ReqData};
+
+use crate::dto::create_task::{MAX_TAGS, normalize_tag};
+use crate::dto::rename_tag::RenameTag;
+use crate::dto::tag_usage::TagUsage;
+use crate::dto::task_preview::TaskPreview;
+use crate::dto::task_tags::TaskTags;
+use crate::model::user_model::User;
+use crate::repository::task_repository::TaskRepository;
+use crate::validator::request_validators::validate_request_body;
+
+#[get("/task/tags")]
+pub async fn get_tags(task_repo: Data<TaskRepository>, logged_user_data: Option<ReqData<User>>) -> HttpResponse {
+    let logged_user = match logged_user_data {
+        Some(claims) => claims,
+        _ => return HttpResponse::Unauthorized().finish()
+    };
+
+    match task_repo.tag_usage(&logged_user.id.unwrap()).await {
+        Ok(usage) => {
+            let tags: Vec<TagUsage> = usage.into_iter()
+                .map(|(tag, count)| TagUsage { tag, count })
+                .collect();
+            HttpResponse::Ok().json(tags)
+        }
+        Err(e) => HttpResponse::InternalServerError().body(e.to_string())
+    }
+}
+
+#[put("/task/tags/{tag}")]
+pub async fn rename_tag(task_repo: Data<TaskRepository>, logged_user_data: Option<ReqData<User>>,
+                        tag: Path<String>, body: Json<RenameTag>) -> HttpResponse {
+    let logged_user = match logged_user_data {
+        Some(claims) => claims,
+        _ => return HttpResponse::Unauthorized().finish()
+    };
+
+    let request = match validate_request_body(body).await {
+        Ok(request) => request,
+        Err(bad_request) => return bad_request
+    };
+
+    let tag = normalize_tag(&tag);
+    match task_repo.rename_tag(&logged_user.id.unwrap(), &tag, &normalize_tag(&request.name)).await {
+        Ok(0) => HttpResponse::NotFound().json("Tag not found"),
+        Ok(_) => HttpResponse::NoContent().finish(),
+        Err(e) => HttpResponse::InternalServerError().body(e.to_string())
+    }
+}
+
+#[post("/task/{task_id}/tags")]
+pub async fn add_task_tags(task_repo: Data<TaskRepository>, logged_user_data: Option<ReqData<User>>,
+                           task_id: Path<String>, body: Json<TaskTags>) -> HttpResponse {
+    let logged_user = match logged_user_data {
+        Some(claims) => claims,
+        _ => return HttpResponse::Unauthorized().finish()
+    };
+
+    let request = match validate_request_body(body).await {
+        Ok(request) => request,
+        Err(bad_request) => return bad_request
+    };
+
+    let user_id = logged_user.id.unwrap();
+    match task_repo.add_tags(&task_id, &user_id, &request.tags).await {
+        Ok(Some(task)) => return HttpResponse::Ok().json(TaskPreview::from(task)),
+        Ok(None) => (),
+        Err(e) => return HttpResponse::InternalServerError().body(e.to_string())
+    };
+
+    // nothing was added, either because the task doesn't exist or it has too many tags
+    match task_repo.find_by_id(&task_id, &user_id).await {
+        Ok(Some(_)) => HttpResponse::BadRequest().json(format!("A task can have at most {} tags", MAX_TAGS)),
+        Ok(None) => HttpResponse::NotFound().json("Task not found"),
+        Err(e) => HttpResponse::InternalServerError().body(e.to_string())
+    }
+}
+
+#[delete("/task/{task_id}/tags/{tag}")]
+pub async fn remove_task_tag(task_repo: Data<TaskRepository>, logged_user_data: Option<ReqData<User>>,
+                             path: Path<(String, String)>) -> HttpResponse {
+    let logged_user = match logged_user_data {
+        Some(claims) => claims,
+        _ => return HttpResponse::Unauthorized().finish()
+    };
+
+    let (task_id, tag) = path.into_inner();
+    match task_repo.remove_tag(&task_id, &logged_user.id.unwrap(), &normalize_tag(&tag)).await {
+        Ok(Some(task)) => HttpResponse::Ok().json(TaskPreview::from(task)),
+        Ok(None) => HttpResponse::NotFound().json("Task not found"),
+        Err(e) => HttpResponse::InternalServerError().body(e.to_string())
+    }
+}
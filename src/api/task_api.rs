@@ -1,15 +1,29 @@
 use std::error::Error;
 
-use actix_web::{delete, get, HttpResponse, post, put};
-use actix_web::web::{Data, Json, Path, ReqData};
+use actix_web::{delete, get, HttpResponse, patch, post, put};
+use actix_web::web::{Data, Json, Path, Query, ReqData};
+use chrono::Utc;
 
-use crate::dto::create_task::CreateTask;
+use crate::dto::create_task::{CreateTask, validate_date};
+use crate::dto::task_page::{TaskCursor, TaskPage};
+use crate::dto::task_query::TaskQuery;
+use crate::dto::task_search::TaskSearch;
+use crate::dto::task_search_result::{TaskHighlights, TaskSearchResult};
 use crate::dto::task_preview::TaskPreview;
+use crate::dto::update_task::UpdateTask;
 use crate::dto::update_task_status::UpdateTaskStatus;
+use crate::model::task_model::TaskStatus;
 use crate::model::user_model::User;
 use crate::repository::task_repository::TaskRepository;
 use crate::repository::user_repository::UserRepository;
-use crate::validator::request_validators::validate_request_body;
+use crate::service::reminder_service::{next_overdue_alert, reminder_instant};
+use crate::service::scheduler_service::Scheduler;
+use crate::service::search_service::{highlight, search_terms};
+use crate::service::task_service::{complete_if_checked, create_next_occurrence, unfinished_blockers};
+use crate::service::time_zone_service::{due_instant, user_time_zone};
+use crate::validator::request_validators::{validate_query, validate_request_body, validation_error};
+
+const DEFAULT_SEARCH_PAGE_SIZE: i64 = 20;
 
 #[get("/task/{id}")]
 pub async fn get_task(task_repo: Data<TaskRepository>, logged_user_data: Option<ReqData<User>>,
@@ -21,13 +35,7 @@ pub async fn get_task(task_repo: Data<TaskRepository>, logged_user_data: Option<
 
     match task_repo.find_by_id(&task_id, &logged_user.id.unwrap()).await {
         Ok(task_option) => match task_option {
-            Some(task) => HttpResponse::Ok().json(TaskPreview {
-                id: task.id.unwrap().to_string(),
-                title: task.title,
-                description: task.description,
-                status: task.status,
-                due_date: task.due_date,
-            }),
+            Some(task) => HttpResponse::Ok().json(TaskPreview::from(task)),
             None => HttpResponse::NotFound().json("Task not found")
         }
         Err(e) => {
@@ -39,32 +47,76 @@ pub async fn get_task(task_repo: Data<TaskRepository>, logged_user_data: Option<
 
 #[get("/task")]
 pub async fn get_all_tasks_for_user(task_repo: Data<TaskRepository>,
-                                    logged_user_data: Option<ReqData<User>>) -> HttpResponse {
+                                    logged_user_data: Option<ReqData<User>>,
+                                    query: Query<TaskQuery>) -> HttpResponse {
     let logged_user = match logged_user_data {
         Some(claims) => claims,
         _ => return HttpResponse::Unauthorized().finish()
     };
 
-    match task_repo.find_all_for_user(&logged_user.id.unwrap()).await {
-        Ok(tasks) => {
-            let tasks_review: Vec<TaskPreview> = tasks.into_iter()
-                .map(|task| TaskPreview {
-                    id: task.id.unwrap().to_string(),
-                    title: task.title,
-                    description: task.description,
-                    status: task.status,
-                    due_date: task.due_date,
-                }).collect();
+    let task_query = match validate_query(query).await {
+        Ok(task_query) => task_query,
+        Err(bad_request) => return bad_request
+    };
 
-            HttpResponse::Ok().json(tasks_review)
-        }
+    let cursor = match &task_query.cursor {
+        Some(cursor) => match TaskCursor::decode(cursor) {
+            Some(cursor) if cursor.sort == task_query.sort.unwrap_or_default()
+                && cursor.order == task_query.order.unwrap_or_default() => Some(cursor),
+            _ => return HttpResponse::BadRequest().json("Invalid cursor")
+        },
+        None => None
+    };
+
+    match task_repo.find_page(&logged_user.id.unwrap(), &task_query, cursor.as_ref(), Utc::now()).await {
+        Ok((tasks, next_cursor)) => HttpResponse::Ok().json(TaskPage {
+            tasks: tasks.into_iter().map(TaskPreview::from).collect(),
+            next_cursor: next_cursor.map(|cursor| cursor.encode()),
+        }),
         Err(e) => HttpResponse::InternalServerError().body(e.to_string())
     }
 }
 
+#[get("/task/search")]
+pub async fn search_tasks(task_repo: Data<TaskRepository>,
+                          logged_user_data: Option<ReqData<User>>,
+                          query: Query<TaskSearch>) -> HttpResponse {
+    let logged_user = match logged_user_data {
+        Some(claims) => claims,
+        _ => return HttpResponse::Unauthorized().finish()
+    };
+
+    let search = match validate_query(query).await {
+        Ok(search) => search,
+        Err(bad_request) => return bad_request
+    };
+
+    let per_page = search.per_page.unwrap_or(DEFAULT_SEARCH_PAGE_SIZE);
+    let skip = (search.page.unwrap_or(1) - 1) * per_page as u64;
+    let results = match task_repo.search(&logged_user.id.unwrap(), &search.q, skip, per_page).await {
+        Ok(results) => results,
+        Err(e) => return HttpResponse::InternalServerError().body(e.to_string())
+    };
+
+    let terms = search_terms(&search.q);
+    let results: Vec<TaskSearchResult> = results.into_iter()
+        .map(|(task, score)| TaskSearchResult {
+            highlights: TaskHighlights {
+                title: highlight(&task.title, &terms),
+                description: highlight(&task.description, &terms),
+            },
+            task: TaskPreview::from(task),
+            score,
+        })
+        .collect();
+
+    HttpResponse::Ok().json(results)
+}
+
 #[post("/task")]
 pub async fn create_task(task_repo: Data<TaskRepository>, user_repo: Data<UserRepository>,
-                         logged_user_data: Option<ReqData<User>>, body: Json<CreateTask>) -> HttpResponse {
+                         scheduler: Data<Scheduler>, logged_user_data: Option<ReqData<User>>,
+                         body: Json<CreateTask>) -> HttpResponse {
     let logged_user = match logged_user_data {
         Some(claims) => claims,
         _ => return HttpResponse::Unauthorized().finish()
@@ -75,6 +127,11 @@ pub async fn create_task(task_repo: Data<TaskRepository>, user_repo: Data<UserRe
         Err(bad_request) => return bad_request,
     };
 
+    let time_zone = user_time_zone(&logged_user);
+    if let Err(e) = validate_date(&new_task.due_date, new_task.due_time, time_zone) {
+        return validation_error("due_date", e);
+    }
+
     let user = match user_repo.find_by_email(&logged_user.email).await {
         Ok(user_option) => {
             match user_option {
@@ -85,22 +142,13 @@ pub async fn create_task(task_repo: Data<TaskRepository>, user_repo: Data<UserRe
         Err(e) => return HttpResponse::InternalServerError().json(format!("Something went wrong while creating task: {}", e)),
     };
 
-    let tasks_result = match task_repo.create_task(&new_task, &user.id.unwrap()).await {
-        Ok(tasks) => tasks,
-        Err(e) => return HttpResponse::InternalServerError().body(e.to_string())
-    };
-
-    let tasks: Vec<TaskPreview> = tasks_result.into_iter()
-        .map(|t| TaskPreview {
-            id: t.id.unwrap().to_string(),
-            title: t.title.to_string(),
-            description: t.description.to_string(),
-            due_date: t.due_date,
-            status: t.status.clone(),
-        })
-        .collect();
-
-    HttpResponse::Created().json(tasks)
+    match task_repo.create_task(&new_task, &user.id.unwrap(), time_zone).await {
+        Ok(task) => {
+            scheduler.reschedule();
+            HttpResponse::Created().json(TaskPreview::from(task))
+        }
+        Err(e) => HttpResponse::InternalServerError().body(e.to_string())
+    }
 }
 
 #[delete("/task/{id}")]
@@ -111,27 +159,15 @@ pub async fn delete_task(task_repo: Data<TaskRepository>,
         _ => return HttpResponse::Unauthorized().finish()
     };
 
-    let tasks_result = match task_repo.delete_by_id(&task_id, &logged_user.id.unwrap()).await {
-        Ok(Some(tasks)) => tasks,
-        Ok(None) => return HttpResponse::NotFound().json("Task not found"),
-        Err(e) => return HttpResponse::InternalServerError().body(e.to_string())
-    };
-
-    let tasks: Vec<TaskPreview> = tasks_result.into_iter()
-        .map(|t| TaskPreview {
-            id: t.id.unwrap().to_string(),
-            title: t.title.to_string(),
-            description: t.description.to_string(),
-            due_date: t.due_date,
-            status: t.status.clone(),
-        })
-        .collect();
-
-    HttpResponse::Ok().json(tasks)
+    match task_repo.delete_by_id(&task_id, &logged_user.id.unwrap()).await {
+        Ok(true) => HttpResponse::NoContent().finish(),
+        Ok(false) => HttpResponse::NotFound().json("Task not found"),
+        Err(e) => HttpResponse::InternalServerError().body(e.to_string())
+    }
 }
 
 #[put("/task/{task_id}")]
-pub async fn update_task_status(task_repo: Data<TaskRepository>,
+pub async fn update_task_status(task_repo: Data<TaskRepository>, scheduler: Data<Scheduler>,
                                 logged_user_data: Option<ReqData<User>>,
                                 task_id: Path<String>,
                                 new_task: Json<UpdateTaskStatus>) -> HttpResponse {
@@ -141,22 +177,106 @@ pub async fn update_task_status(task_repo: Data<TaskRepository>,
         _ => return HttpResponse::Unauthorized().finish()
     };
 
-    let tasks_result = match task_repo.update_status(&task_id,
-                                                &logged_user.id.unwrap(), &new_task.new_status).await {
-        Ok(Some(tasks)) => tasks,
+    let task = match task_repo.find_by_id(&task_id, &logged_user.id.unwrap()).await {
+        Ok(Some(task)) => task,
         Ok(None) => return HttpResponse::NotFound().json("Task not found"),
         Err(e) => return HttpResponse::InternalServerError().body(e.to_string())
     };
 
-    let tasks: Vec<TaskPreview> = tasks_result.into_iter()
-        .map(|t| TaskPreview {
-            id: t.id.unwrap().to_string(),
-            title: t.title.to_string(),
-            description: t.description.to_string(),
-            due_date: t.due_date,
-            status: t.status.clone(),
-        })
-        .collect();
+    if new_task.new_status == TaskStatus::Done && task.has_open_checklist_items() && !new_task.force {
+        return HttpResponse::Conflict().json("The task has open checklist items, set force to complete it anyway");
+    }
+
+    if matches!(new_task.new_status, TaskStatus::InProgress | TaskStatus::Done) {
+        match unfinished_blockers(&task_repo, &task).await {
+            Ok(blockers) if blockers.is_empty() => (),
+            Ok(blockers) => {
+                let titles: Vec<String> = blockers.into_iter().map(|blocker| blocker.title).collect();
+                return HttpResponse::Conflict().json(format!("The task is blocked by: {}", titles.join(", ")));
+            }
+            Err(e) => return HttpResponse::InternalServerError().body(e.to_string())
+        }
+    }
+
+    let require_checked = new_task.new_status == TaskStatus::Done && !new_task.force;
+    let task = match task_repo.update_status(&task_id, &logged_user.id.unwrap(), &new_task.new_status,
+                                             require_checked).await {
+        Ok(Some(task)) => task,
+        // an item was unchecked since the task was read
+        Ok(None) if require_checked => {
+            return HttpResponse::Conflict().json("The task has open checklist items, set force to complete it anyway");
+        }
+        Ok(None) => return HttpResponse::NotFound().json("Task not found"),
+        Err(e) => return HttpResponse::InternalServerError().body(e.to_string())
+    };
+
+    match create_next_occurrence(&task_repo, &task, user_time_zone(&logged_user)).await {
+        Ok(Some(_)) => {
+            scheduler.reschedule();
+            HttpResponse::Ok().json(TaskPreview::from(task))
+        }
+        Ok(None) => HttpResponse::Ok().json(TaskPreview::from(task)),
+        Err(e) => HttpResponse::InternalServerError().body(e.to_string())
+    }
+}
 
-    HttpResponse::Ok().json(tasks)
-}
\ No newline at end of file
+#[patch("/task/{task_id}")]
+pub async fn update_task(task_repo: Data<TaskRepository>, scheduler: Data<Scheduler>,
+                         logged_user_data: Option<ReqData<User>>,
+                         task_id: Path<String>,
+                         body: Json<UpdateTask>) -> HttpResponse {
+    let logged_user = match logged_user_data {
+        Some(claims) => claims,
+        _ => return HttpResponse::Unauthorized().finish()
+    };
+
+    let update = match validate_request_body(body).await {
+        Ok(update) => update,
+        Err(bad_request) => return bad_request,
+    };
+
+    if update.is_empty() {
+        return HttpResponse::BadRequest().json("Nothing to update");
+    }
+
+    // a new date, time or reminder is combined with the parts that stay to get the new
+    // due and reminder instants
+    let time_zone = user_time_zone(&logged_user);
+    let (due_at, reminder_at) = if update.changes_reminder() {
+        let current = match task_repo.find_by_id(&task_id, &logged_user.id.unwrap()).await {
+            Ok(Some(task)) => task,
+            Ok(None) => return HttpResponse::NotFound().json("Task not found"),
+            Err(e) => return HttpResponse::InternalServerError().body(e.to_string())
+        };
+        let due_date = update.due_date.unwrap_or(current.due_date);
+        let due_time = update.due_time.unwrap_or(current.due_time);
+        if update.changes_due() {
+            if let Err(e) = validate_date(&due_date, due_time, time_zone) {
+                return validation_error("due_date", e);
+            }
+        }
+        let due_at = due_instant(due_date, due_time, time_zone);
+        let overdue_alert_at = next_overdue_alert(due_date, due_time, time_zone, Utc::now());
+        let remind_before_hours = update.remind_before_hours.unwrap_or(current.remind_before_hours);
+        let reminder_at = remind_before_hours.map(|hours| reminder_instant(due_at, hours));
+        (update.changes_due().then_some((due_at, overdue_alert_at)), Some(reminder_at))
+    } else {
+        (None, None)
+    };
+
+    let task = match task_repo.update_task(&task_id, &logged_user.id.unwrap(), &update,
+                                           due_at, reminder_at).await {
+        Ok(Some(task)) => task,
+        Ok(None) => return HttpResponse::NotFound().json("Task not found"),
+        Err(e) => return HttpResponse::InternalServerError().body(e.to_string())
+    };
+
+    // switching auto_complete on for a fully checked list completes the task right away
+    match complete_if_checked(&task_repo, task, time_zone).await {
+        Ok(task) => {
+            scheduler.reschedule();
+            HttpResponse::Ok().json(TaskPreview::from(task))
+        }
+        Err(e) => HttpResponse::InternalServerError().body(e.to_string())
+    }
+}
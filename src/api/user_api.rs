@@ -1,20 +1,39 @@
 use actix_web::{
     delete,
+    get,
     HttpResponse,
     put,
     web::{Data, Json},
 };
-use actix_web::web::ReqData;
+use chrono::Utc;
+use actix_web::web::{Query, ReqData};
 
+use crate::dto::mail_delivery::MailDelivery;
+use crate::dto::mail_search::MailSearch;
+use crate::dto::token_claims::TokenClaims;
+use crate::dto::update_password::UpdatePassword;
+use crate::dto::update_reminder_settings::UpdateReminderSettings;
+use crate::dto::update_time_zone::UpdateTimeZone;
 use crate::dto::update_user::UpdateUser;
-use crate::model::user_model::User;
+use crate::model::user_model::{ReminderSettings, User};
+use crate::repository::access_token_repository::AccessTokenRepository;
+use crate::repository::one_time_token_repository::OneTimeTokenRepository;
+use crate::repository::refresh_token_repository::RefreshTokenRepository;
 use crate::repository::task_repository::TaskRepository;
 use crate::repository::user_repository::UserRepository;
-use crate::validator::request_validators::validate_request_body;
+use crate::service::email_service::start_email_verification;
+use crate::service::outbox_service::Outbox;
+use crate::service::password_service::{hash_password, verify_password};
+use crate::service::reminder_service::next_digest_at;
+use crate::service::scheduler_service::Scheduler;
+use crate::service::time_zone_service::user_time_zone;
+use crate::validator::request_validators::{validate_query, validate_request_body};
+
+const DEFAULT_PAGE_SIZE: i64 = 20;
 
 #[put("/user")]
-pub async fn update_user(db: Data<UserRepository>, logged_user_data: Option<ReqData<User>>,
-                         body: Json<UpdateUser>) -> HttpResponse {
+pub async fn update_user(db: Data<UserRepository>, token_db: Data<OneTimeTokenRepository>, outbox: Data<Outbox>,
+                         logged_user_data: Option<ReqData<User>>, body: Json<UpdateUser>) -> HttpResponse {
 
     let logged_user = match logged_user_data {
         Some(claims) => claims,
@@ -26,14 +45,145 @@ pub async fn update_user(db: Data<UserRepository>, logged_user_data: Option<ReqD
         Err(bad_request) => return bad_request
     };
 
-    match db.update_user(logged_user.id.unwrap(), new_user).await {
-        Ok(_) => HttpResponse::Ok().finish(),
+    // the new address only takes effect once it is confirmed through the mailed link
+    let email = new_user.email.clone();
+    if let Err(e) = db.update_user(logged_user.id.unwrap(), new_user).await {
+        return HttpResponse::InternalServerError().body(e.to_string());
+    }
+
+    match start_email_verification(&outbox, &token_db, &logged_user.id.unwrap(), email).await {
+        Ok(_) => HttpResponse::Accepted().finish(),
+        Err(e) => HttpResponse::InternalServerError().body(e.to_string())
+    }
+}
+
+#[put("/user/password")]
+pub async fn update_password(user_db: Data<UserRepository>, refresh_db: Data<RefreshTokenRepository>,
+                             logged_user_data: Option<ReqData<User>>,
+                             token_claims_data: Option<ReqData<TokenClaims>>,
+                             body: Json<UpdatePassword>) -> HttpResponse {
+
+    let (logged_user, token_claims) = match (logged_user_data, token_claims_data) {
+        (Some(user), Some(claims)) => (user, claims),
+        _ => return HttpResponse::Unauthorized().finish()
+    };
+
+    let request = match validate_request_body(body).await {
+        Ok(request) => request,
+        Err(bad_request) => return bad_request
+    };
+
+    match verify_password(&logged_user.password, &request.current_password) {
+        Ok(true) => (),
+        Ok(false) => return HttpResponse::BadRequest().json("Incorrect password"),
+        Err(e) => return HttpResponse::InternalServerError().body(e.to_string())
+    };
+
+    let password_hash = match hash_password(&request.new_password) {
+        Ok(password_hash) => password_hash,
+        Err(e) => return HttpResponse::InternalServerError().body(e.to_string())
+    };
+    if let Err(e) = user_db.update_password(&logged_user.id.unwrap(), password_hash).await {
+        return HttpResponse::InternalServerError().body(e.to_string());
+    }
+
+    // every other session has to sign in again with the new password
+    match refresh_db.revoke_other_families(&logged_user.id.unwrap(), &token_claims.sid).await {
+        Ok(_) => HttpResponse::NoContent().finish(),
         Err(e) => HttpResponse::InternalServerError().body(e.to_string())
     }
 }
 
+#[put("/user/time-zone")]
+pub async fn update_time_zone(user_db: Data<UserRepository>, task_db: Data<TaskRepository>,
+                              scheduler: Data<Scheduler>, logged_user_data: Option<ReqData<User>>,
+                              body: Json<UpdateTimeZone>) -> HttpResponse {
+
+    let logged_user = match logged_user_data {
+        Some(claims) => claims,
+        _ => return HttpResponse::Unauthorized().finish()
+    };
+
+    let request = match validate_request_body(body).await {
+        Ok(request) => request,
+        Err(bad_request) => return bad_request
+    };
+
+    let time_zone = request.time_zone.parse().unwrap();
+    let next_digest = next_digest_at(&logged_user.reminders, time_zone, Utc::now());
+    if let Err(e) = user_db.update_time_zone(&logged_user.id.unwrap(), &request.time_zone, next_digest).await {
+        return HttpResponse::InternalServerError().body(e.to_string());
+    }
+
+    // due dates keep their day and time, which are now read in the new zone
+    if let Err(e) = task_db.update_time_zone(&logged_user.id.unwrap(), time_zone).await {
+        return HttpResponse::InternalServerError().body(e.to_string());
+    }
+
+    scheduler.reschedule();
+    HttpResponse::NoContent().finish()
+}
+
+#[get("/user/reminders")]
+pub async fn get_reminder_settings(logged_user_data: Option<ReqData<User>>) -> HttpResponse {
+    match logged_user_data {
+        Some(logged_user) => HttpResponse::Ok().json(&logged_user.reminders),
+        None => HttpResponse::Unauthorized().finish()
+    }
+}
+
+/// The mails sent or still to be sent to the user, newest first.
+#[get("/user/mails")]
+pub async fn get_mails(outbox: Data<Outbox>, logged_user_data: Option<ReqData<User>>,
+                       query: Query<MailSearch>) -> HttpResponse {
+    let logged_user = match logged_user_data {
+        Some(user) => user,
+        _ => return HttpResponse::Unauthorized().finish()
+    };
+
+    let search = match validate_query(query).await {
+        Ok(search) => search,
+        Err(bad_request) => return bad_request
+    };
+
+    let per_page = search.per_page.unwrap_or(DEFAULT_PAGE_SIZE);
+    let skip = (search.page.unwrap_or(1) - 1) * per_page as u64;
+    match outbox.delivery_log(logged_user.id.as_ref(), search.status, skip, per_page).await {
+        Ok(messages) => {
+            let deliveries: Vec<MailDelivery> = messages.into_iter().map(MailDelivery::from).collect();
+            HttpResponse::Ok().json(deliveries)
+        }
+        Err(e) => HttpResponse::InternalServerError().body(e.to_string())
+    }
+}
+
+#[put("/user/reminders")]
+pub async fn update_reminder_settings(user_db: Data<UserRepository>, scheduler: Data<Scheduler>,
+                                      logged_user_data: Option<ReqData<User>>,
+                                      body: Json<UpdateReminderSettings>) -> HttpResponse {
+
+    let logged_user = match logged_user_data {
+        Some(claims) => claims,
+        _ => return HttpResponse::Unauthorized().finish()
+    };
+
+    let settings: ReminderSettings = match validate_request_body(body).await {
+        Ok(request) => request.into(),
+        Err(bad_request) => return bad_request
+    };
+
+    let next_digest = next_digest_at(&settings, user_time_zone(&logged_user), Utc::now());
+    if let Err(e) = user_db.update_reminders(&logged_user.id.unwrap(), &settings, next_digest).await {
+        return HttpResponse::InternalServerError().body(e.to_string());
+    }
+
+    scheduler.reschedule();
+    HttpResponse::Ok().json(settings)
+}
+
 #[delete("/user")]
 pub async fn delete_user(user_db: Data<UserRepository>, task_db: Data<TaskRepository>,
+                         refresh_db: Data<RefreshTokenRepository>, access_token_db: Data<AccessTokenRepository>,
                          logged_user_data: Option<ReqData<User>>) -> HttpResponse {
 
     let logged_user = match logged_user_data {
@@ -51,6 +201,14 @@ pub async fn delete_user(user_db: Data<UserRepository>, task_db: Data<TaskReposi
         Err(e) => return HttpResponse::InternalServerError().json(format!("Error while deleting the user: {}", e))
     };
 
+    if let Err(e) = refresh_db.revoke_all_for_user(&user.id.unwrap()).await {
+        return HttpResponse::InternalServerError().json(format!("Error while revoking the user's sessions: {}", e));
+    }
+
+    if let Err(e) = access_token_db.delete_all_for_user(&user.id.unwrap()).await {
+        return HttpResponse::InternalServerError().json(format!("Error while deleting the user's access tokens: {}", e));
+    }
+
     match task_db.delete_all_for_user(&user.id.unwrap()).await {
         Ok(_) => HttpResponse::NoContent().finish(),
         Err(e) => HttpResponse::InternalServerError().json(format!("Error while deleting the user's tasks: {}", e))
@@ -0,0 +1,35 @@
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+use crate::model::access_token_model::{PersonalAccessToken, TokenScope};
+
+#[derive(Serialize)]
+pub struct AccessTokenPreview {
+    pub id: String,
+    pub name: String,
+    pub scopes: Vec<TokenScope>,
+    pub created_at: DateTime<Utc>,
+    pub expires_at: Option<DateTime<Utc>>,
+    pub last_used_at: Option<DateTime<Utc>>,
+}
+
+impl From<PersonalAccessToken> for AccessTokenPreview {
+    fn from(token: PersonalAccessToken) -> Self {
+        AccessTokenPreview {
+            id: token.id.unwrap().to_string(),
+            name: token.name,
+            scopes: token.scopes,
+            created_at: token.created_at.to_chrono(),
+            expires_at: token.expires_at.map(|date| date.to_chrono()),
+            last_used_at: token.last_used_at.map(|date| date.to_chrono()),
+        }
+    }
+}
+
+/// Returned once when the token is created; the plain token can't be looked up later.
+#[derive(Serialize)]
+pub struct CreatedAccessToken {
+    pub token: String,
+    #[serde(flatten)]
+    pub details: AccessTokenPreview,
+}
@@ -0,0 +1,8 @@
+use serde::Deserialize;
+use validator::Validate;
+
+/// A task of the same user that has to be done first.
+#[derive(Deserialize, Validate)]
+pub struct AddBlocker {
+    pub blocker_id: String
+}
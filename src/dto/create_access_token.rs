@@ -0,0 +1,17 @@
+use serde::Deserialize;
+use validator::Validate;
+
+use crate::model::access_token_model::TokenScope;
+
+#[derive(Deserialize, Validate)]
+pub struct CreateAccessToken {
+    #[validate(length(min = 1, max = 100))]
+    pub name: String,
+
+    /// Defaults to all task scopes.
+    pub scopes: Option<Vec<TokenScope>>,
+
+    /// The token never expires if left out.
+    #[validate(range(min = 1, max = 365))]
+    pub expires_in_days: Option<i64>
+}
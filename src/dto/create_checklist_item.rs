@@ -0,0 +1,10 @@
+use serde::Deserialize;
+use validator::Validate;
+
+pub const MAX_CHECKLIST_ITEMS: usize = 100;
+
+#[derive(Deserialize, Validate)]
+pub struct CreateChecklistItem {
+    #[validate(length(min = 1, max = 200))]
+    pub text: String
+}
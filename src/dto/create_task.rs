@@ -1,8 +1,13 @@
-use chrono::{Datelike, Local, NaiveDate};
+use chrono::{NaiveDate, NaiveTime, Utc};
+use chrono_tz::Tz;
 use serde::Deserialize;
 use validator::{Validate, ValidationError};
 
+use crate::model::task_model::{Frequency, Priority, Recurrence};
+use crate::service::time_zone_service::{due_instant, today};
+
 #[derive(Deserialize, Validate)]
+#[validate(schema(function = "validate_schedule"))]
 pub struct CreateTask {
     #[validate(length(min = 1))]
     pub title: String,
@@ -10,17 +15,114 @@ pub struct CreateTask {
     #[validate(length(min = 1))]
     pub description: String,
 
-    #[validate(custom = "validate_date")]
-    pub due_date: NaiveDate
+    /// Checked with `validate_date` once the user's zone is known.
+    pub due_date: NaiveDate,
+
+    /// Time of day in the user's zone, the end of the day if left out.
+    pub due_time: Option<NaiveTime>,
+
+    /// Send a reminder this many hours before the task is due.
+    #[validate(range(min = 1, max = "MAX_REMIND_BEFORE_HOURS"))]
+    pub remind_before_hours: Option<u32>,
+
+    /// `Medium` if left out.
+    #[serde(default)]
+    pub priority: Priority,
+
+    #[serde(default)]
+    #[validate(custom = "validate_tags")]
+    pub tags: Vec<String>,
+
+    /// Mark the task `Done` once all checklist items are checked.
+    #[serde(default)]
+    pub auto_complete: bool,
+
+    pub recurrence: Option<Recurrence>
 }
 
-pub fn validate_date(value: &NaiveDate) -> Result<(), ValidationError> {
-    let today = NaiveDate::from_ymd_opt(Local::now().year(),
-                                        Local::now().month(), Local::now().day()).unwrap();
+pub const MAX_TAGS: usize = 20;
+const MAX_TAG_LENGTH: usize = 32;
+const MAX_RECURRENCE_INTERVAL: u32 = 999;
+pub const MAX_REMIND_BEFORE_HOURS: u32 = 720;
 
-    if *value >= today {
-        Ok(())
-    } else {
-        Err(ValidationError::new("Date must not be in the past"))
+/// The due date, and time if there is one, must not be in the past in the user's zone.
+pub fn validate_date(value: &NaiveDate, time: Option<NaiveTime>, time_zone: Tz) -> Result<(), ValidationError> {
+    if *value < today(time_zone) {
+        return Err(ValidationError::new("Date must not be in the past"));
+    }
+    if time.is_some() && due_instant(*value, time, time_zone) < Utc::now() {
+        return Err(ValidationError::new("Time must not be in the past"));
+    }
+    Ok(())
+}
+
+/// The due date together with the recurrence rule that starts from it.
+fn validate_schedule(task: &CreateTask) -> Result<(), ValidationError> {
+    match &task.recurrence {
+        Some(recurrence) => validate_recurrence(recurrence, &task.due_date),
+        None => Ok(())
+    }
+}
+
+pub fn validate_recurrence(value: &Recurrence, due_date: &NaiveDate) -> Result<(), ValidationError> {
+    if value.interval == 0 || value.interval > MAX_RECURRENCE_INTERVAL {
+        return Err(ValidationError::new("Recurrence interval must be between 1 and 999"));
+    }
+    if !value.weekdays.is_empty() && value.frequency != Frequency::Weekly {
+        return Err(ValidationError::new("Weekdays can only be set on weekly recurrences"));
+    }
+    match value.month_day {
+        Some(_) if !matches!(value.frequency, Frequency::Monthly | Frequency::Yearly) =>
+            return Err(ValidationError::new("A day of the month can only be set on monthly or yearly recurrences")),
+        Some(day) if !(1..=31).contains(&day) =>
+            return Err(ValidationError::new("Day of the month must be between 1 and 31")),
+        _ => ()
+    }
+    if value.count == Some(0) {
+        return Err(ValidationError::new("Recurrence count must be at least 1"));
     }
-}
\ No newline at end of file
+    match value.until {
+        Some(until) if until < *due_date => Err(ValidationError::new("Recurrence must not end before the due date")),
+        _ => Ok(())
+    }
+}
+
+pub fn validate_tags(value: &[String]) -> Result<(), ValidationError> {
+    if value.len() > MAX_TAGS {
+        return Err(ValidationError::new("A task can have at most 20 tags"));
+    }
+
+    for tag in value {
+        validate_tag(tag)?;
+    }
+    Ok(())
+}
+
+pub fn validate_tag(value: &str) -> Result<(), ValidationError> {
+    let tag = value.trim();
+    if tag.is_empty() || tag.chars().count() > MAX_TAG_LENGTH {
+        return Err(ValidationError::new("Tags must have between 1 and 32 characters"));
+    }
+    // commas separate the tags in the `GET /task` filter
+    if tag.contains(',') {
+        return Err(ValidationError::new("Tags must not contain commas"));
+    }
+    Ok(())
+}
+
+/// Tags are stored trimmed and lowercase, without duplicates, so `Work` and `work ` are the
+/// same tag.
+pub fn normalize_tags(tags: &[String]) -> Vec<String> {
+    let mut normalized: Vec<String> = Vec::new();
+    for tag in tags {
+        let tag = normalize_tag(tag);
+        if !normalized.contains(&tag) {
+            normalized.push(tag);
+        }
+    }
+    normalized
+}
+
+pub fn normalize_tag(tag: &str) -> String {
+    tag.trim().to_lowercase()
+}
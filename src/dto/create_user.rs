@@ -1,11 +1,16 @@
 use serde::Deserialize;
 use validator::Validate;
 
+use crate::dto::update_time_zone::validate_time_zone;
+
 #[derive(Deserialize, Validate)]
 pub struct CreateUser {
     #[validate(email)]
     pub email: String,
 
     #[validate(length(min = 6))]
-    pub password: String
-}
\ No newline at end of file
+    pub password: String,
+
+    #[validate(custom = "validate_time_zone")]
+    pub time_zone: Option<String>
+}
@@ -0,0 +1,18 @@
+use serde::Serialize;
+
+use crate::model::task_model::TaskStatus;
+
+#[derive(Serialize)]
+pub struct DependencyGraph {
+    pub tasks: Vec<DependencyNode>,
+    /// Ids of the open tasks, each one after the open tasks blocking it.
+    pub order: Vec<String>,
+}
+
+#[derive(Serialize)]
+pub struct DependencyNode {
+    pub id: String,
+    pub title: String,
+    pub status: TaskStatus,
+    pub blocked_by: Vec<String>,
+}
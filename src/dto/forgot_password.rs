@@ -0,0 +1,8 @@
+use serde::Deserialize;
+use validator::Validate;
+
+#[derive(Deserialize, Validate)]
+pub struct ForgotPassword {
+    #[validate(email)]
+    pub email: String
+}
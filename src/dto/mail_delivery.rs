@@ -0,0 +1,44 @@
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+use crate::model::outbox_model::{DeliveryStatus, MailKind, OutboxMessage};
+
+/// Delivery status of a mail in the outbox.
+#[derive(Serialize)]
+pub struct MailDelivery {
+    pub id: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub user_id: Option<String>,
+    pub kind: MailKind,
+    pub to: String,
+    pub subject: String,
+    pub status: DeliveryStatus,
+    pub attempts: u32,
+    /// When the next attempt is due, while the mail is still waiting to be sent.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub next_attempt_at: Option<DateTime<Utc>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_error: Option<String>,
+    pub created_at: DateTime<Utc>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sent_at: Option<DateTime<Utc>>,
+}
+
+impl From<OutboxMessage> for MailDelivery {
+    fn from(message: OutboxMessage) -> Self {
+        let waiting = matches!(message.status, DeliveryStatus::Pending | DeliveryStatus::Sending);
+        MailDelivery {
+            id: message.id.unwrap().to_string(),
+            user_id: message.user_id.map(|id| id.to_string()),
+            kind: message.kind,
+            to: message.to,
+            subject: message.subject,
+            status: message.status,
+            attempts: message.attempts,
+            next_attempt_at: waiting.then(|| message.next_attempt_at.to_chrono()),
+            last_error: message.last_error,
+            created_at: message.created_at.to_chrono(),
+            sent_at: message.sent_at.map(|date| date.to_chrono()),
+        }
+    }
+}
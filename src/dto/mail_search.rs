@@ -0,0 +1,19 @@
+use serde::Deserialize;
+use validator::Validate;
+
+use crate::model::outbox_model::DeliveryStatus;
+
+#[derive(Deserialize, Validate)]
+pub struct MailSearch {
+    pub status: Option<DeliveryStatus>,
+
+    /// Only mails of this user. Only for admins, users always get their own mails.
+    pub user_id: Option<String>,
+
+    /// Capped so the number of results to skip can't overflow.
+    #[validate(range(min = 1, max = 10000))]
+    pub page: Option<u64>,
+
+    #[validate(range(min = 1, max = 100))]
+    pub per_page: Option<i64>
+}
@@ -0,0 +1,8 @@
+use serde::Deserialize;
+use validator::Validate;
+
+#[derive(Deserialize, Validate)]
+pub struct MfaCode {
+    #[validate(length(min = 1))]
+    pub code: String
+}
@@ -0,0 +1,13 @@
+use serde::{Deserialize, Serialize};
+
+/// Claims of the short-lived token handed out after the password step of a sign-in for
+/// users with two-factor authentication. It can only be exchanged at `/auth/sign-in/mfa`.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct MfaPendingClaims {
+    pub sub: String,
+    /// Always `MFA_TOKEN_AUDIENCE`.
+    pub aud: String,
+    pub mfa_pending: bool,
+    pub iat: i64,
+    pub exp: i64,
+}
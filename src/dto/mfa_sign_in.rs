@@ -0,0 +1,11 @@
+use serde::Deserialize;
+use validator::Validate;
+
+#[derive(Deserialize, Validate)]
+pub struct MfaSignIn {
+    #[validate(length(min = 1))]
+    pub mfa_token: String,
+
+    #[validate(length(min = 1))]
+    pub code: String
+}
@@ -4,3 +4,42 @@ pub mod create_user;
 pub mod create_task;
 pub mod task_preview;
 pub mod update_task_status;
+pub mod token_response;
+pub mod refresh_token_request;
+pub mod update_password;
+pub mod forgot_password;
+pub mod reset_password;
+pub mod resend_verification;
+pub mod verify_email;
+pub mod mfa_pending_claims;
+pub mod mfa_challenge;
+pub mod mfa_sign_in;
+pub mod mfa_code;
+pub mod mfa_enrollment;
+pub mod recovery_codes;
+pub mod create_access_token;
+pub mod update_access_token;
+pub mod access_token_preview;
+pub mod user_search;
+pub mod user_summary;
+pub mod oidc_callback;
+pub mod update_task;
+pub mod task_query;
+pub mod task_page;
+pub mod task_search;
+pub mod task_search_result;
+pub mod task_tags;
+pub mod rename_tag;
+pub mod tag_usage;
+pub mod create_checklist_item;
+pub mod update_checklist_item;
+pub mod reorder_checklist;
+pub mod add_blocker;
+pub mod dependency_graph;
+pub mod update_time_zone;
+pub mod update_reminder_settings;
+pub mod mail_search;
+pub mod mail_delivery;
+pub mod update_notification_settings;
+pub mod unsubscribe;
+pub mod update_role;
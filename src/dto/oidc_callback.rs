@@ -0,0 +1,10 @@
+use serde::Deserialize;
+
+/// Query the identity provider appends when redirecting back to us.
+#[derive(Deserialize)]
+pub struct OidcCallback {
+    pub code: Option<String>,
+    pub state: Option<String>,
+    pub error: Option<String>,
+    pub error_description: Option<String>,
+}
@@ -0,0 +1,11 @@
+use serde::Deserialize;
+use validator::Validate;
+
+use crate::dto::create_task::validate_tag;
+
+/// New name of a tag. If the user already has a tag with that name the two are merged.
+#[derive(Deserialize, Validate)]
+pub struct RenameTag {
+    #[validate(custom = "validate_tag")]
+    pub name: String
+}
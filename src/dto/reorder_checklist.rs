@@ -0,0 +1,8 @@
+use serde::Deserialize;
+use validator::Validate;
+
+/// Ids of all items of the checklist, in their new order.
+#[derive(Deserialize, Validate)]
+pub struct ReorderChecklist {
+    pub item_ids: Vec<String>
+}
@@ -0,0 +1,8 @@
+use serde::Deserialize;
+use validator::Validate;
+
+#[derive(Deserialize, Validate)]
+pub struct ResendVerification {
+    #[validate(email)]
+    pub email: String
+}
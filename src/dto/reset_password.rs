@@ -0,0 +1,11 @@
+use serde::Deserialize;
+use validator::Validate;
+
+#[derive(Deserialize, Validate)]
+pub struct ResetPassword {
+    #[validate(length(min = 1))]
+    pub token: String,
+
+    #[validate(length(min = 6))]
+    pub new_password: String
+}
@@ -0,0 +1,8 @@
+use serde::Serialize;
+
+#[derive(Serialize)]
+pub struct TagUsage {
+    pub tag: String,
+    /// Number of the user's tasks with the tag.
+    pub count: i64,
+}
@@ -0,0 +1,44 @@
+use base64::Engine;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use mongodb::bson::oid::ObjectId;
+use serde::{Deserialize, Serialize};
+
+use crate::dto::task_preview::TaskPreview;
+use crate::dto::task_query::{SortOrder, TaskSort};
+
+#[derive(Serialize)]
+pub struct TaskPage {
+    pub tasks: Vec<TaskPreview>,
+    /// Pass as `cursor` to get the next page; missing on the last page.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub next_cursor: Option<String>,
+}
+
+/// Position after the last task of a page: the values of the sort fields and the id, which
+/// breaks ties between tasks with the same values. Only valid for the sort it was made for.
+#[derive(Serialize, Deserialize)]
+pub struct TaskCursor {
+    pub sort: TaskSort,
+    pub order: SortOrder,
+    #[serde(rename = "v", default)]
+    pub values: Vec<CursorValue>,
+    pub id: ObjectId,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(untagged)]
+pub enum CursorValue {
+    Number(i32),
+    Text(String),
+}
+
+impl TaskCursor {
+    pub fn encode(&self) -> String {
+        URL_SAFE_NO_PAD.encode(serde_json::to_vec(self).unwrap())
+    }
+
+    pub fn decode(cursor: &str) -> Option<Self> {
+        let bytes = URL_SAFE_NO_PAD.decode(cursor).ok()?;
+        serde_json::from_slice(&bytes).ok()
+    }
+}
@@ -1,6 +1,6 @@
-use chrono::NaiveDate;
+use chrono::{DateTime, NaiveDate, NaiveTime, Utc};
 use serde::Serialize;
-use crate::model::task_model::TaskStatus;
+use crate::model::task_model::{ChecklistItem, Priority, Recurrence, Task, TaskStatus};
 
 #[derive(Serialize)]
 pub struct TaskPreview {
@@ -9,4 +9,68 @@ pub struct TaskPreview {
     pub description: String,
     pub status: TaskStatus,
     pub due_date: NaiveDate,
-}
\ No newline at end of file
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub due_time: Option<NaiveTime>,
+    /// When the task is due, as a UTC instant.
+    pub due_at: DateTime<Utc>,
+    /// Not done and past its due time, which is read in the user's zone.
+    pub overdue: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub remind_before_hours: Option<u32>,
+    pub priority: Priority,
+    pub tags: Vec<String>,
+    pub checklist: Vec<ChecklistItemPreview>,
+    pub progress: ChecklistProgress,
+    pub auto_complete: bool,
+    pub blocked_by: Vec<String>,
+    pub recurrence: Option<Recurrence>,
+}
+
+#[derive(Serialize)]
+pub struct ChecklistItemPreview {
+    pub id: String,
+    pub text: String,
+    pub done: bool,
+}
+
+#[derive(Serialize)]
+pub struct ChecklistProgress {
+    pub done: usize,
+    pub total: usize,
+}
+
+impl From<ChecklistItem> for ChecklistItemPreview {
+    fn from(item: ChecklistItem) -> Self {
+        ChecklistItemPreview {
+            id: item.id.to_hex(),
+            text: item.text,
+            done: item.done,
+        }
+    }
+}
+
+impl From<Task> for TaskPreview {
+    fn from(task: Task) -> Self {
+        let (done, total) = task.checklist_progress();
+        let due_at = task.due_at.to_chrono();
+        let overdue = task.status != TaskStatus::Done && due_at < Utc::now();
+        TaskPreview {
+            id: task.id.unwrap().to_string(),
+            title: task.title,
+            description: task.description,
+            status: task.status,
+            due_date: task.due_date,
+            due_time: task.due_time,
+            due_at,
+            overdue,
+            remind_before_hours: task.remind_before_hours,
+            priority: task.priority,
+            tags: task.tags,
+            checklist: task.checklist.into_iter().map(ChecklistItemPreview::from).collect(),
+            progress: ChecklistProgress { done, total },
+            auto_complete: task.auto_complete,
+            blocked_by: task.blocked_by.iter().map(|id| id.to_hex()).collect(),
+            recurrence: task.recurrence,
+        }
+    }
+}
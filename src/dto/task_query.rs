@@ -0,0 +1,66 @@
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+use validator::Validate;
+
+use crate::model::task_model::TaskStatus;
+
+#[derive(Deserialize, Validate)]
+pub struct TaskQuery {
+    pub status: Option<TaskStatus>,
+
+    /// First due date to include.
+    pub due_from: Option<NaiveDate>,
+
+    /// Last due date to include.
+    pub due_to: Option<NaiveDate>,
+
+    /// Only tasks that are not done and whose due date has passed.
+    pub overdue: Option<bool>,
+
+    /// Text the title or the description has to contain, ignoring case.
+    #[validate(length(min = 1, max = 100))]
+    pub q: Option<String>,
+
+    /// Comma separated tags; see `tag_match`.
+    pub tags: Option<String>,
+
+    /// Whether tasks need `any` (the default) or `all` of the tags.
+    pub tag_match: Option<TagMatch>,
+
+    pub sort: Option<TaskSort>,
+
+    pub order: Option<SortOrder>,
+
+    #[validate(range(min = 1, max = 100))]
+    pub limit: Option<i64>,
+
+    /// `next_cursor` of the previous page.
+    pub cursor: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum TaskSort {
+    #[default]
+    DueDate,
+    Created,
+    Title,
+    /// Most urgent first, tasks of the same priority by due date.
+    Priority,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum SortOrder {
+    #[default]
+    Asc,
+    Desc,
+}
+
+#[derive(Deserialize, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum TagMatch {
+    #[default]
+    Any,
+    All,
+}
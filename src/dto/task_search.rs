@@ -0,0 +1,17 @@
+use serde::Deserialize;
+use validator::Validate;
+
+#[derive(Deserialize, Validate)]
+pub struct TaskSearch {
+    /// Words to look for. `"quoted phrases"` have to appear as a whole and `-words` must not
+    /// appear at all.
+    #[validate(length(min = 1, max = 200))]
+    pub q: String,
+
+    /// Capped so the number of results to skip can't overflow.
+    #[validate(range(min = 1, max = 10000))]
+    pub page: Option<u64>,
+
+    #[validate(range(min = 1, max = 100))]
+    pub per_page: Option<i64>
+}
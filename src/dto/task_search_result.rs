@@ -0,0 +1,19 @@
+use serde::Serialize;
+
+use crate::dto::task_preview::TaskPreview;
+
+#[derive(Serialize)]
+pub struct TaskSearchResult {
+    #[serde(flatten)]
+    pub task: TaskPreview,
+    /// Relevance computed by the text index; results are ordered by it.
+    pub score: f64,
+    pub highlights: TaskHighlights,
+}
+
+/// HTML-escaped excerpts with the matched words wrapped in `<mark>`.
+#[derive(Serialize)]
+pub struct TaskHighlights {
+    pub title: String,
+    pub description: String,
+}
@@ -0,0 +1,10 @@
+use serde::Deserialize;
+use validator::Validate;
+
+use crate::dto::create_task::validate_tags;
+
+#[derive(Deserialize, Validate)]
+pub struct TaskTags {
+    #[validate(length(min = 1), custom = "validate_tags")]
+    pub tags: Vec<String>
+}
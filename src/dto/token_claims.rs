@@ -2,5 +2,14 @@ use serde::{Deserialize, Serialize};
 
 #[derive(Serialize, Deserialize, Clone)]
 pub struct TokenClaims {
-    pub email: String,
-}
\ No newline at end of file
+    /// Hex encoded `_id` of the user the token was issued to.
+    pub sub: String,
+    /// Always `ACCESS_TOKEN_AUDIENCE`, so no other token we sign passes as an access token.
+    pub aud: String,
+    /// Unique id of this access token.
+    pub jti: String,
+    /// Id of the refresh token family the access token was issued for.
+    pub sid: String,
+    pub iat: i64,
+    pub exp: i64,
+}
@@ -0,0 +1,18 @@
+use serde::Deserialize;
+
+use crate::model::user_model::NotificationKind;
+
+/// Query of the signed link in notification mails.
+#[derive(Deserialize)]
+pub struct Unsubscribe {
+    pub user: String,
+    pub list: NotificationKind,
+    pub sig: String,
+}
+
+/// Body mail clients POST for a one-click unsubscribe (RFC 8058).
+#[derive(Deserialize)]
+pub struct OneClickUnsubscribe {
+    #[serde(rename = "List-Unsubscribe")]
+    pub list_unsubscribe: String,
+}
@@ -0,0 +1,8 @@
+use serde::Deserialize;
+use validator::Validate;
+
+#[derive(Deserialize, Validate)]
+pub struct UpdateAccessToken {
+    #[validate(length(min = 1, max = 100))]
+    pub name: String
+}
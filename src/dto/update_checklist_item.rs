@@ -0,0 +1,11 @@
+use serde::Deserialize;
+use validator::Validate;
+
+#[derive(Deserialize, Validate)]
+pub struct UpdateChecklistItem {
+    #[validate(length(min = 1, max = 200))]
+    pub text: Option<String>,
+
+    /// Checks or unchecks the item.
+    pub done: Option<bool>
+}
@@ -0,0 +1,10 @@
+use serde::Deserialize;
+use validator::Validate;
+
+/// Turns notification mails on or off; settings that are left out stay as they are.
+#[derive(Deserialize, Validate)]
+pub struct UpdateNotificationSettings {
+    pub digest: Option<bool>,
+    pub overdue_alerts: Option<bool>,
+    pub reminders: Option<bool>,
+}
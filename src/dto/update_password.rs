@@ -0,0 +1,10 @@
+use serde::Deserialize;
+use validator::Validate;
+
+#[derive(Deserialize, Validate)]
+pub struct UpdatePassword {
+    pub current_password: String,
+
+    #[validate(length(min = 6))]
+    pub new_password: String
+}
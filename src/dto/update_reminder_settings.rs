@@ -0,0 +1,44 @@
+use chrono::{NaiveTime, Weekday};
+use serde::Deserialize;
+use validator::{Validate, ValidationError};
+
+use crate::model::user_model::{DigestFrequency, ReminderSettings};
+
+const MAX_DAYS_AHEAD: u32 = 30;
+
+#[derive(Deserialize, Validate)]
+#[validate(schema(function = "validate_digest_days"))]
+pub struct UpdateReminderSettings {
+    pub digest_time: NaiveTime,
+
+    #[validate(length(min = 1, max = 7))]
+    pub digest_days: Vec<Weekday>,
+
+    pub digest_frequency: DigestFrequency,
+
+    #[validate(range(max = "MAX_DAYS_AHEAD"))]
+    #[serde(default)]
+    pub days_ahead: u32
+}
+
+impl From<UpdateReminderSettings> for ReminderSettings {
+    fn from(request: UpdateReminderSettings) -> Self {
+        let mut digest_days = request.digest_days;
+        digest_days.sort_by_key(|day| day.num_days_from_monday());
+        digest_days.dedup();
+
+        ReminderSettings {
+            digest_time: request.digest_time,
+            digest_days,
+            digest_frequency: request.digest_frequency,
+            days_ahead: request.days_ahead,
+        }
+    }
+}
+
+fn validate_digest_days(settings: &UpdateReminderSettings) -> Result<(), ValidationError> {
+    if settings.digest_frequency == DigestFrequency::Weekly && settings.digest_days.len() != 1 {
+        return Err(ValidationError::new("A weekly digest goes out on exactly one day"));
+    }
+    Ok(())
+}
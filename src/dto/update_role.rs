@@ -0,0 +1,9 @@
+use serde::Deserialize;
+use validator::Validate;
+
+use crate::model::user_model::Role;
+
+#[derive(Deserialize, Validate)]
+pub struct UpdateRole {
+    pub role: Role
+}
@@ -0,0 +1,58 @@
+use chrono::{NaiveDate, NaiveTime};
+use serde::{Deserialize, Deserializer};
+use validator::Validate;
+
+use crate::dto::create_task::{MAX_REMIND_BEFORE_HOURS, validate_tags};
+use crate::model::task_model::Priority;
+
+/// Partial update of a task. Fields that are left out keep their value; the ones that are
+/// present follow the same rules as in `CreateTask`.
+#[derive(Deserialize, Validate)]
+pub struct UpdateTask {
+    #[validate(length(min = 1))]
+    pub title: Option<String>,
+
+    #[validate(length(min = 1))]
+    pub description: Option<String>,
+
+    pub due_date: Option<NaiveDate>,
+
+    /// `null` removes the time, so the task is due at the end of its day.
+    #[serde(default, deserialize_with = "present")]
+    pub due_time: Option<Option<NaiveTime>>,
+
+    /// `null` turns the reminder off.
+    #[serde(default, deserialize_with = "present")]
+    #[validate(range(min = 1, max = "MAX_REMIND_BEFORE_HOURS"))]
+    pub remind_before_hours: Option<Option<u32>>,
+
+    pub priority: Option<Priority>,
+
+    /// Replaces all tags of the task.
+    #[validate(custom = "validate_tags")]
+    pub tags: Option<Vec<String>>,
+
+    pub auto_complete: Option<bool>,
+}
+
+impl UpdateTask {
+    pub fn is_empty(&self) -> bool {
+        self.title.is_none() && self.description.is_none() && self.due_date.is_none() && self.due_time.is_none()
+            && self.remind_before_hours.is_none() && self.priority.is_none() && self.tags.is_none()
+            && self.auto_complete.is_none()
+    }
+
+    pub fn changes_reminder(&self) -> bool {
+        self.changes_due() || self.remind_before_hours.is_some()
+    }
+
+    pub fn changes_due(&self) -> bool {
+        self.due_date.is_some() || self.due_time.is_some()
+    }
+}
+
+/// Tells a field set to `null` apart from a missing one, which stays `None`.
+fn present<'de, D, T>(deserializer: D) -> Result<Option<T>, D::Error>
+    where D: Deserializer<'de>, T: Deserialize<'de> {
+    T::deserialize(deserializer).map(Some)
+}
@@ -4,5 +4,8 @@ use crate::model::task_model::TaskStatus;
 
 #[derive(Deserialize, Validate)]
 pub struct UpdateTaskStatus {
-    pub new_status: TaskStatus
-}
\ No newline at end of file
+    pub new_status: TaskStatus,
+    /// Allows `Done` even though checklist items are still open.
+    #[serde(default)]
+    pub force: bool
+}
@@ -0,0 +1,17 @@
+use chrono_tz::Tz;
+use serde::Deserialize;
+use validator::{Validate, ValidationError};
+
+#[derive(Deserialize, Validate)]
+pub struct UpdateTimeZone {
+    /// IANA name such as `Europe/Berlin`.
+    #[validate(custom = "validate_time_zone")]
+    pub time_zone: String
+}
+
+pub fn validate_time_zone(value: &str) -> Result<(), ValidationError> {
+    match value.parse::<Tz>() {
+        Ok(_) => Ok(()),
+        Err(_) => Err(ValidationError::new("Unknown time zone"))
+    }
+}
@@ -0,0 +1,15 @@
+use serde::Deserialize;
+use validator::Validate;
+
+#[derive(Deserialize, Validate)]
+pub struct UserSearch {
+    /// Part of the email address to look for.
+    pub q: Option<String>,
+
+    /// Capped so the number of results to skip can't overflow.
+    #[validate(range(min = 1, max = 10000))]
+    pub page: Option<u64>,
+
+    #[validate(range(min = 1, max = 100))]
+    pub per_page: Option<i64>
+}
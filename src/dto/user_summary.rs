@@ -0,0 +1,31 @@
+use serde::Serialize;
+
+use crate::model::user_model::{Role, User};
+
+/// What admins get to see of an account. Never contains password or MFA secrets.
+#[derive(Serialize)]
+pub struct UserSummary {
+    pub id: String,
+    pub email: String,
+    pub role: Role,
+    pub verified: bool,
+    pub disabled: bool,
+    pub mfa_enabled: bool,
+    pub password_reset_required: bool,
+    pub task_count: i64,
+}
+
+impl UserSummary {
+    pub fn new(user: User, task_count: i64) -> Self {
+        UserSummary {
+            id: user.id.unwrap().to_string(),
+            email: user.email,
+            role: user.role,
+            verified: user.verified,
+            disabled: user.disabled,
+            mfa_enabled: user.mfa.enabled,
+            password_reset_required: user.password_reset_required,
+            task_count,
+        }
+    }
+}
@@ -6,12 +6,40 @@ use dotenv::dotenv;
 
 use repository::user_repository::UserRepository;
 
-use crate::api::auth_api::{sign_in, sign_up};
-use crate::api::task_api::{create_task, delete_task, get_all_tasks_for_user, get_task, update_task_status};
-use crate::api::user_api::{delete_user, update_user};
+use crate::api::access_token_api::{create_access_token, delete_access_token, get_access_tokens,
+                                   update_access_token};
+use crate::api::admin_api::{disable_user, enable_user, force_password_reset, get_outbox, get_user, get_users,
+                            set_user_role};
+use crate::api::auth_api::{forgot_password, logout, logout_all, refresh, resend_verification, reset_password,
+                           sign_in, sign_in_mfa, sign_up, verify_email};
+use crate::api::checklist_api::{add_checklist_item, delete_checklist_item, reorder_checklist,
+                                 update_checklist_item};
+use crate::api::dependency_api::{add_blocker, get_dependencies, remove_blocker};
+use crate::api::jwks_api::jwks;
+use crate::api::mfa_api::{confirm_mfa, disable_mfa, enroll_mfa, regenerate_recovery_codes};
+use crate::api::notification_api::{confirm_unsubscribe, get_notification_settings, unsubscribe,
+                                   update_notification_settings};
+use crate::api::oidc_api::{oidc_callback, oidc_login};
+use crate::api::tag_api::{add_task_tags, get_tags, remove_task_tag, rename_tag};
+use crate::api::task_api::{create_task, delete_task, get_all_tasks_for_user, get_task, search_tasks, update_task,
+                           update_task_status};
+use crate::api::user_api::{delete_user, get_mails, get_reminder_settings, update_password, update_reminder_settings,
+                           update_time_zone, update_user};
+use crate::repository::access_token_repository::AccessTokenRepository;
+use crate::repository::login_attempt_repository::LoginAttemptRepository;
+use crate::repository::oidc_login_repository::OidcLoginRepository;
+use crate::repository::one_time_token_repository::OneTimeTokenRepository;
+use crate::repository::rate_limit_repository::RateLimitRepository;
+use crate::repository::refresh_token_repository::RefreshTokenRepository;
+use crate::repository::revoked_token_repository::RevokedTokenRepository;
 use crate::repository::task_repository::TaskRepository;
-use crate::service::email_service::morning_email_scheduler;
-use crate::validator::request_validators::jwt_validator;
+use crate::service::mailer_service::Mailer;
+use crate::service::oidc_service::{OidcClient, OidcConfig};
+use crate::service::outbox_service::{outbox_worker, Outbox};
+use crate::service::scheduler_service::{notification_scheduler, Scheduler};
+use crate::service::signing_key_service::SigningKeys;
+use crate::service::unsubscribe_service::UnsubscribeLinks;
+use crate::validator::request_validators::{admin_guard, jwt_validator};
 
 mod api;
 mod model;
@@ -35,8 +63,42 @@ async fn main() -> std::io::Result<()> {
     let task_repo = TaskRepository::init().await;
     let task_data = Data::new(task_repo);
 
-    // start scheduler on a different thread
-    tokio::spawn(morning_email_scheduler(user_data.clone(), task_data.clone()));
+    let refresh_token_repo = RefreshTokenRepository::init().await;
+    let refresh_token_data = Data::new(refresh_token_repo);
+
+    let revoked_token_repo = RevokedTokenRepository::init().await;
+    let revoked_token_data = Data::new(revoked_token_repo);
+
+    let one_time_token_repo = OneTimeTokenRepository::init().await;
+    let one_time_token_data = Data::new(one_time_token_repo);
+
+    let login_attempt_repo = LoginAttemptRepository::init().await;
+    let login_attempt_data = Data::new(login_attempt_repo);
+
+    let rate_limit_repo = RateLimitRepository::init().await;
+    let rate_limit_data = Data::new(rate_limit_repo);
+
+    let access_token_repo = AccessTokenRepository::init().await;
+    let access_token_data = Data::new(access_token_repo);
+
+    let signing_keys_data = Data::new(SigningKeys::load());
+
+    let oidc_login_repo = OidcLoginRepository::init().await;
+    let oidc_login_data = Data::new(oidc_login_repo);
+
+    // single sign-on is optional, the routes only exist when a provider is configured
+    let oidc_client_data = OidcConfig::from_env().map(|config| Data::new(OidcClient::new(config)));
+
+    let mailer_data = Data::new(Mailer::from_env());
+    let unsubscribe_links = UnsubscribeLinks::from_env();
+    let outbox_data = Data::new(Outbox::init(unsubscribe_links.clone()).await);
+    let unsubscribe_links_data = Data::new(unsubscribe_links);
+    let scheduler_data = Data::new(Scheduler::default());
+
+    // start scheduler and outbox worker on different threads
+    tokio::spawn(notification_scheduler(user_data.clone(), task_data.clone(), outbox_data.clone(),
+                                        scheduler_data.clone()));
+    tokio::spawn(outbox_worker(outbox_data.clone(), mailer_data.clone()));
 
     HttpServer::new(move || {
         let bearer_middleware = HttpAuthentication::bearer(jwt_validator);
@@ -44,18 +106,89 @@ async fn main() -> std::io::Result<()> {
             .wrap(Logger::default())
             .app_data(user_data.clone())
             .app_data(task_data.clone())
+            .app_data(refresh_token_data.clone())
+            .app_data(revoked_token_data.clone())
+            .app_data(one_time_token_data.clone())
+            .app_data(login_attempt_data.clone())
+            .app_data(rate_limit_data.clone())
+            .app_data(access_token_data.clone())
+            .app_data(oidc_login_data.clone())
+            .app_data(mailer_data.clone())
+            .app_data(outbox_data.clone())
+            .app_data(unsubscribe_links_data.clone())
+            .app_data(scheduler_data.clone())
+            .app_data(signing_keys_data.clone())
+            .configure(|cfg| {
+                if let Some(oidc_client_data) = &oidc_client_data {
+                    cfg.app_data(oidc_client_data.clone())
+                        .service(oidc_login)
+                        .service(oidc_callback);
+                }
+            })
+            .service(jwks)
             .service(sign_up)
             .service(sign_in)
+            .service(sign_in_mfa)
+            .service(refresh)
+            .service(forgot_password)
+            .service(reset_password)
+            .service(verify_email)
+            .service(resend_verification)
+            .service(confirm_unsubscribe)
+            .service(unsubscribe)
             .service(
                 web::scope("")
                     .wrap(bearer_middleware)
+                    .service(logout)
+                    .service(logout_all)
                     .service(update_user)
+                    .service(update_password)
+                    .service(update_time_zone)
+                    .service(get_reminder_settings)
+                    .service(update_reminder_settings)
+                    .service(get_notification_settings)
+                    .service(update_notification_settings)
+                    .service(get_mails)
+                    .service(enroll_mfa)
+                    .service(confirm_mfa)
+                    .service(regenerate_recovery_codes)
+                    .service(disable_mfa)
+                    .service(create_access_token)
+                    .service(get_access_tokens)
+                    .service(update_access_token)
+                    .service(delete_access_token)
                     .service(delete_user)
                     .service(create_task)
+                    // these have to come before get_task, which would take "search", "tags" or
+                    // "dependencies" for an id
+                    .service(search_tasks)
+                    .service(get_tags)
+                    .service(get_dependencies)
                     .service(get_task)
                     .service(get_all_tasks_for_user)
                     .service(delete_task)
                     .service(update_task_status)
+                    .service(update_task)
+                    .service(rename_tag)
+                    .service(add_task_tags)
+                    .service(remove_task_tag)
+                    .service(add_checklist_item)
+                    .service(reorder_checklist)
+                    .service(update_checklist_item)
+                    .service(delete_checklist_item)
+                    .service(add_blocker)
+                    .service(remove_blocker)
+                    .service(
+                        web::scope("/admin")
+                            .wrap_fn(admin_guard)
+                            .service(get_users)
+                            .service(get_user)
+                            .service(disable_user)
+                            .service(enable_user)
+                            .service(set_user_role)
+                            .service(force_password_reset)
+                            .service(get_outbox)
+                    )
             )
     })
         .bind(("127.0.0.1", 8080))?
@@ -0,0 +1,15 @@
+use mongodb::bson::DateTime;
+use serde::{Deserialize, Serialize};
+
+/// Failed sign-in attempts for one account or one client address, keyed by
+/// `account:<email>` or `ip:<address>`.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct LoginAttempt {
+    #[serde(rename = "_id")]
+    pub key: String,
+    pub failures: i32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub locked_until: Option<DateTime>,
+    pub last_failure_at: DateTime,
+    pub expires_at: DateTime,
+}
@@ -1,2 +1,10 @@
 pub mod user_model;
-pub mod task_model;
\ No newline at end of file
+pub mod task_model;
+pub mod refresh_token_model;
+pub mod revoked_token_model;
+pub mod one_time_token_model;
+pub mod login_attempt_model;
+pub mod rate_limit_model;
+pub mod access_token_model;
+pub mod oidc_login_model;
+pub mod outbox_model;
@@ -0,0 +1,12 @@
+use mongodb::bson::DateTime;
+use serde::{Deserialize, Serialize};
+
+/// Pending authorization-code login, keyed by the `state` sent to the identity provider.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct OidcLogin {
+    #[serde(rename = "_id")]
+    pub state: String,
+    pub nonce: String,
+    pub code_verifier: String,
+    pub expires_at: DateTime,
+}
@@ -0,0 +1,25 @@
+use mongodb::bson::DateTime;
+use mongodb::bson::oid::ObjectId;
+use serde::{Deserialize, Serialize};
+use strum_macros::Display;
+
+/// Single-use token that is mailed to a user. Only the hash of the token is stored.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct OneTimeToken {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+    pub user_id: ObjectId,
+    pub purpose: TokenPurpose,
+    pub token_hash: String,
+    /// Address being confirmed, for email verification tokens.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub email: Option<String>,
+    pub created_at: DateTime,
+    pub expires_at: DateTime,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Display)]
+pub enum TokenPurpose {
+    PasswordReset,
+    EmailVerification,
+}
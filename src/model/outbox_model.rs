@@ -0,0 +1,73 @@
+use mongodb::bson::{DateTime, Document};
+use mongodb::bson::oid::ObjectId;
+use serde::{Deserialize, Serialize};
+use strum_macros::Display;
+
+use crate::model::user_model::NotificationKind;
+
+/// A mail waiting in the outbox or already handled by the outbox worker. The body is
+/// rendered from `template` and `data` when the mail is sent, so retries pick up fixed
+/// templates as well. `data` may hold one-time links and is removed once the mail has
+/// been sent or given up on.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct OutboxMessage {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+    /// Missing for mails that don't belong to an account yet.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub user_id: Option<ObjectId>,
+    pub kind: MailKind,
+    pub to: String,
+    pub subject: String,
+    pub template: String,
+    #[serde(default)]
+    pub data: Document,
+    /// Signed link that turns this kind of notification off, sent as `List-Unsubscribe`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub unsubscribe_url: Option<String>,
+    pub status: DeliveryStatus,
+    /// Delivery attempts started so far.
+    pub attempts: u32,
+    /// When the worker picks the mail up next. While `Sending` this is when the attempt
+    /// counts as lost, so mails of a crashed worker are retried.
+    pub next_attempt_at: DateTime,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_error: Option<String>,
+    pub created_at: DateTime,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sent_at: Option<DateTime>,
+    /// When the one-time link in an account mail stops working. The mail is dropped
+    /// instead of sent after that.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expires_at: Option<DateTime>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Display)]
+pub enum MailKind {
+    Digest,
+    Reminder,
+    OverdueAlert,
+    PasswordReset,
+    EmailVerification,
+}
+
+impl MailKind {
+    /// The setting that turns this kind of mail off, `None` for account mails.
+    pub fn notification(&self) -> Option<NotificationKind> {
+        match self {
+            MailKind::Digest => Some(NotificationKind::Digest),
+            MailKind::Reminder => Some(NotificationKind::Reminders),
+            MailKind::OverdueAlert => Some(NotificationKind::OverdueAlerts),
+            MailKind::PasswordReset | MailKind::EmailVerification => None,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Display)]
+pub enum DeliveryStatus {
+    Pending,
+    Sending,
+    Sent,
+    /// Gave up after too many attempts or a permanent failure.
+    Dead,
+}
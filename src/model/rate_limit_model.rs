@@ -0,0 +1,11 @@
+use mongodb::bson::DateTime;
+use serde::{Deserialize, Serialize};
+
+/// Request counter for one client within one fixed time window.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct RateLimitBucket {
+    #[serde(rename = "_id")]
+    pub key: String,
+    pub count: i32,
+    pub expires_at: DateTime,
+}
@@ -0,0 +1,18 @@
+use mongodb::bson::DateTime;
+use mongodb::bson::oid::ObjectId;
+use serde::{Deserialize, Serialize};
+
+/// Server-side record of an issued refresh token. Only the hash of the token is stored.
+/// Every token created through rotation shares the `family_id` of the token it replaced.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct RefreshToken {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+    pub user_id: ObjectId,
+    pub family_id: String,
+    pub token_hash: String,
+    pub used: bool,
+    pub revoked: bool,
+    pub created_at: DateTime,
+    pub expires_at: DateTime,
+}
@@ -0,0 +1,13 @@
+use mongodb::bson::DateTime;
+use mongodb::bson::oid::ObjectId;
+use serde::{Deserialize, Serialize};
+
+/// Access token that was revoked before its expiry, keyed by the token's `jti`.
+/// The entry is only needed until the token would have expired anyway.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct RevokedToken {
+    #[serde(rename = "_id")]
+    pub jti: String,
+    pub user_id: ObjectId,
+    pub expires_at: DateTime,
+}
@@ -1,9 +1,10 @@
-use chrono::NaiveDate;
+use chrono::{Datelike, NaiveDate, NaiveTime, Weekday};
+use mongodb::bson::DateTime;
 use mongodb::bson::oid::ObjectId;
 use serde::{Deserialize, Serialize};
 use strum_macros::{Display};
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Task {
     #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
     pub id: Option<ObjectId>,
@@ -11,12 +12,152 @@ pub struct Task {
     pub title: String,
     pub description: String,
     pub status: TaskStatus,
+    /// Day the task is due on in the user's zone.
     pub due_date: NaiveDate,
+    /// Time of day in the user's zone; the task is due at the end of the day without one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub due_time: Option<NaiveTime>,
+    /// `due_date` and `due_time` as an instant, see `due_instant`.
+    pub due_at: DateTime,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub remind_before_hours: Option<u32>,
+    /// When the reminder goes out; removed once it is sent.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reminder_at: Option<DateTime>,
+    /// When the next overdue alert goes out, see `next_overdue_alert`; removed after the last.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub overdue_alert_at: Option<DateTime>,
+    #[serde(default)]
+    pub priority: Priority,
+    /// `priority.rank()`, stored so tasks can be sorted by priority.
+    #[serde(default = "Priority::default_rank")]
+    pub priority_rank: i32,
+    /// Normalized, lowercase labels; see `normalize_tags`.
+    #[serde(default)]
+    pub tags: Vec<String>,
+    #[serde(default)]
+    pub checklist: Vec<ChecklistItem>,
+    /// Move the task to `Done` as soon as the last checklist item is checked.
+    #[serde(default)]
+    pub auto_complete: bool,
+    /// Tasks of the same user that have to be done before this one can be worked on.
+    #[serde(default)]
+    pub blocked_by: Vec<ObjectId>,
+    /// Repeats the task; the next occurrence is created when this one is done.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub recurrence: Option<Recurrence>,
+    /// The occurrence of the series this task follows. It is unique, so the series
+    /// continues only once from each occurrence.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub previous_occurrence: Option<ObjectId>,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone, Display)]
+impl Task {
+    /// Number of checked items and of all items on the checklist.
+    pub fn checklist_progress(&self) -> (usize, usize) {
+        let done = self.checklist.iter().filter(|item| item.done).count();
+        (done, self.checklist.len())
+    }
+
+    pub fn has_open_checklist_items(&self) -> bool {
+        self.checklist.iter().any(|item| !item.done)
+    }
+}
+
+/// The parts of a task needed to look at the dependencies between tasks.
+#[derive(Deserialize, Debug)]
+pub struct TaskNode {
+    #[serde(rename = "_id")]
+    pub id: ObjectId,
+    pub title: String,
+    pub status: TaskStatus,
+    #[serde(default)]
+    pub blocked_by: Vec<ObjectId>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ChecklistItem {
+    pub id: ObjectId,
+    pub text: String,
+    pub done: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Display)]
 pub enum TaskStatus {
     ToDo,
     InProgress,
     Done,
 }
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Default, Display)]
+pub enum Priority {
+    Low,
+    #[default]
+    Medium,
+    High,
+    Urgent,
+}
+
+impl Priority {
+    /// Higher ranks are more urgent.
+    pub fn rank(&self) -> i32 {
+        match self {
+            Priority::Low => 0,
+            Priority::Medium => 1,
+            Priority::High => 2,
+            Priority::Urgent => 3,
+        }
+    }
+
+    fn default_rank() -> i32 {
+        Priority::default().rank()
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum Frequency {
+    Daily,
+    Weekly,
+    Monthly,
+    Yearly,
+}
+
+/// A subset of an iCalendar RRULE, counted from the due date of the task it belongs to.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Recurrence {
+    pub frequency: Frequency,
+    /// Repeat every `interval` days, weeks, months or years.
+    #[serde(default = "Recurrence::default_interval")]
+    pub interval: u32,
+    /// Days a weekly task repeats on, the weekday of the due date if left out.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub weekdays: Vec<Weekday>,
+    /// Day a monthly or yearly task repeats on, the day of the due date if left out. Months
+    /// that are too short use their last day.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub month_day: Option<u32>,
+    /// Occurrences left, this one included.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub count: Option<u32>,
+    /// Last day an occurrence can be due on.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub until: Option<NaiveDate>,
+}
+
+impl Recurrence {
+    /// Fills in what the rule leaves to the due date, so later occurrences don't drift
+    /// after a month that is too short for the day they fall on.
+    pub fn anchored(&self, due_date: NaiveDate) -> Recurrence {
+        let mut rule = self.clone();
+        match rule.frequency {
+            Frequency::Weekly if rule.weekdays.is_empty() => rule.weekdays.push(due_date.weekday()),
+            Frequency::Monthly | Frequency::Yearly if rule.month_day.is_none() => rule.month_day = Some(due_date.day()),
+            _ => (),
+        }
+        rule
+    }
+
+    fn default_interval() -> u32 {
+        1
+    }
+}
@@ -1,10 +1,134 @@
+use chrono::{NaiveTime, Weekday};
+use mongodb::bson::DateTime;
 use mongodb::bson::oid::ObjectId;
 use serde::{Deserialize, Serialize};
+use strum_macros::Display;
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
 pub struct User {
     #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
     pub id: Option<ObjectId>,
     pub email: String,
     pub password: String,
+    #[serde(default)]
+    pub verified: bool,
+    /// New address the user asked to switch to; it only replaces `email` once confirmed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pending_email: Option<String>,
+    #[serde(default)]
+    pub mfa: MfaSettings,
+    #[serde(default)]
+    pub role: Role,
+    /// Disabled accounts can neither sign in nor use existing tokens.
+    #[serde(default)]
+    pub disabled: bool,
+    /// Set by an admin; the user has to go through the password reset before signing in
+    /// or using existing tokens again.
+    #[serde(default)]
+    pub password_reset_required: bool,
+    /// External account the user signs in with through OpenID Connect.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub oidc: Option<OidcIdentity>,
+    /// IANA name of the zone due dates are in; the server default if not set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub time_zone: Option<String>,
+    #[serde(default)]
+    pub reminders: ReminderSettings,
+    /// When the scheduler sends the next digest; computed from `reminders` once the user
+    /// is picked up by the scheduler.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub next_digest_at: Option<DateTime>,
+    #[serde(default)]
+    pub notifications: NotificationSettings,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum DigestFrequency {
+    Daily,
+    Weekly,
+}
+
+/// When the user gets the digest of upcoming tasks and what it covers.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ReminderSettings {
+    /// Local time the digest is sent at.
+    pub digest_time: NaiveTime,
+    /// Days the digest goes out on; exactly one for a weekly digest.
+    pub digest_days: Vec<Weekday>,
+    pub digest_frequency: DigestFrequency,
+    /// Tasks due up to this many days after the digest day are included too. A weekly
+    /// digest always covers at least the coming week.
+    pub days_ahead: u32,
+}
+
+impl Default for ReminderSettings {
+    fn default() -> Self {
+        ReminderSettings {
+            digest_time: NaiveTime::from_hms_opt(6, 0, 0).unwrap(),
+            digest_days: vec![Weekday::Mon, Weekday::Tue, Weekday::Wed, Weekday::Thu, Weekday::Fri,
+                              Weekday::Sat, Weekday::Sun],
+            digest_frequency: DigestFrequency::Daily,
+            days_ahead: 0,
+        }
+    }
+}
+
+/// Which notification mails the user gets. Account mails, like password resets, are
+/// always sent.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct NotificationSettings {
+    pub digest: bool,
+    pub overdue_alerts: bool,
+    pub reminders: bool,
+}
+
+impl Default for NotificationSettings {
+    fn default() -> Self {
+        NotificationSettings {
+            digest: true,
+            overdue_alerts: true,
+            reminders: true,
+        }
+    }
+}
+
+/// A kind of notification mail the user can turn off; named like its flag in
+/// `NotificationSettings`.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Display)]
+#[serde(rename_all = "snake_case")]
+#[strum(serialize_all = "snake_case")]
+pub enum NotificationKind {
+    Digest,
+    OverdueAlerts,
+    Reminders,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct OidcIdentity {
+    pub issuer: String,
+    pub subject: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Default, Display)]
+pub enum Role {
+    #[default]
+    User,
+    Admin,
+}
+
+/// TOTP second factor of a user. Recovery codes are stored hashed.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct MfaSettings {
+    pub enabled: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub secret: Option<String>,
+    /// Secret handed out by enrollment that still waits for a confirming code.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pending_secret: Option<String>,
+    #[serde(default)]
+    pub recovery_codes: Vec<String>,
+    /// Last accepted TOTP time step, so a code can't be used twice.
+    #[serde(default)]
+    pub last_step: i64,
 }
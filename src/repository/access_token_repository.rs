@@ -0,0 +1,103 @@
+use std::env;
+use std::time::Duration;
+
+use futures::TryStreamExt;
+use mongodb::{Client, Collection, IndexModel};
+use mongodb::bson::{DateTime, doc};
+use mongodb::bson::oid::ObjectId;
+use mongodb::error::Error as MongoError;
+use mongodb::options::{FindOneAndUpdateOptions, IndexOptions, ReturnDocument};
+use mongodb::results::{DeleteResult, InsertOneResult, UpdateResult};
+
+use crate::model::access_token_model::PersonalAccessToken;
+
+pub struct AccessTokenRepository {
+    col: Collection<PersonalAccessToken>,
+}
+
+impl AccessTokenRepository {
+    pub async fn init() -> Self {
+        let uri = match env::var("MONGO_URI") {
+            Ok(variable) => variable.to_string(),
+            Err(_) => "Error loading env variable".to_string(),
+        };
+
+        let client = Client::with_uri_str(uri).await.unwrap();
+        let db = client.database("rust-actix");
+        let col: Collection<PersonalAccessToken> = db.collection("PersonalAccessToken");
+
+        // tokens without an expiry have no `expires_at` and are never removed
+        let expiry_index = IndexModel::builder()
+            .keys(doc! { "expires_at": 1 })
+            .options(IndexOptions::builder().expire_after(Duration::from_secs(0)).build())
+            .build();
+        let hash_index = IndexModel::builder()
+            .keys(doc! { "token_hash": 1 })
+            .options(IndexOptions::builder().unique(true).build())
+            .build();
+        col.create_indexes(vec![expiry_index, hash_index], None).await.unwrap();
+
+        AccessTokenRepository { col }
+    }
+
+    pub async fn create_token(&self, token: &PersonalAccessToken) -> Result<InsertOneResult, MongoError> {
+        let result = self.col.insert_one(token, None).await?;
+
+        Ok(result)
+    }
+
+    pub async fn find_by_hash(&self, token_hash: &str) -> Result<Option<PersonalAccessToken>, MongoError> {
+        let filter = doc! { "token_hash": token_hash };
+        self.col.find_one(filter, None).await
+    }
+
+    pub async fn find_all_for_user(&self, user_id: &ObjectId) -> Result<Vec<PersonalAccessToken>, MongoError> {
+        let filter = doc! { "user_id": user_id };
+        let cursor = self.col.find(filter, None).await?;
+
+        cursor.try_collect().await
+    }
+
+    pub async fn rename(&self, id: &ObjectId, user_id: &ObjectId,
+                        name: &str) -> Result<Option<PersonalAccessToken>, MongoError> {
+        let filter = doc! {
+            "_id": id,
+            "user_id": user_id
+        };
+        let update = doc! {
+            "$set": {
+                "name": name
+            }
+        };
+        let options = FindOneAndUpdateOptions::builder()
+            .return_document(ReturnDocument::After)
+            .build();
+
+        self.col.find_one_and_update(filter, update, options).await
+    }
+
+    pub async fn touch(&self, id: &ObjectId) -> Result<UpdateResult, MongoError> {
+        let filter = doc! { "_id": id };
+        let update = doc! {
+            "$set": {
+                "last_used_at": DateTime::now()
+            }
+        };
+
+        self.col.update_one(filter, update, None).await
+    }
+
+    pub async fn delete(&self, id: &ObjectId, user_id: &ObjectId) -> Result<DeleteResult, MongoError> {
+        let filter = doc! {
+            "_id": id,
+            "user_id": user_id
+        };
+
+        self.col.delete_one(filter, None).await
+    }
+
+    pub async fn delete_all_for_user(&self, user_id: &ObjectId) -> Result<DeleteResult, MongoError> {
+        let filter = doc! { "user_id": user_id };
+        self.col.delete_many(filter, None).await
+    }
+}
@@ -0,0 +1,77 @@
+use std::env;
+use std::time::Duration;
+
+use mongodb::{Client, Collection, IndexModel};
+use mongodb::bson::{DateTime, doc};
+use mongodb::error::Error as MongoError;
+use mongodb::options::{FindOneAndUpdateOptions, IndexOptions, ReturnDocument};
+use mongodb::results::{DeleteResult, UpdateResult};
+
+use crate::model::login_attempt_model::LoginAttempt;
+
+pub struct LoginAttemptRepository {
+    col: Collection<LoginAttempt>,
+}
+
+impl LoginAttemptRepository {
+    pub async fn init() -> Self {
+        let uri = match env::var("MONGO_URI") {
+            Ok(variable) => variable.to_string(),
+            Err(_) => "Error loading env variable".to_string(),
+        };
+
+        let client = Client::with_uri_str(uri).await.unwrap();
+        let db = client.database("rust-actix");
+        let col: Collection<LoginAttempt> = db.collection("LoginAttempt");
+
+        // counters are forgotten some time after the last failure
+        let expiry_index = IndexModel::builder()
+            .keys(doc! { "expires_at": 1 })
+            .options(IndexOptions::builder().expire_after(Duration::from_secs(0)).build())
+            .build();
+        col.create_index(expiry_index, None).await.unwrap();
+
+        LoginAttemptRepository { col }
+    }
+
+    pub async fn find(&self, key: &str) -> Result<Option<LoginAttempt>, MongoError> {
+        let filter = doc! { "_id": key };
+        self.col.find_one(filter, None).await
+    }
+
+    /// Increments the failure counter of the key and returns the updated record.
+    pub async fn record_failure(&self, key: &str, expires_at: DateTime) -> Result<Option<LoginAttempt>, MongoError> {
+        let filter = doc! { "_id": key };
+        let update = doc! {
+            "$inc": {
+                "failures": 1
+            },
+            "$set": {
+                "last_failure_at": DateTime::now(),
+                "expires_at": expires_at
+            }
+        };
+        let options = FindOneAndUpdateOptions::builder()
+            .upsert(true)
+            .return_document(ReturnDocument::After)
+            .build();
+
+        self.col.find_one_and_update(filter, update, options).await
+    }
+
+    pub async fn lock(&self, key: &str, locked_until: DateTime) -> Result<UpdateResult, MongoError> {
+        let filter = doc! { "_id": key };
+        let update = doc! {
+            "$set": {
+                "locked_until": locked_until
+            }
+        };
+
+        self.col.update_one(filter, update, None).await
+    }
+
+    pub async fn clear(&self, key: &str) -> Result<DeleteResult, MongoError> {
+        let filter = doc! { "_id": key };
+        self.col.delete_one(filter, None).await
+    }
+}
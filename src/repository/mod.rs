@@ -1,2 +1,29 @@
+use mongodb::error::{Error as MongoError, ErrorKind, WriteFailure};
+
 pub mod user_repository;
 pub mod task_repository;
+pub mod refresh_token_repository;
+pub mod revoked_token_repository;
+pub mod one_time_token_repository;
+pub mod login_attempt_repository;
+pub mod rate_limit_repository;
+pub mod access_token_repository;
+pub mod oidc_login_repository;
+pub mod outbox_repository;
+
+/// Whether the write failed because it would have broken a unique index.
+pub fn is_duplicate_key(error: &MongoError) -> bool {
+    matches!(&*error.kind, ErrorKind::Write(WriteFailure::WriteError(e)) if e.code == 11000)
+}
+
+/// Escapes a user supplied string so it can be embedded in a `$regex` literally.
+pub fn regex_escape(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        if "\\.+*?()|[]{}^$#&-~".contains(c) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
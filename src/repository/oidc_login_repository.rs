@@ -0,0 +1,53 @@
+use std::env;
+use std::time::Duration;
+
+use mongodb::{Client, Collection, IndexModel};
+use mongodb::bson::{DateTime, doc};
+use mongodb::error::Error as MongoError;
+use mongodb::options::IndexOptions;
+use mongodb::results::InsertOneResult;
+
+use crate::model::oidc_login_model::OidcLogin;
+
+pub struct OidcLoginRepository {
+    col: Collection<OidcLogin>,
+}
+
+impl OidcLoginRepository {
+    pub async fn init() -> Self {
+        let uri = match env::var("MONGO_URI") {
+            Ok(variable) => variable.to_string(),
+            Err(_) => "Error loading env variable".to_string(),
+        };
+
+        let client = Client::with_uri_str(uri).await.unwrap();
+        let db = client.database("rust-actix");
+        let col: Collection<OidcLogin> = db.collection("OidcLogin");
+
+        let expiry_index = IndexModel::builder()
+            .keys(doc! { "expires_at": 1 })
+            .options(IndexOptions::builder().expire_after(Duration::from_secs(0)).build())
+            .build();
+        col.create_index(expiry_index, None).await.unwrap();
+
+        OidcLoginRepository { col }
+    }
+
+    pub async fn create(&self, login: OidcLogin) -> Result<InsertOneResult, MongoError> {
+        let result = self.col.insert_one(login, None).await?;
+
+        Ok(result)
+    }
+
+    /// Removes and returns the pending login, so every `state` can only be used once.
+    pub async fn consume(&self, state: &str) -> Result<Option<OidcLogin>, MongoError> {
+        let filter = doc! {
+            "_id": state,
+            "expires_at": {
+                "$gt": DateTime::now()
+            }
+        };
+
+        self.col.find_one_and_delete(filter, None).await
+    }
+}
@@ -0,0 +1,69 @@
+use std::env;
+use std::time::Duration;
+
+use mongodb::{Client, Collection, IndexModel};
+use mongodb::bson::{DateTime, doc};
+use mongodb::bson::oid::ObjectId;
+use mongodb::error::Error as MongoError;
+use mongodb::options::IndexOptions;
+use mongodb::results::{DeleteResult, InsertOneResult};
+
+use crate::model::one_time_token_model::{OneTimeToken, TokenPurpose};
+
+pub struct OneTimeTokenRepository {
+    col: Collection<OneTimeToken>,
+}
+
+impl OneTimeTokenRepository {
+    pub async fn init() -> Self {
+        let uri = match env::var("MONGO_URI") {
+            Ok(variable) => variable.to_string(),
+            Err(_) => "Error loading env variable".to_string(),
+        };
+
+        let client = Client::with_uri_str(uri).await.unwrap();
+        let db = client.database("rust-actix");
+        let col: Collection<OneTimeToken> = db.collection("OneTimeToken");
+
+        let expiry_index = IndexModel::builder()
+            .keys(doc! { "expires_at": 1 })
+            .options(IndexOptions::builder().expire_after(Duration::from_secs(0)).build())
+            .build();
+        let hash_index = IndexModel::builder()
+            .keys(doc! { "token_hash": 1 })
+            .options(IndexOptions::builder().unique(true).build())
+            .build();
+        col.create_indexes(vec![expiry_index, hash_index], None).await.unwrap();
+
+        OneTimeTokenRepository { col }
+    }
+
+    pub async fn create(&self, token: OneTimeToken) -> Result<InsertOneResult, MongoError> {
+        let result = self.col.insert_one(token, None).await?;
+
+        Ok(result)
+    }
+
+    /// Removes and returns the token if it exists and has not expired yet. The TTL monitor
+    /// only runs once a minute, so expiry is checked here as well.
+    pub async fn consume(&self, token_hash: &str, purpose: &TokenPurpose) -> Result<Option<OneTimeToken>, MongoError> {
+        let filter = doc! {
+            "token_hash": token_hash,
+            "purpose": purpose.to_string(),
+            "expires_at": {
+                "$gt": DateTime::now()
+            }
+        };
+
+        self.col.find_one_and_delete(filter, None).await
+    }
+
+    pub async fn delete_for_user(&self, user_id: &ObjectId, purpose: &TokenPurpose) -> Result<DeleteResult, MongoError> {
+        let filter = doc! {
+            "user_id": user_id,
+            "purpose": purpose.to_string()
+        };
+
+        self.col.delete_many(filter, None).await
+    }
+}
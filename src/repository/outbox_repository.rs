@@ -0,0 +1,197 @@
+use std::env;
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use futures::TryStreamExt;
+use mongodb::{Client, Collection, IndexModel};
+use mongodb::bson::{self, doc, Document};
+use mongodb::bson::oid::ObjectId;
+use mongodb::error::Error as MongoError;
+use mongodb::options::{FindOneAndUpdateOptions, FindOptions, IndexOptions, ReturnDocument};
+use mongodb::results::{InsertOneResult, UpdateResult};
+
+use crate::model::outbox_model::{DeliveryStatus, OutboxMessage};
+
+/// How long sent mails stay in the delivery log.
+const SENT_RETENTION: Duration = Duration::from_secs(30 * 24 * 60 * 60);
+
+pub struct OutboxRepository {
+    col: Collection<OutboxMessage>,
+}
+
+impl OutboxRepository {
+    pub async fn init() -> Self {
+        let uri = match env::var("MONGO_URI") {
+            Ok(variable) => variable.to_string(),
+            Err(_) => "Error loading env variable".to_string(),
+        };
+
+        let client = Client::with_uri_str(uri).await.unwrap();
+        let db = client.database("rust-actix");
+        let col: Collection<OutboxMessage> = db.collection("Outbox");
+
+        let due_index = IndexModel::builder()
+            .keys(doc! { "status": 1, "next_attempt_at": 1 })
+            .build();
+        let user_index = IndexModel::builder()
+            .keys(doc! { "user_id": 1, "created_at": -1 })
+            .build();
+        // only sent mails have sent_at, pending and dead ones are kept
+        let retention_index = IndexModel::builder()
+            .keys(doc! { "sent_at": 1 })
+            .options(IndexOptions::builder().expire_after(SENT_RETENTION).build())
+            .build();
+        col.create_indexes(vec![due_index, user_index, retention_index], None).await.unwrap();
+
+        OutboxRepository { col }
+    }
+
+    pub async fn enqueue(&self, message: OutboxMessage) -> Result<InsertOneResult, MongoError> {
+        let result = self.col.insert_one(message, None).await?;
+
+        Ok(result)
+    }
+
+    /// Takes the mail that has been due the longest and marks it as being sent until
+    /// `lease_until`. Mails that had `max_attempts` already or whose link expired are left
+    /// for `abandon_stale`. Returns `None` when nothing is due.
+    pub async fn claim_due(&self, now: DateTime<Utc>, lease_until: DateTime<Utc>,
+                           max_attempts: u32) -> Result<Option<OutboxMessage>, MongoError> {
+        let now = bson::DateTime::from_chrono(now);
+        let filter = doc! {
+            "status": { "$in": [DeliveryStatus::Pending.to_string(), DeliveryStatus::Sending.to_string()] },
+            "next_attempt_at": { "$lte": now },
+            "attempts": { "$lt": max_attempts },
+            "$or": [
+                { "expires_at": { "$exists": false } },
+                { "expires_at": { "$gt": now } }
+            ]
+        };
+        let new_doc = doc! {
+            "$set": {
+                "status": DeliveryStatus::Sending.to_string(),
+                "next_attempt_at": bson::DateTime::from_chrono(lease_until)
+            },
+            "$inc": {
+                "attempts": 1
+            }
+        };
+        let options = FindOneAndUpdateOptions::builder()
+            .sort(doc! { "next_attempt_at": 1 })
+            .return_document(ReturnDocument::After)
+            .build();
+
+        self.col.find_one_and_update(filter, new_doc, options).await
+    }
+
+    pub async fn mark_sent(&self, message: &OutboxMessage, now: DateTime<Utc>) -> Result<UpdateResult, MongoError> {
+        let new_doc = doc! {
+            "$set": {
+                "status": DeliveryStatus::Sent.to_string(),
+                "sent_at": bson::DateTime::from_chrono(now)
+            },
+            "$unset": {
+                "last_error": "",
+                "data": ""
+            }
+        };
+
+        self.col.update_one(claimed(message), new_doc, None).await
+    }
+
+    /// Records the failed attempt and puts the mail back in the queue until `next_attempt_at`,
+    /// or in the dead-letter state when there is none.
+    pub async fn mark_failed(&self, message: &OutboxMessage, error: &str,
+                             next_attempt_at: Option<DateTime<Utc>>) -> Result<UpdateResult, MongoError> {
+        let new_doc = match next_attempt_at {
+            Some(next_attempt_at) => doc! {
+                "$set": {
+                    "status": DeliveryStatus::Pending.to_string(),
+                    "next_attempt_at": bson::DateTime::from_chrono(next_attempt_at),
+                    "last_error": error
+                }
+            },
+            None => dead(error),
+        };
+
+        self.col.update_one(claimed(message), new_doc, None).await
+    }
+
+    /// Gives up on due mails that had `max_attempts` without an outcome, which happens when
+    /// sending them keeps crashing the worker, and on mails whose link has expired.
+    /// Returns how many mails went to the dead-letter state.
+    pub async fn abandon_stale(&self, now: DateTime<Utc>, max_attempts: u32) -> Result<u64, MongoError> {
+        let now = bson::DateTime::from_chrono(now);
+        let waiting = [DeliveryStatus::Pending.to_string(), DeliveryStatus::Sending.to_string()];
+
+        let exhausted = doc! {
+            "status": { "$in": waiting.as_slice() },
+            "next_attempt_at": { "$lte": now },
+            "attempts": { "$gte": max_attempts }
+        };
+        let exhausted = self.col.update_many(exhausted, dead("no outcome after the last attempt"), None).await?;
+
+        let expired = doc! {
+            "status": { "$in": waiting.as_slice() },
+            "expires_at": { "$lte": now }
+        };
+        let expired = self.col.update_many(expired, dead("link expired before the mail was sent"), None).await?;
+
+        Ok(exhausted.modified_count + expired.modified_count)
+    }
+
+    /// When the worker has to look at the outbox next.
+    pub async fn next_attempt_at(&self) -> Result<Option<DateTime<Utc>>, MongoError> {
+        let filter = doc! {
+            "status": { "$in": [DeliveryStatus::Pending.to_string(), DeliveryStatus::Sending.to_string()] }
+        };
+        let options = FindOptions::builder()
+            .sort(doc! { "next_attempt_at": 1 })
+            .limit(1)
+            .build();
+        let mut cursor = self.col.find(filter, options).await?;
+
+        Ok(cursor.try_next().await?.map(|message| message.next_attempt_at.to_chrono()))
+    }
+
+    /// Pages through the delivery log, newest mail first.
+    pub async fn search(&self, user_id: Option<&ObjectId>, status: Option<DeliveryStatus>,
+                        skip: u64, limit: i64) -> Result<Vec<OutboxMessage>, MongoError> {
+        let mut filter = Document::new();
+        if let Some(user_id) = user_id {
+            filter.insert("user_id", user_id);
+        }
+        if let Some(status) = status {
+            filter.insert("status", status.to_string());
+        }
+        let options = FindOptions::builder()
+            .sort(doc! { "created_at": -1, "_id": -1 })
+            .skip(skip)
+            .limit(limit)
+            .build();
+        let cursor = self.col.find(filter, options).await?;
+
+        cursor.try_collect().await
+    }
+}
+
+fn dead(error: &str) -> Document {
+    doc! {
+        "$set": {
+            "status": DeliveryStatus::Dead.to_string(),
+            "last_error": error
+        },
+        "$unset": {
+            "data": ""
+        }
+    }
+}
+
+/// Matches the mail only while it is still claimed by the attempt that loaded it, so a
+/// worker whose lease ran out can't overwrite the outcome of a later attempt.
+fn claimed(message: &OutboxMessage) -> Document {
+    doc! {
+        "_id": message.id,
+        "attempts": message.attempts
+    }
+}
@@ -0,0 +1,56 @@
+use std::env;
+use std::time::Duration;
+
+use mongodb::{Client, Collection, IndexModel};
+use mongodb::bson::{DateTime, doc};
+use mongodb::error::Error as MongoError;
+use mongodb::options::{FindOneAndUpdateOptions, IndexOptions, ReturnDocument};
+
+use crate::model::rate_limit_model::RateLimitBucket;
+
+pub struct RateLimitRepository {
+    col: Collection<RateLimitBucket>,
+}
+
+impl RateLimitRepository {
+    pub async fn init() -> Self {
+        let uri = match env::var("MONGO_URI") {
+            Ok(variable) => variable.to_string(),
+            Err(_) => "Error loading env variable".to_string(),
+        };
+
+        let client = Client::with_uri_str(uri).await.unwrap();
+        let db = client.database("rust-actix");
+        let col: Collection<RateLimitBucket> = db.collection("RateLimit");
+
+        let expiry_index = IndexModel::builder()
+            .keys(doc! { "expires_at": 1 })
+            .options(IndexOptions::builder().expire_after(Duration::from_secs(0)).build())
+            .build();
+        col.create_index(expiry_index, None).await.unwrap();
+
+        RateLimitRepository { col }
+    }
+
+    /// Counts one request against the bucket and returns the number of requests seen in it.
+    pub async fn hit(&self, key: &str, expires_at: DateTime) -> Result<i32, MongoError> {
+        let filter = doc! { "_id": key };
+        let update = doc! {
+            "$inc": {
+                "count": 1
+            },
+            "$setOnInsert": {
+                "expires_at": expires_at
+            }
+        };
+        let options = FindOneAndUpdateOptions::builder()
+            .upsert(true)
+            .return_document(ReturnDocument::After)
+            .build();
+
+        match self.col.find_one_and_update(filter, update, options).await {
+            Ok(bucket) => Ok(bucket.map(|b| b.count).unwrap_or(1)),
+            Err(e) => Err(e)
+        }
+    }
+}
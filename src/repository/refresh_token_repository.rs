@@ -0,0 +1,122 @@
+use std::env;
+use std::time::Duration;
+
+use mongodb::{Client, Collection, IndexModel};
+use mongodb::bson::doc;
+use mongodb::bson::oid::ObjectId;
+use mongodb::error::Error as MongoError;
+use mongodb::options::IndexOptions;
+use mongodb::results::{InsertOneResult, UpdateResult};
+
+use crate::model::refresh_token_model::RefreshToken;
+
+pub struct RefreshTokenRepository {
+    col: Collection<RefreshToken>,
+}
+
+impl RefreshTokenRepository {
+    pub async fn init() -> Self {
+        let uri = match env::var("MONGO_URI") {
+            Ok(variable) => variable.to_string(),
+            Err(_) => "Error loading env variable".to_string(),
+        };
+
+        let client = Client::with_uri_str(uri).await.unwrap();
+        let db = client.database("rust-actix");
+        let col: Collection<RefreshToken> = db.collection("RefreshToken");
+
+        // expired tokens are removed by mongo itself
+        let expiry_index = IndexModel::builder()
+            .keys(doc! { "expires_at": 1 })
+            .options(IndexOptions::builder().expire_after(Duration::from_secs(0)).build())
+            .build();
+        let hash_index = IndexModel::builder()
+            .keys(doc! { "token_hash": 1 })
+            .options(IndexOptions::builder().unique(true).build())
+            .build();
+        // every authenticated request checks its family, revoking goes by family or user
+        let family_index = IndexModel::builder().keys(doc! { "family_id": 1 }).build();
+        let user_index = IndexModel::builder().keys(doc! { "user_id": 1 }).build();
+        col.create_indexes(vec![expiry_index, hash_index, family_index, user_index], None).await.unwrap();
+
+        RefreshTokenRepository { col }
+    }
+
+    pub async fn create(&self, token: RefreshToken) -> Result<InsertOneResult, MongoError> {
+        let result = self.col.insert_one(token, None).await?;
+
+        Ok(result)
+    }
+
+    pub async fn find_by_hash(&self, token_hash: &str) -> Result<Option<RefreshToken>, MongoError> {
+        let filter = doc! { "token_hash": token_hash };
+        self.col.find_one(filter, None).await
+    }
+
+    /// Marks the token as used. Returns `None` if the token was already used or revoked,
+    /// which means someone else presented it first.
+    pub async fn mark_used(&self, id: &ObjectId) -> Result<Option<RefreshToken>, MongoError> {
+        let filter = doc! {
+            "_id": id,
+            "used": false,
+            "revoked": false
+        };
+        let update = doc! {
+            "$set": {
+                "used": true
+            }
+        };
+
+        self.col.find_one_and_update(filter, update, None).await
+    }
+
+    pub async fn revoke_family(&self, family_id: &str) -> Result<UpdateResult, MongoError> {
+        let filter = doc! { "family_id": family_id };
+        let update = doc! {
+            "$set": {
+                "revoked": true
+            }
+        };
+
+        self.col.update_many(filter, update, None).await
+    }
+
+    pub async fn revoke_all_for_user(&self, user_id: &ObjectId) -> Result<UpdateResult, MongoError> {
+        let filter = doc! { "user_id": user_id };
+        let update = doc! {
+            "$set": {
+                "revoked": true
+            }
+        };
+
+        self.col.update_many(filter, update, None).await
+    }
+
+    pub async fn revoke_other_families(&self, user_id: &ObjectId, family_id: &str) -> Result<UpdateResult, MongoError> {
+        let filter = doc! {
+            "user_id": user_id,
+            "family_id": {
+                "$ne": family_id
+            }
+        };
+        let update = doc! {
+            "$set": {
+                "revoked": true
+            }
+        };
+
+        self.col.update_many(filter, update, None).await
+    }
+
+    pub async fn is_family_revoked(&self, family_id: &str) -> Result<bool, MongoError> {
+        let filter = doc! {
+            "family_id": family_id,
+            "revoked": true
+        };
+
+        match self.col.find_one(filter, None).await {
+            Ok(token) => Ok(token.is_some()),
+            Err(e) => Err(e)
+        }
+    }
+}
@@ -0,0 +1,58 @@
+use std::env;
+use std::time::Duration;
+
+use mongodb::{Client, Collection, IndexModel};
+use mongodb::bson::doc;
+use mongodb::error::Error as MongoError;
+use mongodb::options::{IndexOptions, UpdateOptions};
+use mongodb::results::UpdateResult;
+
+use crate::model::revoked_token_model::RevokedToken;
+
+pub struct RevokedTokenRepository {
+    col: Collection<RevokedToken>,
+}
+
+impl RevokedTokenRepository {
+    pub async fn init() -> Self {
+        let uri = match env::var("MONGO_URI") {
+            Ok(variable) => variable.to_string(),
+            Err(_) => "Error loading env variable".to_string(),
+        };
+
+        let client = Client::with_uri_str(uri).await.unwrap();
+        let db = client.database("rust-actix");
+        let col: Collection<RevokedToken> = db.collection("RevokedToken");
+
+        // entries are dropped once the revoked token would have expired on its own
+        let expiry_index = IndexModel::builder()
+            .keys(doc! { "expires_at": 1 })
+            .options(IndexOptions::builder().expire_after(Duration::from_secs(0)).build())
+            .build();
+        col.create_index(expiry_index, None).await.unwrap();
+
+        RevokedTokenRepository { col }
+    }
+
+    pub async fn revoke(&self, token: RevokedToken) -> Result<UpdateResult, MongoError> {
+        let filter = doc! { "_id": &token.jti };
+        let update = doc! {
+            "$setOnInsert": {
+                "user_id": token.user_id,
+                "expires_at": token.expires_at
+            }
+        };
+        let options = UpdateOptions::builder().upsert(true).build();
+
+        self.col.update_one(filter, update, options).await
+    }
+
+    pub async fn is_revoked(&self, jti: &str) -> Result<bool, MongoError> {
+        let filter = doc! { "_id": jti };
+
+        match self.col.find_one(filter, None).await {
+            Ok(token) => Ok(token.is_some()),
+            Err(e) => Err(e)
+        }
+    }
+}
@@ -1,16 +1,28 @@
+use std::collections::HashMap;
 use std::env;
 use std::str::FromStr;
 
-use chrono::NaiveDate;
+use chrono::{DateTime, Duration, NaiveDate, Utc};
+use chrono_tz::Tz;
 use futures::TryStreamExt;
-use mongodb::{Client, Collection};
-use mongodb::bson::doc;
+use mongodb::{Client, Collection, IndexModel};
+use mongodb::bson::{self, Bson, doc, Document, from_document, Regex, to_bson};
 use mongodb::bson::oid::ObjectId;
 use mongodb::error::Error as MongoError;
-use mongodb::results::DeleteResult;
+use mongodb::options::{FindOneAndUpdateOptions, FindOneOptions, FindOptions, IndexOptions, ReturnDocument};
+use mongodb::results::{DeleteResult, UpdateResult};
 
-use crate::dto::create_task::CreateTask;
-use crate::model::task_model::{Task, TaskStatus};
+use crate::dto::create_checklist_item::MAX_CHECKLIST_ITEMS;
+use crate::dto::create_task::{CreateTask, MAX_TAGS, normalize_tags};
+use crate::dto::task_page::{CursorValue, TaskCursor};
+use crate::dto::task_query::{SortOrder, TagMatch, TaskQuery, TaskSort};
+use crate::dto::update_task::UpdateTask;
+use crate::model::task_model::{ChecklistItem, Priority, Task, TaskNode, TaskStatus};
+use crate::repository::{is_duplicate_key, regex_escape};
+use crate::service::reminder_service::{next_overdue_alert, reminder_instant, OVERDUE_ALERT_DAYS};
+use crate::service::time_zone_service::{default_time_zone, due_instant, END_OF_DAY};
+
+const DEFAULT_PAGE_SIZE: i64 = 50;
 
 pub struct TaskRepository {
     col: Collection<Task>,
@@ -26,27 +38,127 @@ impl TaskRepository {
         let client = Client::with_uri_str(uri).await.unwrap();
         let db = client.database("rust-actix");
         let col: Collection<Task> = db.collection("Task");
+
+        let text_index_options = IndexOptions::builder()
+            .name(String::from("task_text"))
+            .weights(doc! { "title": 3, "description": 1 })
+            .build();
+        // one index per sort order, each also serving the filters on the same field
+        let indexes = vec![
+            IndexModel::builder().keys(doc! { "user_id": 1, "_id": 1 }).build(),
+            IndexModel::builder().keys(doc! { "user_id": 1, "due_at": 1, "_id": 1 }).build(),
+            IndexModel::builder().keys(doc! { "user_id": 1, "due_date": 1, "_id": 1 }).build(),
+            IndexModel::builder().keys(doc! { "user_id": 1, "title": 1, "_id": 1 }).build(),
+            IndexModel::builder().keys(doc! { "user_id": 1, "status": 1, "due_at": 1 }).build(),
+            IndexModel::builder().keys(doc! { "user_id": 1, "priority_rank": -1, "due_at": 1, "_id": 1 }).build(),
+            IndexModel::builder().keys(doc! { "user_id": 1, "tags": 1 }).build(),
+            IndexModel::builder()
+                .keys(doc! { "reminder_at": 1 })
+                .options(IndexOptions::builder().sparse(true).build())
+                .build(),
+            IndexModel::builder()
+                .keys(doc! { "overdue_alert_at": 1 })
+                .options(IndexOptions::builder().sparse(true).build())
+                .build(),
+            IndexModel::builder()
+                .keys(doc! { "previous_occurrence": 1 })
+                .options(IndexOptions::builder().sparse(true).unique(true).build())
+                .build(),
+            // the user_id prefix makes every text search name the user it is scoped to
+            IndexModel::builder()
+                .keys(doc! { "user_id": 1, "title": "text", "description": "text" })
+                .options(text_index_options)
+                .build(),
+        ];
+        col.create_indexes(indexes, None).await.unwrap();
+
+        // tasks from before priorities existed would sort below `Low` without a rank
+        let unranked = doc! { "priority_rank": { "$exists": false } };
+        let default_priority = Priority::default();
+        let set_default = doc! {
+            "$set": {
+                "priority": default_priority.to_string(),
+                "priority_rank": default_priority.rank()
+            }
+        };
+        col.update_many(unranked, set_default, None).await.unwrap();
+
+        // tasks from before due times existed are due at the end of their day
+        let without_instant = doc! { "due_at": { "$exists": false } };
+        col.update_many(without_instant, due_instant_pipeline(default_time_zone()), None).await.unwrap();
+
+        // open tasks from before overdue alerts existed get the alerts that are still ahead
+        let last_alert_day = OVERDUE_ALERT_DAYS.iter().max().unwrap();
+        let without_alert = doc! {
+            "overdue_alert_at": { "$exists": false },
+            "status": { "$ne": TaskStatus::Done.to_string() },
+            "due_at": { "$gt": bson::DateTime::from_chrono(Utc::now() - Duration::days(*last_alert_day)) }
+        };
+        let set_alert = vec![doc! { "$set": { "overdue_alert_at": next_overdue_alert_expression(default_time_zone()) } }];
+        col.update_many(without_alert, set_alert, None).await.unwrap();
+
         TaskRepository { col }
     }
 
-    pub async fn create_task(&self, new_task: &CreateTask, user_id: &ObjectId) -> Result<Vec<Task>, MongoError> {
-        let new_doc = Task {
+    pub async fn create_task(&self, new_task: &CreateTask, user_id: &ObjectId,
+                             time_zone: Tz) -> Result<Task, MongoError> {
+        let due_at = due_instant(new_task.due_date, new_task.due_time, time_zone);
+        let mut new_doc = Task {
             id: None,
-            user_id: user_id.clone(),
+            user_id: *user_id,
             title: new_task.title.clone(),
             description: new_task.description.to_string(),
             status: TaskStatus::ToDo,
             due_date: new_task.due_date,
+            due_time: new_task.due_time,
+            due_at: bson::DateTime::from_chrono(due_at),
+            remind_before_hours: new_task.remind_before_hours,
+            reminder_at: new_task.remind_before_hours
+                .map(|hours| bson::DateTime::from_chrono(reminder_instant(due_at, hours))),
+            overdue_alert_at: next_overdue_alert(new_task.due_date, new_task.due_time, time_zone, Utc::now())
+                .map(bson::DateTime::from_chrono),
+            priority: new_task.priority,
+            priority_rank: new_task.priority.rank(),
+            tags: normalize_tags(&new_task.tags),
+            checklist: Vec::new(),
+            auto_complete: new_task.auto_complete,
+            blocked_by: Vec::new(),
+            recurrence: new_task.recurrence.as_ref().map(|recurrence| recurrence.anchored(new_task.due_date)),
+            previous_occurrence: None,
         };
-        match self.col.insert_one(new_doc, None).await {
-            Ok(_) => match self.find_all_for_user(user_id).await {
-                Ok(tasks) => Ok(tasks),
-                Err(e) => Err(e)
-            },
+        let result = self.col.insert_one(&new_doc, None).await?;
+        new_doc.id = result.inserted_id.as_object_id();
+
+        Ok(new_doc)
+    }
+
+    /// Stores the next occurrence of a recurring task.
+    /// Returns `None` if the previous occurrence has been continued already.
+    pub async fn create_occurrence(&self, mut task: Task) -> Result<Option<Task>, MongoError> {
+        match self.col.insert_one(&task, None).await {
+            Ok(result) => {
+                task.id = result.inserted_id.as_object_id();
+                Ok(Some(task))
+            }
+            Err(e) if is_duplicate_key(&e) => Ok(None),
             Err(e) => Err(e)
         }
     }
 
+    /// Removes the recurrence from a done task whose series has been continued, so it
+    /// isn't continued again if the task is reopened and done again.
+    pub async fn clear_recurrence(&self, task_id: &ObjectId, user_id: &ObjectId) -> Result<UpdateResult, MongoError> {
+        let filter = doc! {
+            "_id": task_id,
+            "user_id": user_id,
+            "status": TaskStatus::Done.to_string(),
+            "recurrence": { "$exists": true }
+        };
+        let new_doc = doc! { "$unset": { "recurrence": "" } };
+
+        self.col.update_one(filter, new_doc, None).await
+    }
+
     pub async fn find_by_id(&self, task_id: &String, user_id: &ObjectId) -> Result<Option<Task>, MongoError> {
         let task_object_id = match ObjectId::from_str(task_id) {
             Ok(id) => id,
@@ -64,12 +176,117 @@ impl TaskRepository {
         }
     }
 
-    pub async fn find_all_for_user(&self, user_id: &ObjectId) -> Result<Vec<Task>, MongoError> {
-        let filter = doc! { "user_id": user_id };
-        let cursor = self.col.find(filter, None).await?;
-        let tasks: Vec<Task> = cursor.try_collect().await.map_err(MongoError::from)?;
+    /// One page of the user's tasks matching the query, starting after the cursor. The
+    /// returned cursor points past the last task and is `None` on the last page.
+    pub async fn find_page(&self, user_id: &ObjectId, query: &TaskQuery, after: Option<&TaskCursor>,
+                           now: DateTime<Utc>) -> Result<(Vec<Task>, Option<TaskCursor>), MongoError> {
+        let sort = query.sort.unwrap_or_default();
+        let order = query.order.unwrap_or_default();
+        let direction = order_direction(order);
+        // the id always comes last to break ties
+        let sort_keys: Vec<(&str, i32)> = match sort {
+            TaskSort::DueDate => vec![("due_at", direction)],
+            TaskSort::Created => vec![],
+            TaskSort::Title => vec![("title", direction)],
+            TaskSort::Priority => vec![("priority_rank", -direction), ("due_at", direction)],
+        };
 
-        Ok(tasks)
+        let mut conditions = vec![doc! { "user_id": user_id }];
+        if let Some(status) = &query.status {
+            conditions.push(doc! { "status": status.to_string() });
+        }
+        if let Some(due_from) = query.due_from {
+            conditions.push(doc! { "due_date": { "$gte": due_from.to_string() } });
+        }
+        if let Some(due_to) = query.due_to {
+            conditions.push(doc! { "due_date": { "$lte": due_to.to_string() } });
+        }
+        if query.overdue == Some(true) {
+            conditions.push(doc! {
+                "due_at": { "$lt": bson::DateTime::from_chrono(now) },
+                "status": { "$ne": TaskStatus::Done.to_string() }
+            });
+        }
+        if let Some(tags) = &query.tags {
+            let tags: Vec<String> = normalize_tags(&tags.split(',').map(String::from).collect::<Vec<_>>())
+                .into_iter()
+                .filter(|tag| !tag.is_empty())
+                .collect();
+            if !tags.is_empty() {
+                let operator = match query.tag_match.unwrap_or_default() {
+                    TagMatch::Any => "$in",
+                    TagMatch::All => "$all",
+                };
+                conditions.push(doc! { "tags": { operator: tags } });
+            }
+        }
+        if let Some(text) = &query.q {
+            let pattern = Regex { pattern: regex_escape(text), options: String::from("i") };
+            conditions.push(doc! {
+                "$or": [
+                    { "title": pattern.clone() },
+                    { "description": pattern }
+                ]
+            });
+        }
+        if let Some(after) = after {
+            conditions.push(keyset_condition(&sort_keys, &after.values, after.id, direction));
+        }
+
+        let mut sort_doc = Document::new();
+        for (field, field_direction) in &sort_keys {
+            sort_doc.insert(*field, *field_direction);
+        }
+        sort_doc.insert("_id", direction);
+
+        let limit = query.limit.unwrap_or(DEFAULT_PAGE_SIZE);
+        let options = FindOptions::builder()
+            .sort(sort_doc)
+            .limit(limit + 1)
+            .build();
+        let cursor = self.col.find(doc! { "$and": conditions }, options).await?;
+        let mut tasks: Vec<Task> = cursor.try_collect().await?;
+
+        // one task more than asked for tells whether there is another page
+        let next_cursor = if tasks.len() as i64 > limit {
+            tasks.truncate(limit as usize);
+            tasks.last().map(|task| TaskCursor {
+                sort,
+                order,
+                values: sort_keys.iter().map(|(field, _)| sort_value(task, field)).collect(),
+                id: task.id.unwrap(),
+            })
+        } else {
+            None
+        };
+
+        Ok((tasks, next_cursor))
+    }
+
+    /// Text search over title and description of the user's tasks, most relevant first.
+    /// Each task comes with its text score.
+    pub async fn search(&self, user_id: &ObjectId, query: &str, skip: u64,
+                        limit: i64) -> Result<Vec<(Task, f64)>, MongoError> {
+        let filter = doc! {
+            "user_id": user_id,
+            "$text": { "$search": query }
+        };
+        let options = FindOptions::builder()
+            .projection(doc! { "score": { "$meta": "textScore" } })
+            .sort(doc! { "score": { "$meta": "textScore" }, "_id": 1 })
+            .skip(skip)
+            .limit(limit)
+            .build();
+        let cursor = self.col.clone_with_type::<Document>().find(filter, options).await?;
+        let documents: Vec<Document> = cursor.try_collect().await?;
+
+        documents.into_iter()
+            .map(|mut document| {
+                let score = document.remove("score").and_then(|score| score.as_f64()).unwrap_or(0.0);
+                let task: Task = from_document(document).map_err(MongoError::custom)?;
+                Ok((task, score))
+            })
+            .collect()
     }
 
     pub async fn delete_all_for_user(&self, user_id: &ObjectId) -> Result<DeleteResult, MongoError> {
@@ -80,7 +297,8 @@ impl TaskRepository {
         }
     }
 
-    pub async fn delete_by_id(&self, task_id: &String, user_id: &ObjectId) -> Result<Option<Vec<Task>>, MongoError> {
+    /// Returns `false` if the user has no task with that id.
+    pub async fn delete_by_id(&self, task_id: &String, user_id: &ObjectId) -> Result<bool, MongoError> {
         let task_object_id = match ObjectId::from_str(task_id) {
             Ok(id) => id,
             Err(e) => return Err(MongoError::custom(format!("Error parsing ObjectId: {}", e)))
@@ -91,65 +309,614 @@ impl TaskRepository {
             "user_id": user_id
         };
 
-        let delete_result = match self.col.delete_one(filter, None).await {
-            Ok(result) => result,
-            Err(e) => return Err(e)
-        };
-
+        let delete_result = self.col.delete_one(filter, None).await?;
         if delete_result.deleted_count == 0 {
-            return Ok(None);
+            return Ok(false);
         }
 
-        match self.find_all_for_user(user_id).await {
-            Ok(tasks) => Ok(Some(tasks)),
-            Err(e) => Err(e)
-        }
+        // a deleted task doesn't block anything anymore
+        let blocked_filter = doc! { "user_id": user_id, "blocked_by": task_object_id };
+        let new_doc = doc! { "$pull": { "blocked_by": task_object_id } };
+        self.col.update_many(blocked_filter, new_doc, None).await?;
+
+        Ok(true)
     }
 
-    pub async fn update_status(&self, task_id: &String, user_id: &ObjectId,
-                               new_status: &TaskStatus) -> Result<Option<Vec<Task>>, MongoError> {
+    /// With `require_checked` the status only changes while no checklist item is open, so
+    /// an item unchecked in the meantime isn't completed along with the task. `None` if the
+    /// task doesn't exist or has open items.
+    pub async fn update_status(&self, task_id: &String, user_id: &ObjectId, new_status: &TaskStatus,
+                               require_checked: bool) -> Result<Option<Task>, MongoError> {
         let task_object_id = match ObjectId::from_str(task_id) {
             Ok(id) => id,
             Err(e) => return Err(MongoError::custom(format!("Error parsing ObjectId: {}", e)))
         };
 
-        let filter = doc! {
+        let mut filter = doc! {
             "_id": task_object_id,
             "user_id": user_id
         };
+        if require_checked {
+            filter.insert("checklist.done", doc! { "$ne": false });
+        }
 
         let new_doc = doc! {
             "$set": {
                 "status": new_status.to_string()
             }
         };
+        let options = FindOneAndUpdateOptions::builder()
+            .return_document(ReturnDocument::After)
+            .build();
+
+        self.col.find_one_and_update(filter, new_doc, options).await
+    }
+
+    /// Applies the fields present in the update and returns the task as it is afterwards.
+    /// `due_at` is the resulting due instant and has to be passed whenever the due date or
+    /// time change, together with the next overdue alert. `reminder_at` has to be passed
+    /// whenever the reminder changes with them; `Some(None)` turns the reminder off.
+    pub async fn update_task(&self, task_id: &str, user_id: &ObjectId, update: &UpdateTask,
+                             due_at: Option<(DateTime<Utc>, Option<DateTime<Utc>>)>,
+                             reminder_at: Option<Option<DateTime<Utc>>>) -> Result<Option<Task>, MongoError> {
+        let task_object_id = match ObjectId::from_str(task_id) {
+            Ok(id) => id,
+            Err(e) => return Err(MongoError::custom(format!("Error parsing ObjectId: {}", e)))
+        };
 
-        let update_result = match self.col.update_one(filter, new_doc, None).await {
-            Ok(result) => result,
-            Err(e) => return Err(e)
+        let filter = doc! {
+            "_id": task_object_id,
+            "user_id": user_id
         };
 
-        if update_result.modified_count == 0 {
-            return Ok(None);
+        let mut fields = Document::new();
+        if let Some(title) = &update.title {
+            fields.insert("title", title);
+        }
+        if let Some(description) = &update.description {
+            fields.insert("description", description);
+        }
+        if let Some(due_date) = update.due_date {
+            fields.insert("due_date", due_date.to_string());
+        }
+        let mut removed = Document::new();
+        match update.due_time {
+            Some(Some(due_time)) => { fields.insert("due_time", due_time.to_string()); }
+            Some(None) => { removed.insert("due_time", ""); }
+            None => ()
+        }
+        if let Some((due_at, overdue_alert_at)) = due_at {
+            fields.insert("due_at", bson::DateTime::from_chrono(due_at));
+            match overdue_alert_at {
+                Some(overdue_alert_at) => { fields.insert("overdue_alert_at", bson::DateTime::from_chrono(overdue_alert_at)); }
+                None => { removed.insert("overdue_alert_at", ""); }
+            }
+        }
+        match update.remind_before_hours {
+            Some(Some(hours)) => { fields.insert("remind_before_hours", hours); }
+            Some(None) => { removed.insert("remind_before_hours", ""); }
+            None => ()
+        }
+        match reminder_at {
+            Some(Some(reminder_at)) => { fields.insert("reminder_at", bson::DateTime::from_chrono(reminder_at)); }
+            Some(None) => { removed.insert("reminder_at", ""); }
+            None => ()
+        }
+        if let Some(priority) = update.priority {
+            fields.insert("priority", priority.to_string());
+            fields.insert("priority_rank", priority.rank());
+        }
+        if let Some(tags) = &update.tags {
+            fields.insert("tags", normalize_tags(tags));
+        }
+        if let Some(auto_complete) = update.auto_complete {
+            fields.insert("auto_complete", auto_complete);
         }
 
-        match self.find_all_for_user(user_id).await {
-            Ok(tasks) => Ok(Some(tasks)),
-            Err(e) => Err(e)
+        let mut new_doc = Document::new();
+        if !fields.is_empty() {
+            new_doc.insert("$set", fields);
         }
+        if !removed.is_empty() {
+            new_doc.insert("$unset", removed);
+        }
+        let options = FindOneAndUpdateOptions::builder()
+            .return_document(ReturnDocument::After)
+            .build();
+
+        self.col.find_one_and_update(filter, new_doc, options).await
+    }
+
+    /// Recomputes when the user's tasks are due after the user moved to another zone. The
+    /// dates and times stay the same, they are just read in the new zone.
+    pub async fn update_time_zone(&self, user_id: &ObjectId, time_zone: Tz) -> Result<u64, MongoError> {
+        let filter = doc! { "user_id": user_id };
+        let result = self.col.update_many(filter, due_instant_pipeline(time_zone), None).await?;
+
+        Ok(result.modified_count)
     }
 
-    pub async fn find_by_due_date(&self, user_id: &ObjectId, due_date: NaiveDate) -> Result<Vec<Task>, MongoError> {
+    /// Adds the tags the task doesn't have yet and returns the updated task. `None` if the
+    /// task doesn't exist or would end up with more than `MAX_TAGS` tags.
+    pub async fn add_tags(&self, task_id: &str, user_id: &ObjectId,
+                          tags: &[String]) -> Result<Option<Task>, MongoError> {
+        let task_object_id = match ObjectId::from_str(task_id) {
+            Ok(id) => id,
+            Err(e) => return Err(MongoError::custom(format!("Error parsing ObjectId: {}", e)))
+        };
+
+        let tags = normalize_tags(tags);
         let filter = doc! {
+            "_id": task_object_id,
             "user_id": user_id,
-            "due_date": due_date.to_string(),
+            "$expr": {
+                "$lte": [
+                    { "$size": { "$setUnion": [{ "$ifNull": ["$tags", []] }, { "$literal": &tags }] } },
+                    MAX_TAGS as i64
+                ]
+            }
+        };
+        let new_doc = doc! {
+            "$addToSet": {
+                "tags": { "$each": tags }
+            }
+        };
+        let options = FindOneAndUpdateOptions::builder()
+            .return_document(ReturnDocument::After)
+            .build();
+
+        self.col.find_one_and_update(filter, new_doc, options).await
+    }
+
+    pub async fn remove_tag(&self, task_id: &str, user_id: &ObjectId,
+                            tag: &str) -> Result<Option<Task>, MongoError> {
+        let task_object_id = match ObjectId::from_str(task_id) {
+            Ok(id) => id,
+            Err(e) => return Err(MongoError::custom(format!("Error parsing ObjectId: {}", e)))
+        };
+
+        let filter = doc! {
+            "_id": task_object_id,
+            "user_id": user_id
+        };
+        let new_doc = doc! {
+            "$pull": {
+                "tags": tag
+            }
+        };
+        let options = FindOneAndUpdateOptions::builder()
+            .return_document(ReturnDocument::After)
+            .build();
+
+        self.col.find_one_and_update(filter, new_doc, options).await
+    }
+
+    /// All tags of the user with the number of tasks using them, most used first.
+    pub async fn tag_usage(&self, user_id: &ObjectId) -> Result<Vec<(String, i64)>, MongoError> {
+        let pipeline = vec![
+            doc! { "$match": { "user_id": user_id } },
+            doc! { "$unwind": "$tags" },
+            doc! { "$group": { "_id": "$tags", "count": { "$sum": 1 } } },
+            doc! { "$sort": { "count": -1, "_id": 1 } },
+        ];
+        let cursor = self.col.aggregate(pipeline, None).await?;
+        let groups: Vec<Document> = cursor.try_collect().await?;
+
+        let usage = groups.iter()
+            .filter_map(|group| match (group.get_str("_id"), group.get_i32("count")) {
+                (Ok(tag), Ok(count)) => Some((tag.to_string(), count as i64)),
+                _ => None
+            })
+            .collect();
+
+        Ok(usage)
+    }
+
+    /// Replaces the tag on all of the user's tasks. Tasks that already have the new tag
+    /// keep it once, which merges the two tags. Returns the number of tasks changed.
+    pub async fn rename_tag(&self, user_id: &ObjectId, tag: &str, new_tag: &str) -> Result<u64, MongoError> {
+        let filter = doc! {
+            "user_id": user_id,
+            "tags": tag
+        };
+        let pipeline = vec![doc! {
+            "$set": {
+                "tags": {
+                    "$concatArrays": [
+                        {
+                            "$filter": {
+                                "input": "$tags",
+                                "cond": { "$not": [{ "$in": ["$$this", { "$literal": [tag, new_tag] }] }] }
+                            }
+                        },
+                        // tags starting with `$` would be read as field paths otherwise
+                        { "$literal": [new_tag] }
+                    ]
+                }
+            }
+        }];
+        let result = self.col.update_many(filter, pipeline, None).await?;
+
+        Ok(result.matched_count)
+    }
+
+    /// Appends the item. `None` if the task doesn't exist or its checklist is full.
+    pub async fn add_checklist_item(&self, task_id: &str, user_id: &ObjectId,
+                                    item: &ChecklistItem) -> Result<Option<Task>, MongoError> {
+        let mut filter = task_filter(task_id, user_id)?;
+        filter.insert(format!("checklist.{}", MAX_CHECKLIST_ITEMS - 1), doc! { "$exists": false });
+        let new_doc = doc! {
+            "$push": {
+                "checklist": to_bson(item)?
+            }
+        };
+
+        self.col.find_one_and_update(filter, new_doc, return_updated()).await
+    }
+
+    /// Changes text and/or state of an item. `None` if the task or the item doesn't exist.
+    pub async fn update_checklist_item(&self, task_id: &str, user_id: &ObjectId, item_id: &ObjectId,
+                                       text: Option<&str>, done: Option<bool>) -> Result<Option<Task>, MongoError> {
+        let mut filter = task_filter(task_id, user_id)?;
+        filter.insert("checklist.id", item_id);
+
+        let mut fields = Document::new();
+        if let Some(text) = text {
+            fields.insert("checklist.$.text", text);
+        }
+        if let Some(done) = done {
+            fields.insert("checklist.$.done", done);
+        }
+        if fields.is_empty() {
+            return self.col.find_one(filter, None).await;
+        }
+
+        self.col.find_one_and_update(filter, doc! { "$set": fields }, return_updated()).await
+    }
+
+    pub async fn remove_checklist_item(&self, task_id: &str, user_id: &ObjectId,
+                                       item_id: &ObjectId) -> Result<Option<Task>, MongoError> {
+        let mut filter = task_filter(task_id, user_id)?;
+        filter.insert("checklist.id", item_id);
+        let new_doc = doc! {
+            "$pull": {
+                "checklist": { "id": item_id }
+            }
+        };
+
+        self.col.find_one_and_update(filter, new_doc, return_updated()).await
+    }
+
+    /// Puts the checklist items in the order of `item_ids`, which have to be distinct. The
+    /// items are rearranged as they are stored, so changes made to them in the meantime stay.
+    /// `None` if the task doesn't exist or the ids aren't exactly those of its items.
+    pub async fn reorder_checklist(&self, task_id: &str, user_id: &ObjectId,
+                                   item_ids: &[ObjectId]) -> Result<Option<Task>, MongoError> {
+        let mut filter = task_filter(task_id, user_id)?;
+        filter.insert("checklist", doc! { "$size": item_ids.len() as i32 });
+        filter.insert("checklist.id", doc! { "$all": item_ids });
+
+        let new_doc = vec![doc! {
+            "$set": {
+                "checklist": {
+                    "$map": {
+                        "input": item_ids,
+                        "as": "item_id",
+                        "in": {
+                            "$arrayElemAt": [
+                                { "$filter": { "input": "$checklist", "cond": { "$eq": ["$$this.id", "$$item_id"] } } },
+                                0
+                            ]
+                        }
+                    }
+                }
+            }
+        }];
+
+        self.col.find_one_and_update(filter, new_doc, return_updated()).await
+    }
+
+    /// Id, title, status and blockers of all of the user's tasks, ordered by due date.
+    pub async fn find_nodes(&self, user_id: &ObjectId) -> Result<Vec<TaskNode>, MongoError> {
+        let filter = doc! { "user_id": user_id };
+        let options = FindOptions::builder()
+            .projection(doc! { "title": 1, "status": 1, "blocked_by": 1 })
+            .sort(doc! { "due_date": 1, "_id": 1 })
+            .build();
+        let cursor = self.col.clone_with_type::<TaskNode>().find(filter, options).await?;
+
+        cursor.try_collect().await
+    }
+
+    /// Those of the given tasks that are not done yet.
+    pub async fn find_unfinished(&self, user_id: &ObjectId, task_ids: &[ObjectId]) -> Result<Vec<Task>, MongoError> {
+        let filter = doc! {
+            "_id": { "$in": task_ids },
+            "user_id": user_id,
+            "status": { "$ne": TaskStatus::Done.to_string() }
+        };
+        let cursor = self.col.find(filter, None).await?;
+
+        cursor.try_collect().await
+    }
+
+    pub async fn add_blocker(&self, task_id: &str, user_id: &ObjectId,
+                             blocker_id: &ObjectId) -> Result<Option<Task>, MongoError> {
+        let filter = task_filter(task_id, user_id)?;
+        let new_doc = doc! {
+            "$addToSet": {
+                "blocked_by": blocker_id
+            }
+        };
+
+        self.col.find_one_and_update(filter, new_doc, return_updated()).await
+    }
+
+    pub async fn remove_blocker(&self, task_id: &str, user_id: &ObjectId,
+                                blocker_id: &ObjectId) -> Result<Option<Task>, MongoError> {
+        let filter = task_filter(task_id, user_id)?;
+        let new_doc = doc! {
+            "$pull": {
+                "blocked_by": blocker_id
+            }
+        };
+
+        self.col.find_one_and_update(filter, new_doc, return_updated()).await
+    }
+
+    /// Open tasks due on the given days, most urgent first.
+    pub async fn find_due_between(&self, user_id: &ObjectId, from: NaiveDate,
+                                  to: NaiveDate) -> Result<Vec<Task>, MongoError> {
+        let filter = doc! {
+            "user_id": user_id,
+            "due_date": { "$gte": from.to_string(), "$lte": to.to_string() },
             "status": {
                 "$ne": TaskStatus::Done.to_string()
             }
         };
-        let cursor = self.col.find(filter, None).await?;
+        let options = FindOptions::builder()
+            .sort(doc! { "priority_rank": -1, "due_at": 1, "_id": 1 })
+            .build();
+        let cursor = self.col.find(filter, options).await?;
         let tasks: Vec<Task> = cursor.try_collect().await.map_err(MongoError::from)?;
 
         Ok(tasks)
     }
+
+    /// Open tasks of the user that were due before `now`, longest overdue first.
+    pub async fn find_overdue(&self, user_id: &ObjectId, now: DateTime<Utc>) -> Result<Vec<Task>, MongoError> {
+        let filter = doc! {
+            "user_id": user_id,
+            "due_at": { "$lt": bson::DateTime::from_chrono(now) },
+            "status": { "$ne": TaskStatus::Done.to_string() }
+        };
+        let options = FindOptions::builder()
+            .sort(doc! { "due_at": 1, "_id": 1 })
+            .build();
+        let cursor = self.col.find(filter, options).await?;
+
+        cursor.try_collect().await
+    }
+
+    /// The task with the overdue alert that has been due by `now` the longest. Tasks done in
+    /// the meantime get no alerts.
+    pub async fn find_due_overdue_alert(&self, now: DateTime<Utc>) -> Result<Option<Task>, MongoError> {
+        let filter = doc! {
+            "overdue_alert_at": { "$lte": bson::DateTime::from_chrono(now) },
+            "status": { "$ne": TaskStatus::Done.to_string() }
+        };
+        let options = FindOneOptions::builder().sort(doc! { "overdue_alert_at": 1 }).build();
+
+        self.col.find_one(filter, options).await
+    }
+
+    /// Replaces the due overdue alert of the task, as it was loaded, with the next one or
+    /// removes it when there is none. Returns `false` if the alert was taken or the task got
+    /// another due time in the meantime.
+    pub async fn advance_overdue_alert(&self, task: &Task, next: Option<DateTime<Utc>>) -> Result<bool, MongoError> {
+        let filter = doc! {
+            "_id": task.id,
+            "due_at": task.due_at,
+            "overdue_alert_at": task.overdue_alert_at
+        };
+        let new_doc = match next {
+            Some(next) => doc! { "$set": { "overdue_alert_at": bson::DateTime::from_chrono(next) } },
+            None => doc! { "$unset": { "overdue_alert_at": "" } },
+        };
+        let result = self.col.update_one(filter, new_doc, None).await?;
+
+        Ok(result.modified_count == 1)
+    }
+
+    /// When the earliest pending overdue alert is due.
+    pub async fn next_overdue_alert_at(&self) -> Result<Option<DateTime<Utc>>, MongoError> {
+        let filter = doc! {
+            "overdue_alert_at": { "$exists": true },
+            "status": { "$ne": TaskStatus::Done.to_string() }
+        };
+        let options = FindOptions::builder()
+            .sort(doc! { "overdue_alert_at": 1 })
+            .limit(1)
+            .build();
+        let mut cursor = self.col.find(filter, options).await?;
+
+        Ok(cursor.try_next().await?.and_then(|task| task.overdue_alert_at).map(|alert_at| alert_at.to_chrono()))
+    }
+
+    /// Takes one reminder that is due by `now` off the queue. Reminders of tasks that were
+    /// done in the meantime are never sent.
+    pub async fn take_due_reminder(&self, now: DateTime<Utc>) -> Result<Option<Task>, MongoError> {
+        let filter = doc! {
+            "reminder_at": { "$lte": bson::DateTime::from_chrono(now) },
+            "status": { "$ne": TaskStatus::Done.to_string() }
+        };
+        let new_doc = doc! { "$unset": { "reminder_at": "" } };
+
+        self.col.find_one_and_update(filter, new_doc, None).await
+    }
+
+    /// When the earliest pending reminder is due.
+    pub async fn next_reminder_at(&self) -> Result<Option<DateTime<Utc>>, MongoError> {
+        let filter = doc! {
+            "reminder_at": { "$exists": true },
+            "status": { "$ne": TaskStatus::Done.to_string() }
+        };
+        let options = FindOptions::builder()
+            .sort(doc! { "reminder_at": 1 })
+            .limit(1)
+            .build();
+        let mut cursor = self.col.find(filter, options).await?;
+
+        Ok(cursor.try_next().await?.and_then(|task| task.reminder_at).map(|reminder_at| reminder_at.to_chrono()))
+    }
+
+    /// Number of tasks of each of the given users. Users without tasks are left out.
+    pub async fn count_by_users(&self, user_ids: &[ObjectId]) -> Result<HashMap<ObjectId, i64>, MongoError> {
+        let pipeline = vec![
+            doc! { "$match": { "user_id": { "$in": user_ids } } },
+            doc! { "$group": { "_id": "$user_id", "count": { "$sum": 1 } } },
+        ];
+        let cursor = self.col.aggregate(pipeline, None).await?;
+        let groups: Vec<Document> = cursor.try_collect().await?;
+
+        let counts = groups.iter()
+            .filter_map(|group| match (group.get_object_id("_id"), group.get_i32("count")) {
+                (Ok(user_id), Ok(count)) => Some((user_id, count as i64)),
+                _ => None
+            })
+            .collect();
+
+        Ok(counts)
+    }
+}
+
+fn task_filter(task_id: &str, user_id: &ObjectId) -> Result<Document, MongoError> {
+    let task_object_id = match ObjectId::from_str(task_id) {
+        Ok(id) => id,
+        Err(e) => return Err(MongoError::custom(format!("Error parsing ObjectId: {}", e)))
+    };
+
+    Ok(doc! {
+        "_id": task_object_id,
+        "user_id": user_id
+    })
+}
+
+fn return_updated() -> FindOneAndUpdateOptions {
+    FindOneAndUpdateOptions::builder()
+        .return_document(ReturnDocument::After)
+        .build()
+}
+
+/// Matches everything that sorts after the given position: tasks that are past it on the
+/// first key, or equal on the first key and past it on the second, and so on down to the id.
+fn keyset_condition(sort_keys: &[(&str, i32)], values: &[CursorValue], id: ObjectId, direction: i32) -> Document {
+    let mut keys: Vec<(&str, i32, Bson)> = sort_keys.iter().zip(values)
+        .map(|((field, field_direction), value)| (*field, *field_direction, match value {
+            CursorValue::Number(number) => Bson::Int32(*number),
+            // instants travel as RFC 3339 text but have to be compared as dates
+            CursorValue::Text(text) if *field == "due_at" => match DateTime::parse_from_rfc3339(text) {
+                Ok(instant) => Bson::DateTime(bson::DateTime::from_chrono(instant.with_timezone(&Utc))),
+                Err(_) => Bson::String(text.clone()),
+            },
+            CursorValue::Text(text) => Bson::String(text.clone()),
+        }))
+        .collect();
+    keys.push(("_id", direction, Bson::ObjectId(id)));
+
+    let branches: Vec<Document> = (0..keys.len())
+        .map(|index| {
+            let mut branch = Document::new();
+            for (field, _, value) in &keys[..index] {
+                branch.insert(*field, value.clone());
+            }
+            let (field, field_direction, value) = &keys[index];
+            let comparison = if *field_direction > 0 { "$gt" } else { "$lt" };
+            branch.insert(*field, doc! { comparison: value.clone() });
+            branch
+        })
+        .collect();
+
+    doc! { "$or": branches }
+}
+
+fn sort_value(task: &Task, field: &str) -> CursorValue {
+    match field {
+        "priority_rank" => CursorValue::Number(task.priority_rank),
+        "title" => CursorValue::Text(task.title.clone()),
+        _ => CursorValue::Text(task.due_at.to_chrono().to_rfc3339()),
+    }
+}
+
+/// Update pipeline setting `due_at` from `due_date` and `due_time` read in the given zone,
+/// and moving reminders and overdue alerts that haven't been sent yet along with it.
+/// `$dateFromString` reads skipped and repeated times the way `local_instant` does.
+fn due_instant_pipeline(time_zone: Tz) -> Vec<Document> {
+    vec![
+        doc! {
+            "$set": {
+                "due_at": {
+                    "$dateFromString": {
+                        "dateString": { "$concat": ["$due_date", "T", { "$ifNull": ["$due_time", END_OF_DAY] }] },
+                        "timezone": time_zone.name()
+                    }
+                }
+            }
+        },
+        doc! {
+            "$set": {
+                "reminder_at": {
+                    "$cond": [
+                        { "$gt": ["$reminder_at", null] },
+                        { "$subtract": ["$due_at", { "$multiply": ["$remind_before_hours", 3_600_000] }] },
+                        "$$REMOVE"
+                    ]
+                },
+                "overdue_alert_at": {
+                    "$cond": [
+                        { "$gt": ["$overdue_alert_at", null] },
+                        next_overdue_alert_expression(time_zone),
+                        "$$REMOVE"
+                    ]
+                }
+            }
+        },
+    ]
+}
+
+/// The first alert day after `due_at` that is still ahead, see `next_overdue_alert`.
+/// Removes the field when all of them have passed.
+fn next_overdue_alert_expression(time_zone: Tz) -> Document {
+    doc! {
+        "$ifNull": [
+            {
+                "$min": {
+                    "$filter": {
+                        "input": {
+                            "$map": {
+                                "input": OVERDUE_ALERT_DAYS.to_vec(),
+                                "in": {
+                                    "$dateAdd": {
+                                        "startDate": "$due_at",
+                                        "unit": "day",
+                                        "amount": "$$this",
+                                        "timezone": time_zone.name()
+                                    }
+                                }
+                            }
+                        },
+                        "cond": { "$gt": ["$$this", "$$NOW"] }
+                    }
+                }
+            },
+            "$$REMOVE"
+        ]
+    }
+}
+
+fn order_direction(order: SortOrder) -> i32 {
+    match order {
+        SortOrder::Asc => 1,
+        SortOrder::Desc => -1,
+    }
 }
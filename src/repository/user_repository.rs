@@ -3,17 +3,23 @@ extern crate dotenv;
 use std::env;
 use futures::TryStreamExt;
 
+use chrono::{DateTime, Utc};
 use mongodb::{
     bson::doc,
     Client,
-    Collection, results::InsertOneResult,
+    Collection, IndexModel, results::InsertOneResult,
 };
+use mongodb::bson::{self, to_bson, Document};
 use mongodb::bson::oid::ObjectId;
+use mongodb::bson::Regex;
 use mongodb::error::Error as MongoError;
+use mongodb::options::{FindOneAndUpdateOptions, FindOptions, IndexOptions, ReturnDocument};
 use mongodb::results::UpdateResult;
 
+use crate::dto::update_notification_settings::UpdateNotificationSettings;
 use crate::dto::update_user::UpdateUser;
-use crate::model::user_model::User;
+use crate::model::user_model::{NotificationKind, OidcIdentity, ReminderSettings, Role, User};
+use crate::repository::{is_duplicate_key, regex_escape};
 
 pub struct UserRepository {
     col: Collection<User>,
@@ -29,14 +35,52 @@ impl UserRepository {
         let client = Client::with_uri_str(uri).await.unwrap();
         let db = client.database("rust-actix");
         let col: Collection<User> = db.collection("User");
+
+        // the scheduler looks for the next digest to send on every run
+        let digest_index = IndexModel::builder().keys(doc! { "next_digest_at": 1 }).build();
+        // an address belongs to one account, however it got there
+        let email_index = IndexModel::builder()
+            .keys(doc! { "email": 1 })
+            .options(IndexOptions::builder().unique(true).build())
+            .build();
+        if let Err(e) = col.create_indexes(vec![digest_index, email_index], None).await {
+            // deployments from before the index may have several accounts with one address
+            let duplicates = duplicate_emails(&col).await.unwrap_or_default();
+            if !duplicates.is_empty() {
+                panic!("Several accounts share the email addresses {}; merge or delete the extra accounts \
+                        before starting again", duplicates.join(", "));
+            }
+            panic!("Could not create the user indexes: {}", e);
+        }
+
+        // accounts from before email verification existed keep working as they did
+        let without_verified = doc! { "verified": { "$exists": false } };
+        col.update_many(without_verified, doc! { "$set": { "verified": true } }, None).await.unwrap();
+
+        // admins are made by other admins, so the first ones come from the environment. Only
+        // confirmed addresses count, and only until there is an admin, so neither whoever signs
+        // up with the address first nor a demoted admin gets the role from a restart
+        let admin_emails: Vec<String> = env::var("ADMIN_EMAILS")
+            .map(|emails| emails.split(',').map(str::trim).filter(|email| !email.is_empty())
+                .map(str::to_string).collect())
+            .unwrap_or_default();
+        let admin = doc! { "role": Role::Admin.to_string() };
+        if !admin_emails.is_empty() && col.count_documents(admin, None).await.unwrap() == 0 {
+            let filter = doc! { "email": { "$in": admin_emails }, "verified": true };
+            col.update_many(filter, doc! { "$set": { "role": Role::Admin.to_string() } }, None).await.unwrap();
+        }
+
         UserRepository { col }
     }
 
-    pub async fn create_user(&self, email: String, password: String) -> Result<InsertOneResult, MongoError> {
+    pub async fn create_user(&self, email: String, password: String,
+                             time_zone: Option<String>) -> Result<InsertOneResult, MongoError> {
         let new_doc = User {
             id: None,
             email,
             password,
+            time_zone,
+            ..Default::default()
         };
         let result = self.col.insert_one(new_doc, None).await?;
 
@@ -51,10 +95,94 @@ impl UserRepository {
         }
     }
 
+    pub async fn find_by_id(&self, id: &ObjectId) -> Result<Option<User>, MongoError> {
+        let filter = doc! { "_id": id };
+        self.col.find_one(filter, None).await
+    }
+
+    pub async fn find_by_oidc_identity(&self, identity: &OidcIdentity) -> Result<Option<User>, MongoError> {
+        let filter = doc! {
+            "oidc.issuer": &identity.issuer,
+            "oidc.subject": &identity.subject
+        };
+        self.col.find_one(filter, None).await
+    }
+
+    /// Accounts created through single sign-on get an unusable password and are verified,
+    /// since the identity provider already confirmed the address.
+    /// Creates the account of a new single sign-on user. `None` if an account with the
+    /// address was created in the meantime.
+    pub async fn create_oidc_user(&self, email: String, password: String,
+                                  identity: OidcIdentity) -> Result<Option<InsertOneResult>, MongoError> {
+        let new_doc = User {
+            id: None,
+            email,
+            password,
+            verified: true,
+            oidc: Some(identity),
+            ..Default::default()
+        };
+
+        match self.col.insert_one(new_doc, None).await {
+            Ok(result) => Ok(Some(result)),
+            Err(e) if is_duplicate_key(&e) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Links an existing account to the external identity. Only verified accounts are
+    /// linked, otherwise whoever registered the address first would share the account with
+    /// its owner. Returns `None` if the account isn't verified.
+    pub async fn link_oidc_identity(&self, id: &ObjectId, identity: &OidcIdentity) -> Result<Option<User>, MongoError> {
+        let new_doc = doc! {
+            "$set": {
+                "oidc": {
+                    "issuer": &identity.issuer,
+                    "subject": &identity.subject
+                }
+            }
+        };
+        let filter = doc! { "_id": id, "verified": true };
+        let options = FindOneAndUpdateOptions::builder()
+            .return_document(ReturnDocument::After)
+            .build();
+
+        self.col.find_one_and_update(filter, new_doc, options).await
+    }
+
+    /// Stores the requested email as pending; it replaces the current one in `verify_email`.
     pub async fn update_user(&self, id: ObjectId, new_user: UpdateUser) -> Result<UpdateResult, MongoError> {
         let new_doc = doc! {
             "$set": {
-                "email": new_user.email
+                "pending_email": new_user.email
+            }
+        };
+        let filter = doc! { "_id": id };
+        let result = self.col.update_one(filter, new_doc, None).await?;
+
+        Ok(result)
+    }
+
+    pub async fn update_password(&self, id: &ObjectId, password_hash: String) -> Result<UpdateResult, MongoError> {
+        let new_doc = doc! {
+            "$set": {
+                "password": password_hash,
+                "password_reset_required": false
+            }
+        };
+        let filter = doc! { "_id": id };
+        let result = self.col.update_one(filter, new_doc, None).await?;
+
+        Ok(result)
+    }
+
+    /// Sets the zone together with the next digest time, which moves with it.
+    pub async fn update_time_zone(&self, id: &ObjectId, time_zone: &str,
+                                  next_digest_at: Option<DateTime<Utc>>) -> Result<UpdateResult, MongoError> {
+        let new_doc = doc! {
+            "$set": {
+                "time_zone": time_zone,
+                "next_digest_at": next_digest_at.map(bson::DateTime::from_chrono)
             }
         };
         let filter = doc! { "_id": id };
@@ -63,6 +191,289 @@ impl UserRepository {
         Ok(result)
     }
 
+    pub async fn update_reminders(&self, id: &ObjectId, settings: &ReminderSettings,
+                                  next_digest_at: Option<DateTime<Utc>>) -> Result<UpdateResult, MongoError> {
+        let new_doc = doc! {
+            "$set": {
+                "reminders": to_bson(settings)?,
+                "next_digest_at": next_digest_at.map(bson::DateTime::from_chrono)
+            }
+        };
+        let filter = doc! { "_id": id };
+        let result = self.col.update_one(filter, new_doc, None).await?;
+
+        Ok(result)
+    }
+
+    /// Sets the given notification flags. The next digest is computed again, so turning
+    /// the digest back on doesn't send one for the time it was off.
+    pub async fn update_notifications(&self, id: &ObjectId, update: &UpdateNotificationSettings,
+                                      next_digest_at: Option<DateTime<Utc>>) -> Result<Option<User>, MongoError> {
+        let mut set = doc! { "next_digest_at": next_digest_at.map(bson::DateTime::from_chrono) };
+        let flags = [
+            (NotificationKind::Digest, update.digest),
+            (NotificationKind::OverdueAlerts, update.overdue_alerts),
+            (NotificationKind::Reminders, update.reminders),
+        ];
+        for (kind, enabled) in flags {
+            if let Some(enabled) = enabled {
+                set.insert(format!("notifications.{}", kind), enabled);
+            }
+        }
+        let new_doc = doc! { "$set": set };
+        let filter = doc! { "_id": id };
+        let options = FindOneAndUpdateOptions::builder()
+            .return_document(ReturnDocument::After)
+            .build();
+
+        self.col.find_one_and_update(filter, new_doc, options).await
+    }
+
+    /// Turns one kind of notification off. Returns `false` if the user doesn't exist.
+    pub async fn unsubscribe(&self, id: &ObjectId, kind: NotificationKind) -> Result<bool, MongoError> {
+        let mut set = Document::new();
+        set.insert(format!("notifications.{}", kind), false);
+        let new_doc = doc! { "$set": set };
+        let filter = doc! { "_id": id };
+        let result = self.col.update_one(filter, new_doc, None).await?;
+
+        Ok(result.matched_count == 1)
+    }
+
+    /// Users who get digests and whose next one is due by `now`, or who haven't been
+    /// scheduled yet.
+    pub async fn find_digest_due(&self, now: DateTime<Utc>) -> Result<Vec<User>, MongoError> {
+        let filter = doc! {
+            "verified": true,
+            "disabled": { "$ne": true },
+            "notifications.digest": { "$ne": false },
+            "$or": [
+                { "next_digest_at": { "$lte": bson::DateTime::from_chrono(now) } },
+                { "next_digest_at": null }
+            ]
+        };
+        let cursor = self.col.find(filter, None).await?;
+
+        cursor.try_collect().await
+    }
+
+    /// Moves the next digest time on, but only if it is still `previous`. The scheduler
+    /// that manages to move it is the one that sends the digest.
+    pub async fn reschedule_digest(&self, id: &ObjectId, previous: Option<bson::DateTime>,
+                                   next: Option<DateTime<Utc>>) -> Result<bool, MongoError> {
+        let filter = doc! { "_id": id, "next_digest_at": previous };
+        let new_doc = doc! {
+            "$set": {
+                "next_digest_at": next.map(bson::DateTime::from_chrono)
+            }
+        };
+        let result = self.col.update_one(filter, new_doc, None).await?;
+
+        Ok(result.modified_count == 1)
+    }
+
+    /// When the earliest scheduled digest is due.
+    pub async fn next_digest_at(&self) -> Result<Option<DateTime<Utc>>, MongoError> {
+        let filter = doc! {
+            "verified": true,
+            "disabled": { "$ne": true },
+            "notifications.digest": { "$ne": false },
+            "next_digest_at": { "$ne": null }
+        };
+        let options = FindOptions::builder()
+            .sort(doc! { "next_digest_at": 1 })
+            .limit(1)
+            .build();
+        let mut cursor = self.col.find(filter, options).await?;
+
+        Ok(cursor.try_next().await?.and_then(|user| user.next_digest_at).map(|next| next.to_chrono()))
+    }
+
+    /// Confirms `email` as the address of the account, promoting it if it was the pending one.
+    /// Returns `false` if the address is no longer the account's or another account has
+    /// taken it since, which the unique index on `email` catches.
+    pub async fn verify_email(&self, id: &ObjectId, email: &str) -> Result<bool, MongoError> {
+        let new_doc = doc! {
+            "$set": {
+                "email": email,
+                "verified": true
+            },
+            "$unset": {
+                "pending_email": ""
+            }
+        };
+        let filter = doc! {
+            "_id": id,
+            "$or": [
+                { "email": email },
+                { "pending_email": email }
+            ]
+        };
+        match self.col.update_one(filter, new_doc, None).await {
+            Ok(result) => Ok(result.matched_count == 1),
+            Err(e) if is_duplicate_key(&e) => Ok(false),
+            Err(e) => Err(e)
+        }
+    }
+
+    pub async fn start_mfa_enrollment(&self, id: &ObjectId, secret: &str) -> Result<UpdateResult, MongoError> {
+        let new_doc = doc! {
+            "$set": {
+                "mfa.pending_secret": secret
+            }
+        };
+        let filter = doc! { "_id": id };
+        let result = self.col.update_one(filter, new_doc, None).await?;
+
+        Ok(result)
+    }
+
+    pub async fn enable_mfa(&self, id: &ObjectId, secret: &str, recovery_codes: Vec<String>,
+                            step: i64) -> Result<UpdateResult, MongoError> {
+        let new_doc = doc! {
+            "$set": {
+                "mfa.enabled": true,
+                "mfa.secret": secret,
+                "mfa.recovery_codes": recovery_codes,
+                "mfa.last_step": step
+            },
+            "$unset": {
+                "mfa.pending_secret": ""
+            }
+        };
+        let filter = doc! { "_id": id };
+        let result = self.col.update_one(filter, new_doc, None).await?;
+
+        Ok(result)
+    }
+
+    pub async fn disable_mfa(&self, id: &ObjectId) -> Result<UpdateResult, MongoError> {
+        let new_doc = doc! {
+            "$set": {
+                "mfa.enabled": false,
+                "mfa.recovery_codes": []
+            },
+            "$unset": {
+                "mfa.secret": "",
+                "mfa.pending_secret": ""
+            }
+        };
+        let filter = doc! { "_id": id };
+        let result = self.col.update_one(filter, new_doc, None).await?;
+
+        Ok(result)
+    }
+
+    pub async fn set_recovery_codes(&self, id: &ObjectId, recovery_codes: Vec<String>) -> Result<UpdateResult, MongoError> {
+        let new_doc = doc! {
+            "$set": {
+                "mfa.recovery_codes": recovery_codes
+            }
+        };
+        let filter = doc! { "_id": id };
+        let result = self.col.update_one(filter, new_doc, None).await?;
+
+        Ok(result)
+    }
+
+    /// Removes the recovery code from the user. Returns `false` if the code wasn't there.
+    pub async fn consume_recovery_code(&self, id: &ObjectId, code_hash: &str) -> Result<bool, MongoError> {
+        let new_doc = doc! {
+            "$pull": {
+                "mfa.recovery_codes": code_hash
+            }
+        };
+        let filter = doc! {
+            "_id": id,
+            "mfa.recovery_codes": code_hash
+        };
+        let result = self.col.update_one(filter, new_doc, None).await?;
+
+        Ok(result.modified_count == 1)
+    }
+
+    /// Records the time step of an accepted TOTP code. Returns `false` if the same or a
+    /// later step was accepted before, which means the code is being replayed.
+    pub async fn advance_mfa_step(&self, id: &ObjectId, step: i64) -> Result<bool, MongoError> {
+        let new_doc = doc! {
+            "$set": {
+                "mfa.last_step": step
+            }
+        };
+        let filter = doc! {
+            "_id": id,
+            "$or": [
+                { "mfa.last_step": { "$lt": step } },
+                { "mfa.last_step": { "$exists": false } }
+            ]
+        };
+        let result = self.col.update_one(filter, new_doc, None).await?;
+
+        Ok(result.modified_count == 1)
+    }
+
+    /// Enabling an account starts its digests over, so it doesn't get one for a time it was
+    /// disabled at; the scheduler picks it up like a new user.
+    pub async fn set_disabled(&self, id: &ObjectId, disabled: bool) -> Result<Option<User>, MongoError> {
+        let new_doc = if disabled {
+            doc! { "$set": { "disabled": true } }
+        } else {
+            doc! { "$set": { "disabled": false, "next_digest_at": null } }
+        };
+        let filter = doc! { "_id": id };
+        let options = FindOneAndUpdateOptions::builder()
+            .return_document(ReturnDocument::After)
+            .build();
+
+        self.col.find_one_and_update(filter, new_doc, options).await
+    }
+
+    pub async fn set_role(&self, id: &ObjectId, role: Role) -> Result<Option<User>, MongoError> {
+        let new_doc = doc! {
+            "$set": {
+                "role": role.to_string()
+            }
+        };
+        let filter = doc! { "_id": id };
+        let options = FindOneAndUpdateOptions::builder()
+            .return_document(ReturnDocument::After)
+            .build();
+
+        self.col.find_one_and_update(filter, new_doc, options).await
+    }
+
+    pub async fn require_password_reset(&self, id: &ObjectId) -> Result<Option<User>, MongoError> {
+        let new_doc = doc! {
+            "$set": {
+                "password_reset_required": true
+            }
+        };
+        let filter = doc! { "_id": id };
+        let options = FindOneAndUpdateOptions::builder()
+            .return_document(ReturnDocument::After)
+            .build();
+
+        self.col.find_one_and_update(filter, new_doc, options).await
+    }
+
+    /// Pages through all users, optionally only those whose email contains `query`.
+    pub async fn search(&self, query: Option<&str>, skip: u64, limit: i64) -> Result<Vec<User>, MongoError> {
+        let filter = query.map(|query| doc! {
+            "email": Regex {
+                pattern: regex_escape(query),
+                options: String::from("i"),
+            }
+        });
+        let options = FindOptions::builder()
+            .sort(doc! { "email": 1 })
+            .skip(skip)
+            .limit(limit)
+            .build();
+        let cursor = self.col.find(filter, options).await?;
+
+        cursor.try_collect().await
+    }
+
     pub async fn delete_user(&self, id: ObjectId) -> Result<Option<User>, MongoError> {
         let filter = doc! { "_id": id };
         match self.col.find_one_and_delete(filter, None).await {
@@ -70,11 +481,16 @@ impl UserRepository {
             Err(e) => Err(e)
         }
     }
+}
 
-    pub async fn find_all(&self) -> Result<Vec<User>, MongoError> {
-        let cursor = self.col.find(None, None).await?;
-        let users: Vec<User> = cursor.try_collect().await.map_err(MongoError::from)?;
+/// Addresses used by more than one account, which the unique email index doesn't allow.
+async fn duplicate_emails(col: &Collection<User>) -> Result<Vec<String>, MongoError> {
+    let pipeline = vec![
+        doc! { "$group": { "_id": "$email", "count": { "$sum": 1 } } },
+        doc! { "$match": { "count": { "$gt": 1 } } },
+        doc! { "$sort": { "_id": 1 } },
+    ];
+    let groups: Vec<Document> = col.aggregate(pipeline, None).await?.try_collect().await?;
 
-        Ok(users)
-    }
+    Ok(groups.iter().filter_map(|group| group.get_str("_id").ok()).map(str::to_string).collect())
 }
@@ -1,29 +1,42 @@
 
-use actix_web::web::Data;
-use chrono::{Duration, Local, NaiveDate, Timelike, TimeZone, Utc};
-use lettre::{
-    Message,
-    SmtpTransport,
-    Transport,
-};
+use std::error::Error;
+use std::fmt;
+
+use chrono::{Duration, NaiveDate};
+use chrono_tz::Tz;
+use lettre::Message;
+use lettre::address::AddressError;
 use lettre::error::Error as LettreError;
-use lettre::message::header::ContentType;
-use lettre::message::Mailbox;
-use lettre::transport::smtp::authentication::Credentials;
+use lettre::message::{Mailbox, MultiPart};
+use lettre::message::header::{Header, HeaderName, HeaderValue};
+use lettre::transport::file::Error as FileError;
 use lettre::transport::smtp::Error as SmtpError;
-use lettre::transport::smtp::response::Response;
-use log::{debug, error, info};
-use mime::TEXT_HTML;
-use tokio::time::sleep;
+use handlebars::RenderError;
+use mongodb::bson::oid::ObjectId;
+use mongodb::error::Error as MongoError;
+use serde_json::{json, Value};
+
+use crate::model::one_time_token_model::TokenPurpose;
+use crate::model::outbox_model::{MailKind, OutboxMessage};
+use crate::model::task_model::{Priority, Task};
+use crate::repository::one_time_token_repository::OneTimeTokenRepository;
+use crate::service::mailer_service::Mailer;
+use crate::service::outbox_service::Outbox;
+use crate::service::reminder_service::days_overdue;
+use crate::service::time_zone_service::today;
+use crate::service::token_service::issue_one_time_token;
 
-use crate::model::task_model::Task;
-use crate::repository::task_repository::TaskRepository;
-use crate::repository::user_repository::UserRepository;
+const DEFAULT_EMAIL_VERIFICATION_TTL_HOURS: i64 = 48;
+const DEFAULT_PASSWORD_RESET_TTL_MINUTES: i64 = 30;
 
 #[derive(Debug)]
 pub enum EmailError {
     Smtp(SmtpError),
     Lettre(LettreError),
+    File(FileError),
+    Io(std::io::Error),
+    Template(RenderError),
+    Address(AddressError),
 }
 
 impl From<SmtpError> for EmailError {
@@ -38,105 +51,272 @@ impl From<LettreError> for EmailError {
     }
 }
 
-pub async fn morning_email_scheduler(user_repo: Data<UserRepository>, task_repo: Data<TaskRepository>) {
-    info!("Scheduler is active");
-
-    loop {
-        let now = chrono_tz::Europe::Bucharest.from_utc_datetime(&Utc::now().naive_utc());
-        let next_morning = (now + Duration::days(1))
-            .with_hour(6)
-            .unwrap()
-            .with_minute(0)
-            .unwrap()
-            .with_second(0)
-            .unwrap();
+impl From<FileError> for EmailError {
+    fn from(error: FileError) -> Self {
+        EmailError::File(error)
+    }
+}
 
-        let duration_until_next_morning = (next_morning - now)
-            .to_std().expect("Failed to calculate duration");
+impl From<std::io::Error> for EmailError {
+    fn from(error: std::io::Error) -> Self {
+        EmailError::Io(error)
+    }
+}
 
-        sleep(duration_until_next_morning).await;
+impl From<RenderError> for EmailError {
+    fn from(error: RenderError) -> Self {
+        EmailError::Template(error)
+    }
+}
 
-        info!("Running scheduler for task emails");
-        send_email_to_users(&user_repo, &task_repo).await;
+impl From<AddressError> for EmailError {
+    fn from(error: AddressError) -> Self {
+        EmailError::Address(error)
+    }
+}
 
-        let seconds = duration_until_next_morning.as_secs();
-        let minutes = seconds / 60;
-        let hours = minutes / 60;
-        info!("Scheduler finished. Next run in {}h {}m {}s", hours, minutes, seconds);
+impl EmailError {
+    /// Whether sending the same mail again can't succeed either.
+    pub fn is_permanent(&self) -> bool {
+        match self {
+            EmailError::Smtp(e) => e.is_permanent(),
+            EmailError::Lettre(_) | EmailError::Template(_) | EmailError::Address(_) => true,
+            EmailError::File(_) | EmailError::Io(_) => false,
+        }
     }
 }
 
-async fn send_email_to_users(user_repo: &Data<UserRepository>, task_repo: &Data<TaskRepository>) {
-    let users = match user_repo.find_all().await {
-        Ok(users) => users,
-        Err(e) => {
-            error!("Error in scheduler while fetching users: {}", e);
-            Vec::new()
+impl fmt::Display for EmailError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EmailError::Smtp(e) => write!(f, "SMTP delivery failed: {}", e),
+            EmailError::Lettre(e) => write!(f, "Invalid message: {}", e),
+            EmailError::File(e) => write!(f, "Could not write message file: {}", e),
+            EmailError::Io(e) => write!(f, "Could not write message to maildir: {}", e),
+            EmailError::Template(e) => write!(f, "Could not render message: {}", e),
+            EmailError::Address(e) => write!(f, "Invalid recipient: {}", e),
         }
+    }
+}
+
+/// Queues a mail of the open tasks due between `from` and `until` for the user, after
+/// the tasks that are already overdue. `from` is the user's today.
+pub async fn queue_digest_email(outbox: &Outbox, user_id: &ObjectId, to: &str, from: NaiveDate, until: NaiveDate,
+                                tasks: &[Task], overdue: &[Task]) -> Result<(), MongoError> {
+    let (mut subject, title) = if from == until {
+        (format!("Tasks due on {}", from), String::from("Tasks Due Today"))
+    } else {
+        (format!("Tasks due from {} to {}", from, until), format!("Tasks Due Until {}", until))
     };
+    if !overdue.is_empty() {
+        subject.push_str(&format!(" ({} overdue)", overdue.len()));
+    }
+    let overdue: Vec<Value> = overdue.iter()
+        .map(|task| json!({
+            "title": task.title,
+            "description": task.description,
+            "status": task.status.to_string(),
+            "due_date": task.due_date.to_string(),
+            "days_overdue": days_overdue(task.due_date, from),
+        }))
+        .collect();
+    let data = json!({
+        "title": title,
+        "base_url": app_base_url(),
+        "overdue": overdue,
+        "groups": priority_groups(tasks),
+    });
 
-    let today: NaiveDate = Local::now().date_naive();
-    for user in users {
-        let user_tasks = match task_repo.find_by_due_date(&user.id.unwrap(), today).await {
-            Ok(tasks) => tasks,
-            Err(e) => {
-                error!("Error in scheduler while fetching user\'s tasks: {}", e);
-                Vec::new()
-            }
-        };
-
-        if user_tasks.is_empty() {
-            continue;
-        } else {
-            let subject = format!("Tasks due on {}", today);
-            let body = build_html_body(&user_tasks).await;
-
-            match send_email(&user.email, &subject, body).await {
-                Ok(_) => debug!("Email sent to {} for {} tasks", user.email, user_tasks.len()),
-                Err(e) => error!("Error sending email to {}: {:?}", user.email, e)
-            };
+    outbox.enqueue(Some(user_id), MailKind::Digest, to, &subject, "digest", &data).await
+}
+
+/// Queues the reminder the user asked for ahead of a task's due time.
+pub async fn queue_reminder_email(outbox: &Outbox, to: &str, task: &Task, time_zone: Tz) -> Result<(), MongoError> {
+    let due_at = task.due_at.to_chrono().with_timezone(&time_zone);
+    let subject = format!("Reminder: {} is due {}", task.title, due_at.format("%Y-%m-%d %H:%M"));
+    let data = json!({
+        "title": "Task reminder",
+        "base_url": app_base_url(),
+        "task_title": task.title,
+        "description": task.description,
+        "due": due_at.format("%A, %Y-%m-%d at %H:%M").to_string(),
+        "time_zone": time_zone.name(),
+    });
+
+    outbox.enqueue(Some(&task.user_id), MailKind::Reminder, to, &subject, "reminder", &data).await
+}
+
+/// Queues an alert about a task that is still open after its due date. The later the
+/// alert, the more urgent its wording.
+pub async fn queue_overdue_alert_email(outbox: &Outbox, to: &str, task: &Task, time_zone: Tz) -> Result<(), MongoError> {
+    let days = days_overdue(task.due_date, today(time_zone));
+    let subject = match days {
+        0..=2 => format!("Overdue: {}", task.title),
+        3..=6 => format!("Still overdue: {}", task.title),
+        _ => format!("Overdue for over a week: {}", task.title),
+    };
+    let data = json!({
+        "title": "Overdue task",
+        "base_url": app_base_url(),
+        "task_title": task.title,
+        "description": task.description,
+        "status": task.status.to_string(),
+        "due_date": task.due_date.to_string(),
+        "days_overdue": days,
+    });
+
+    outbox.enqueue(Some(&task.user_id), MailKind::OverdueAlert, to, &subject, "overdue_alert", &data).await
+}
+
+/// Issues a password reset token and mails the reset link to the user.
+pub async fn start_password_reset(outbox: &Outbox, token_repo: &OneTimeTokenRepository,
+                                  user_id: &ObjectId, email: String) -> Result<(), MongoError> {
+    let ttl = link_ttl(MailKind::PasswordReset).unwrap();
+    let token = issue_one_time_token(token_repo, user_id, TokenPurpose::PasswordReset, None, ttl).await?;
+
+    queue_password_reset_email(outbox, user_id, &email, &token).await
+}
+
+async fn queue_password_reset_email(outbox: &Outbox, user_id: &ObjectId, to: &str,
+                                    token: &str) -> Result<(), MongoError> {
+    let base_url = app_base_url();
+    let subject = String::from("Reset your password");
+    let data = json!({
+        "title": "Password reset",
+        "link": format!("{}/reset-password?token={}", base_url, token),
+        "base_url": base_url,
+        "token": token,
+    });
+
+    outbox.enqueue(Some(user_id), MailKind::PasswordReset, to, &subject, "password_reset", &data).await
+}
+
+/// Issues a verification token for `email` and mails the confirmation link to that address.
+pub async fn start_email_verification(outbox: &Outbox, token_repo: &OneTimeTokenRepository,
+                                      user_id: &ObjectId, email: String) -> Result<(), MongoError> {
+    let ttl = link_ttl(MailKind::EmailVerification).unwrap();
+    let token = issue_one_time_token(token_repo, user_id, TokenPurpose::EmailVerification,
+                                     Some(email.clone()), ttl).await?;
+
+    queue_verification_email(outbox, user_id, &email, &token).await
+}
+
+async fn queue_verification_email(outbox: &Outbox, user_id: &ObjectId, to: &str,
+                                  token: &str) -> Result<(), MongoError> {
+    let base_url = app_base_url();
+    let subject = String::from("Confirm your email address");
+    let data = json!({
+        "title": "Confirm your email address",
+        "link": format!("{}/auth/verify?token={}", base_url, token),
+        "base_url": base_url,
+    });
+
+    outbox.enqueue(Some(user_id), MailKind::EmailVerification, to, &subject, "verify_email", &data).await
+}
+
+/// How long the one-time link in an account mail works, `None` for mails without one.
+pub fn link_ttl(kind: MailKind) -> Option<Duration> {
+    match kind {
+        MailKind::PasswordReset => {
+            let minutes = std::env::var("PASSWORD_RESET_TTL_MINUTES").ok()
+                .and_then(|value| value.parse().ok())
+                .unwrap_or(DEFAULT_PASSWORD_RESET_TTL_MINUTES);
+            Some(Duration::minutes(minutes))
+        }
+        MailKind::EmailVerification => {
+            let hours = std::env::var("EMAIL_VERIFICATION_TTL_HOURS").ok()
+                .and_then(|value| value.parse().ok())
+                .unwrap_or(DEFAULT_EMAIL_VERIFICATION_TTL_HOURS);
+            Some(Duration::hours(hours))
         }
+        MailKind::Digest | MailKind::Reminder | MailKind::OverdueAlert => None,
     }
 }
 
-async fn send_email(to: &String, subject: &String, body: String) -> Result<Response, EmailError> {
-    let smtp_username = std::env::var("SMTP_USERNAME").expect("SMTP_USERNAME not provided");
-    let smtp_password = std::env::var("SMTP_PASSWORD").expect("SMTP_PASSWORD not provided");
+pub fn app_base_url() -> String {
+    std::env::var("APP_BASE_URL").unwrap_or_else(|_| String::from("http://127.0.0.1:8080"))
+}
+
+/// Renders both variants of the mail's template and sends them as one
+/// `multipart/alternative` mail. Only called by the outbox worker.
+pub async fn send_email(mailer: &Mailer, message: &OutboxMessage) -> Result<(), EmailError> {
+    let body = mailer.templates().render(&message.template, &message.data)?;
 
-    let from_mailbox: Mailbox = smtp_username.parse().unwrap();
-    let to_mailbox: Mailbox = to.parse().unwrap();
-    let message_result = Message::builder()
-        .from(from_mailbox)
+    let to_mailbox: Mailbox = message.to.parse()?;
+    let mut builder = Message::builder()
+        .from(mailer.sender())
         .to(to_mailbox)
-        .header(ContentType::parse(TEXT_HTML.as_ref()).unwrap())
-        .subject(subject)
-        .body(body);
+        .subject(&message.subject);
+    if let Some(url) = &message.unsubscribe_url {
+        builder = builder
+            .header(ListUnsubscribe(url.clone()))
+            .header(ListUnsubscribePost);
+    }
+    let message_result = builder
+        .multipart(MultiPart::alternative_plain_html(body.text, body.html));
 
     let message = match message_result {
         Ok(msg) => msg,
         Err(e) => return Err(EmailError::Lettre(e)),
     };
 
-    let credentials = Credentials::new(smtp_username, smtp_password);
-    let mailer = SmtpTransport::relay("smtp.gmail.com")
-        .unwrap()
-        .credentials(credentials)
-        .build();
+    mailer.send(&message).await
+}
+
+/// `List-Unsubscribe` with the link that turns the notification off.
+#[derive(Clone)]
+struct ListUnsubscribe(String);
+
+impl Header for ListUnsubscribe {
+    fn name() -> HeaderName {
+        HeaderName::new_from_ascii_str("List-Unsubscribe")
+    }
 
-    mailer.send(&message).map_err(EmailError::from)
+    fn parse(s: &str) -> Result<Self, Box<dyn Error + Send + Sync>> {
+        Ok(ListUnsubscribe(s.trim().trim_start_matches('<').trim_end_matches('>').to_string()))
+    }
+
+    fn display(&self) -> HeaderValue {
+        HeaderValue::new(Self::name(), format!("<{}>", self.0))
+    }
 }
 
-async fn build_html_body(tasks: &Vec<Task>) -> String {
-    let mut body = String::from("<html><body>");
-    body.push_str("<h1>Tasks Due Today</h1>");
-    body.push_str("<ul>");
+/// Tells mail clients that a POST to the `List-Unsubscribe` link unsubscribes in one click
+/// (RFC 8058).
+#[derive(Clone)]
+struct ListUnsubscribePost;
 
-    for task in tasks {
-        body.push_str(&format!("<li><b>{}</b> -> {} -> [In: {}]</li>", task.title, task.description, task.status));
+impl Header for ListUnsubscribePost {
+    fn name() -> HeaderName {
+        HeaderName::new_from_ascii_str("List-Unsubscribe-Post")
     }
 
-    body.push_str("</ul>");
-    body.push_str("</body></html>");
+    fn parse(_: &str) -> Result<Self, Box<dyn Error + Send + Sync>> {
+        Ok(ListUnsubscribePost)
+    }
+
+    fn display(&self) -> HeaderValue {
+        HeaderValue::new(Self::name(), String::from("List-Unsubscribe=One-Click"))
+    }
+}
 
-    body
-}
\ No newline at end of file
+/// The tasks grouped by priority, most urgent group first, leaving out empty groups.
+fn priority_groups(tasks: &[Task]) -> Vec<Value> {
+    [Priority::Urgent, Priority::High, Priority::Medium, Priority::Low].into_iter()
+        .filter_map(|priority| {
+            let group: Vec<Value> = tasks.iter()
+                .filter(|task| task.priority == priority)
+                .map(|task| json!({
+                    "title": task.title,
+                    "description": task.description,
+                    "status": task.status.to_string(),
+                }))
+                .collect();
+            if group.is_empty() {
+                None
+            } else {
+                Some(json!({ "priority": priority.to_string(), "tasks": group }))
+            }
+        })
+        .collect()
+}
@@ -0,0 +1,260 @@
+use std::fs;
+use std::path::PathBuf;
+
+use lettre::{FileTransport, Message, SmtpTransport, Transport};
+use lettre::message::Mailbox;
+use lettre::transport::smtp::authentication::Credentials;
+use log::{debug, info};
+
+use crate::service::email_service::EmailError;
+use crate::service::template_service::Templates;
+use crate::service::token_service::generate_token;
+
+/// The relay mails went through before the host could be configured.
+const DEFAULT_SMTP_HOST: &str = "smtp.gmail.com";
+const DEFAULT_MAIL_DIR: &str = "mail";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TlsMode {
+    /// Plain connection, e.g. for a local MailHog.
+    None,
+    /// Upgrades a plain connection, usually on port 587.
+    StartTls,
+    /// TLS from the start, usually on port 465.
+    Tls,
+}
+
+impl TlsMode {
+    fn default_port(&self) -> u16 {
+        match self {
+            TlsMode::None => 25,
+            TlsMode::StartTls => 587,
+            TlsMode::Tls => 465,
+        }
+    }
+}
+
+enum Backend {
+    Smtp(SmtpTransport),
+    /// One `.eml` file per message in a directory.
+    File(FileTransport),
+    /// A maildir (`tmp`, `new` and `cur`) any mail client can open.
+    Maildir(PathBuf),
+    /// Keeps the messages in memory. Only tests can read them, so it can't be configured.
+    #[cfg(test)]
+    Memory(std::sync::Mutex<Vec<Message>>),
+}
+
+/// Sends the mails of the application through the backend selected by `MAIL_TRANSPORT`:
+/// `smtp` (the default), `file` or `maildir`. Built once at startup and shared,
+/// together with the templates the mails are rendered from.
+pub struct Mailer {
+    from: Mailbox,
+    transport: Backend,
+    templates: Templates,
+}
+
+impl Mailer {
+    pub fn from_env() -> Self {
+        let transport = match std::env::var("MAIL_TRANSPORT").as_deref() {
+            Ok("smtp") | Err(_) => Backend::Smtp(smtp_transport()),
+            Ok("file") => {
+                let dir = mail_dir();
+                fs::create_dir_all(&dir).expect("MAIL_DIR can't be created");
+                Backend::File(FileTransport::new(dir))
+            }
+            Ok("maildir") => {
+                let dir = mail_dir();
+                for sub_dir in ["tmp", "new", "cur"] {
+                    fs::create_dir_all(dir.join(sub_dir)).expect("MAIL_DIR can't be created");
+                }
+                Backend::Maildir(dir)
+            }
+            Ok(other) => panic!("Unknown MAIL_TRANSPORT {}, expected smtp, file or maildir", other),
+        };
+
+        // Gmail and most relays only accept the account's own address as sender
+        let from = std::env::var("MAIL_FROM")
+            .or_else(|_| std::env::var("SMTP_USERNAME"))
+            .expect("MAIL_FROM not provided");
+        let from = from.parse().expect("MAIL_FROM is not a valid address");
+
+        Mailer { from, transport, templates: Templates::from_env() }
+    }
+
+    pub fn sender(&self) -> Mailbox {
+        self.from.clone()
+    }
+
+    pub fn templates(&self) -> &Templates {
+        &self.templates
+    }
+
+    pub async fn send(&self, message: &Message) -> Result<(), EmailError> {
+        match &self.transport {
+            Backend::Smtp(transport) => {
+                // the SMTP conversation blocks, so it must not hold up the runtime's threads
+                let transport = transport.clone();
+                let message = message.clone();
+                tokio::task::spawn_blocking(move || transport.send(&message)).await
+                    .unwrap_or_else(|e| std::panic::resume_unwind(e.into_panic()))?;
+            }
+            Backend::File(transport) => {
+                let id = transport.send(message)?;
+                debug!("Email written to {}.eml", id);
+            }
+            Backend::Maildir(dir) => {
+                // written to tmp first so readers never see half a message in new
+                let name = format!("{}.{}.taskr", chrono::Utc::now().timestamp(), generate_token());
+                let tmp_path = dir.join("tmp").join(&name);
+                fs::write(&tmp_path, message.formatted())?;
+                fs::rename(&tmp_path, dir.join("new").join(&name))?;
+            }
+            #[cfg(test)]
+            Backend::Memory(messages) => {
+                messages.lock().unwrap().push(message.clone());
+            }
+        }
+        Ok(())
+    }
+
+    /// Messages the in-memory backend captured so far, empty for every other backend.
+    #[cfg(test)]
+    pub fn captured(&self) -> Vec<Message> {
+        match &self.transport {
+            Backend::Memory(messages) => messages.lock().unwrap().clone(),
+            _ => Vec::new(),
+        }
+    }
+}
+
+/// SMTP relay from `SMTP_HOST`, `SMTP_PORT` and `SMTP_TLS` (`none`, `starttls` or `tls`).
+/// Without `SMTP_HOST` mails go through Gmail over TLS, as they always did.
+/// `SMTP_USERNAME` and `SMTP_PASSWORD` are only sent when both are set, and never over a
+/// plain connection.
+fn smtp_transport() -> SmtpTransport {
+    let host = std::env::var("SMTP_HOST").ok();
+    let tls = match std::env::var("SMTP_TLS").as_deref() {
+        Ok("none") => TlsMode::None,
+        Ok("starttls") => TlsMode::StartTls,
+        Ok("tls") => TlsMode::Tls,
+        Err(_) if host.is_none() => TlsMode::Tls,
+        Err(_) => TlsMode::StartTls,
+        Ok(other) => panic!("Unknown SMTP_TLS {}, expected none, starttls or tls", other),
+    };
+    let host = host.unwrap_or_else(|| String::from(DEFAULT_SMTP_HOST));
+    let port = std::env::var("SMTP_PORT").ok()
+        .map(|port| port.parse().expect("SMTP_PORT is not a port number"))
+        .unwrap_or_else(|| tls.default_port());
+
+    let builder = match tls {
+        TlsMode::None => SmtpTransport::builder_dangerous(&host),
+        TlsMode::StartTls => SmtpTransport::starttls_relay(&host).expect("SMTP_HOST is not a valid host name"),
+        TlsMode::Tls => SmtpTransport::relay(&host).expect("SMTP_HOST is not a valid host name"),
+    };
+    let builder = match (std::env::var("SMTP_USERNAME"), std::env::var("SMTP_PASSWORD")) {
+        (Ok(_), Ok(_)) if tls == TlsMode::None => {
+            panic!("SMTP credentials are not sent over a plain connection, set SMTP_TLS to starttls or tls")
+        }
+        (Ok(username), Ok(password)) => builder.credentials(Credentials::new(username, password)),
+        _ => builder,
+    };
+
+    info!("Sending mail through {}:{} ({:?})", host, port, tls);
+    builder.port(port).build()
+}
+
+fn mail_dir() -> PathBuf {
+    PathBuf::from(std::env::var("MAIL_DIR").unwrap_or_else(|_| String::from(DEFAULT_MAIL_DIR)))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use mongodb::bson::{doc, DateTime};
+
+    use crate::model::outbox_model::{DeliveryStatus, MailKind, OutboxMessage};
+    use crate::service::email_service::send_email;
+
+    use super::*;
+
+    fn memory_mailer() -> Mailer {
+        Mailer {
+            from: "taskr@example.com".parse().unwrap(),
+            transport: Backend::Memory(Mutex::new(Vec::new())),
+            templates: Templates::from_env(),
+        }
+    }
+
+    fn reminder(unsubscribe_url: Option<&str>) -> OutboxMessage {
+        let mut data = doc! {
+            "title": "Task reminder",
+            "base_url": "https://taskr.example.com",
+            "task_title": "Water the plants",
+            "description": "All of them",
+            "due": "Monday, 2024-03-04 at 18:00",
+            "time_zone": "Europe/Berlin",
+        };
+        if let Some(url) = unsubscribe_url {
+            data.insert("unsubscribe_url", url);
+        }
+
+        OutboxMessage {
+            id: None,
+            user_id: None,
+            kind: MailKind::Reminder,
+            to: String::from("user@example.com"),
+            subject: String::from("Reminder: Water the plants"),
+            template: String::from("reminder"),
+            data,
+            unsubscribe_url: unsubscribe_url.map(String::from),
+            status: DeliveryStatus::Sending,
+            attempts: 1,
+            next_attempt_at: DateTime::now(),
+            last_error: None,
+            created_at: DateTime::now(),
+            sent_at: None,
+            expires_at: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn memory_backend_captures_rendered_mail() {
+        let mailer = memory_mailer();
+        send_email(&mailer, &reminder(None)).await.unwrap();
+
+        let captured = mailer.captured();
+        assert_eq!(captured.len(), 1);
+        let formatted = String::from_utf8(captured[0].formatted()).unwrap();
+        assert!(formatted.contains("From: taskr@example.com"));
+        assert!(formatted.contains("To: user@example.com"));
+        assert!(formatted.contains("Subject: Reminder: Water the plants"));
+        assert!(formatted.contains("multipart/alternative"));
+        assert!(formatted.contains("text/plain"));
+        assert!(formatted.contains("text/html"));
+        assert!(!formatted.contains("List-Unsubscribe"));
+    }
+
+    #[tokio::test]
+    async fn notifications_carry_one_click_unsubscribe_headers() {
+        let mailer = memory_mailer();
+        let url = "https://taskr.example.com/notifications/unsubscribe?user=1&list=reminders&sig=abc";
+        send_email(&mailer, &reminder(Some(url))).await.unwrap();
+
+        let formatted = String::from_utf8(mailer.captured()[0].formatted()).unwrap();
+        assert!(formatted.contains(&format!("List-Unsubscribe: <{}>", url)));
+        assert!(formatted.contains("List-Unsubscribe-Post: List-Unsubscribe=One-Click"));
+    }
+
+    #[tokio::test]
+    async fn invalid_recipient_is_a_permanent_failure() {
+        let mailer = memory_mailer();
+        let mut message = reminder(None);
+        message.to = String::from("not an address");
+
+        let error = send_email(&mailer, &message).await.unwrap_err();
+        assert!(error.is_permanent());
+        assert!(mailer.captured().is_empty());
+    }
+}
@@ -0,0 +1,143 @@
+use chrono::Utc;
+use data_encoding::BASE32_NOPAD;
+use hmac::{Hmac, Mac};
+use mongodb::error::Error as MongoError;
+use rand::Rng;
+use rand::RngCore;
+use sha1::Sha1;
+use url::form_urlencoded::byte_serialize;
+
+use crate::model::user_model::User;
+use crate::repository::user_repository::UserRepository;
+use crate::service::token_service::hash_token;
+
+const TOTP_PERIOD_SECONDS: i64 = 30;
+const TOTP_DIGITS: u32 = 6;
+/// Steps before and after the current one that are still accepted, to allow for clock drift.
+const TOTP_ALLOWED_DRIFT: i64 = 1;
+const RECOVERY_CODE_COUNT: usize = 10;
+const RECOVERY_CODE_ALPHABET: &[u8] = b"abcdefghjkmnpqrstuvwxyz23456789";
+
+/// Generates a new base32 encoded TOTP secret.
+pub fn generate_secret() -> String {
+    let mut bytes = [0u8; 20];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    BASE32_NOPAD.encode(&bytes)
+}
+
+/// Key URI understood by authenticator apps.
+pub fn otpauth_uri(secret: &str, account: &str) -> String {
+    let issuer = std::env::var("MFA_ISSUER").unwrap_or_else(|_| String::from("taskr"));
+    let label: String = byte_serialize(format!("{}:{}", issuer, account).as_bytes()).collect();
+    let issuer: String = byte_serialize(issuer.as_bytes()).collect();
+
+    format!("otpauth://totp/{}?secret={}&issuer={}&algorithm=SHA1&digits={}&period={}",
+            label, secret, issuer, TOTP_DIGITS, TOTP_PERIOD_SECONDS)
+}
+
+/// Checks the code against the current time step and its neighbours and returns the
+/// matching step.
+pub fn verify_totp(secret: &str, code: &str) -> Option<i64> {
+    let key = BASE32_NOPAD.decode(secret.as_bytes()).ok()?;
+    let code = code.trim();
+    if code.len() != TOTP_DIGITS as usize || !code.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+
+    let current_step = Utc::now().timestamp() / TOTP_PERIOD_SECONDS;
+    (current_step - TOTP_ALLOWED_DRIFT..=current_step + TOTP_ALLOWED_DRIFT)
+        .find(|step| totp_code(&key, *step) == code)
+}
+
+fn totp_code(key: &[u8], step: i64) -> String {
+    let mut mac = Hmac::<Sha1>::new_from_slice(key).unwrap();
+    mac.update(&step.to_be_bytes());
+    let hash = mac.finalize().into_bytes();
+
+    // dynamic truncation from RFC 4226
+    let offset = (hash[hash.len() - 1] & 0x0f) as usize;
+    let binary = u32::from_be_bytes([hash[offset] & 0x7f, hash[offset + 1], hash[offset + 2], hash[offset + 3]]);
+
+    format!("{:0width$}", binary % 10u32.pow(TOTP_DIGITS), width = TOTP_DIGITS as usize)
+}
+
+/// Generates a fresh set of recovery codes. The plain codes are returned together with
+/// the hashes that get stored.
+pub fn generate_recovery_codes() -> (Vec<String>, Vec<String>) {
+    let mut rng = rand::thread_rng();
+    let codes: Vec<String> = (0..RECOVERY_CODE_COUNT)
+        .map(|_| {
+            let chars: String = (0..10)
+                .map(|_| RECOVERY_CODE_ALPHABET[rng.gen_range(0..RECOVERY_CODE_ALPHABET.len())] as char)
+                .collect();
+            format!("{}-{}", &chars[..5], &chars[5..])
+        })
+        .collect();
+    let hashes = codes.iter().map(|code| hash_recovery_code(code)).collect();
+
+    (codes, hashes)
+}
+
+fn hash_recovery_code(code: &str) -> String {
+    hash_token(&code.trim().to_lowercase())
+}
+
+/// Accepts either a TOTP code or one of the user's recovery codes. Both are single use.
+pub async fn verify_second_factor(user_repo: &UserRepository, user: &User, code: &str) -> Result<bool, MongoError> {
+    let secret = match &user.mfa.secret {
+        Some(secret) if user.mfa.enabled => secret,
+        _ => return Ok(false)
+    };
+
+    if code.contains('-') {
+        return user_repo.consume_recovery_code(&user.id.unwrap(), &hash_recovery_code(code)).await;
+    }
+
+    match verify_totp(secret, code) {
+        Some(step) => user_repo.advance_mfa_step(&user.id.unwrap(), step).await,
+        None => Ok(false)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Key of the SHA-1 test vectors in RFC 6238.
+    const RFC_KEY: &[u8] = b"12345678901234567890";
+
+    #[test]
+    fn codes_match_rfc_6238() {
+        // the RFC lists eight digits, the last six are the code
+        assert_eq!(totp_code(RFC_KEY, 59 / TOTP_PERIOD_SECONDS), "287082");
+        assert_eq!(totp_code(RFC_KEY, 1111111109 / TOTP_PERIOD_SECONDS), "081804");
+        assert_eq!(totp_code(RFC_KEY, 2000000000 / TOTP_PERIOD_SECONDS), "279037");
+    }
+
+    #[test]
+    fn accepts_the_current_code() {
+        let secret = BASE32_NOPAD.encode(RFC_KEY);
+        let step = Utc::now().timestamp() / TOTP_PERIOD_SECONDS;
+
+        assert!(verify_totp(&secret, &totp_code(RFC_KEY, step)).is_some());
+        assert!(verify_totp(&secret, &format!(" {} ", totp_code(RFC_KEY, step))).is_some());
+    }
+
+    #[test]
+    fn rejects_codes_outside_the_drift_window() {
+        let secret = BASE32_NOPAD.encode(RFC_KEY);
+        let step = Utc::now().timestamp() / TOTP_PERIOD_SECONDS;
+
+        assert!(verify_totp(&secret, &totp_code(RFC_KEY, step - 3)).is_none());
+        assert!(verify_totp(&secret, &totp_code(RFC_KEY, step + 3)).is_none());
+    }
+
+    #[test]
+    fn rejects_malformed_codes_and_secrets() {
+        let secret = BASE32_NOPAD.encode(RFC_KEY);
+
+        assert!(verify_totp(&secret, "12345").is_none());
+        assert!(verify_totp(&secret, "12a456").is_none());
+        assert!(verify_totp("not base32!", "123456").is_none());
+    }
+}
@@ -1 +1,17 @@
-pub mod email_service;
\ No newline at end of file
+pub mod email_service;
+pub mod token_service;
+pub mod password_service;
+pub mod throttle_service;
+pub mod mfa_service;
+pub mod oidc_service;
+pub mod signing_key_service;
+pub mod search_service;
+pub mod task_service;
+pub mod recurrence_service;
+pub mod time_zone_service;
+pub mod reminder_service;
+pub mod scheduler_service;
+pub mod mailer_service;
+pub mod template_service;
+pub mod outbox_service;
+pub mod unsubscribe_service;
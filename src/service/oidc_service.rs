@@ -0,0 +1,364 @@
+use std::fmt;
+use std::time::{Duration, Instant};
+
+use base64::Engine;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use jsonwebtoken::{Algorithm, decode, decode_header, DecodingKey, Validation};
+use jsonwebtoken::errors::Error as JwtError;
+use jsonwebtoken::jwk::JwkSet;
+use log::debug;
+use reqwest::Error as HttpError;
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use tokio::sync::RwLock;
+use url::Url;
+
+/// How long the discovery document and the provider keys are reused before fetching them again.
+const METADATA_CACHE_TTL: Duration = Duration::from_secs(60 * 60);
+/// Minimum time between two key refreshes triggered by an unknown `kid`.
+const JWKS_MIN_REFRESH_INTERVAL: Duration = Duration::from_secs(60);
+
+#[derive(Debug)]
+pub enum OidcError {
+    Http(HttpError),
+    Jwt(JwtError),
+    InvalidResponse(String),
+}
+
+impl From<HttpError> for OidcError {
+    fn from(error: HttpError) -> Self {
+        OidcError::Http(error)
+    }
+}
+
+impl From<JwtError> for OidcError {
+    fn from(error: JwtError) -> Self {
+        OidcError::Jwt(error)
+    }
+}
+
+impl fmt::Display for OidcError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OidcError::Http(e) => write!(f, "Identity provider request failed: {}", e),
+            OidcError::Jwt(e) => write!(f, "Invalid ID token: {}", e),
+            OidcError::InvalidResponse(message) => write!(f, "Invalid identity provider response: {}", message),
+        }
+    }
+}
+
+pub struct OidcConfig {
+    pub issuer: String,
+    pub client_id: String,
+    pub client_secret: String,
+    pub redirect_uri: String,
+    pub scopes: String,
+    /// Create accounts for unknown users instead of rejecting them.
+    pub auto_provision: bool,
+}
+
+impl OidcConfig {
+    /// Single sign-on is only enabled when `OIDC_ISSUER` is set.
+    pub fn from_env() -> Option<Self> {
+        let issuer = std::env::var("OIDC_ISSUER").ok()?;
+
+        Some(OidcConfig {
+            issuer,
+            client_id: std::env::var("OIDC_CLIENT_ID").expect("OIDC_CLIENT_ID not provided"),
+            client_secret: std::env::var("OIDC_CLIENT_SECRET").expect("OIDC_CLIENT_SECRET not provided"),
+            redirect_uri: std::env::var("OIDC_REDIRECT_URI").expect("OIDC_REDIRECT_URI not provided"),
+            scopes: std::env::var("OIDC_SCOPES").unwrap_or_else(|_| String::from("openid email profile")),
+            auto_provision: std::env::var("OIDC_AUTO_PROVISION").map(|value| value != "false").unwrap_or(true),
+        })
+    }
+}
+
+#[derive(Deserialize, Clone)]
+pub struct ProviderMetadata {
+    pub issuer: String,
+    pub authorization_endpoint: String,
+    pub token_endpoint: String,
+    pub jwks_uri: String,
+}
+
+#[derive(Deserialize)]
+struct TokenEndpointResponse {
+    id_token: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct IdTokenClaims {
+    pub sub: String,
+    pub email: Option<String>,
+    #[serde(default)]
+    pub email_verified: bool,
+    pub nonce: Option<String>,
+}
+
+struct Cached<T> {
+    value: T,
+    fetched_at: Instant,
+}
+
+/// Relying party side of the authorization-code flow. Discovery document and keys are
+/// cached, so a client is meant to be built once and shared.
+pub struct OidcClient {
+    pub config: OidcConfig,
+    http: reqwest::Client,
+    metadata: RwLock<Option<Cached<ProviderMetadata>>>,
+    jwks: RwLock<Option<Cached<JwkSet>>>,
+}
+
+impl OidcClient {
+    pub fn new(config: OidcConfig) -> Self {
+        OidcClient {
+            config,
+            http: reqwest::Client::new(),
+            metadata: RwLock::new(None),
+            jwks: RwLock::new(None),
+        }
+    }
+
+    pub async fn metadata(&self) -> Result<ProviderMetadata, OidcError> {
+        if let Some(cached) = self.metadata.read().await.as_ref() {
+            if cached.fetched_at.elapsed() < METADATA_CACHE_TTL {
+                return Ok(cached.value.clone());
+            }
+        }
+
+        let url = format!("{}/.well-known/openid-configuration", self.config.issuer.trim_end_matches('/'));
+        debug!("Fetching OpenID provider metadata from {}", url);
+        let metadata: ProviderMetadata = self.http.get(&url).send().await?
+            .error_for_status()?
+            .json().await?;
+
+        // ID tokens carry the issuer exactly as discovered, trailing slash included
+        if metadata.issuer != self.config.issuer {
+            return Err(OidcError::InvalidResponse(format!("unexpected issuer {}", metadata.issuer)));
+        }
+
+        *self.metadata.write().await = Some(Cached { value: metadata.clone(), fetched_at: Instant::now() });
+        Ok(metadata)
+    }
+
+    async fn jwks(&self, force_refresh: bool) -> Result<JwkSet, OidcError> {
+        if let Some(cached) = self.jwks.read().await.as_ref() {
+            let max_age = if force_refresh { JWKS_MIN_REFRESH_INTERVAL } else { METADATA_CACHE_TTL };
+            if cached.fetched_at.elapsed() < max_age {
+                return Ok(cached.value.clone());
+            }
+        }
+
+        let metadata = self.metadata().await?;
+        debug!("Fetching OpenID provider keys from {}", metadata.jwks_uri);
+        let jwks: JwkSet = self.http.get(&metadata.jwks_uri).send().await?
+            .error_for_status()?
+            .json().await?;
+
+        *self.jwks.write().await = Some(Cached { value: jwks.clone(), fetched_at: Instant::now() });
+        Ok(jwks)
+    }
+
+    pub async fn authorization_url(&self, state: &str, nonce: &str, code_verifier: &str) -> Result<String, OidcError> {
+        let metadata = self.metadata().await?;
+        let mut url = Url::parse(&metadata.authorization_endpoint)
+            .map_err(|e| OidcError::InvalidResponse(e.to_string()))?;
+
+        url.query_pairs_mut()
+            .append_pair("response_type", "code")
+            .append_pair("client_id", &self.config.client_id)
+            .append_pair("redirect_uri", &self.config.redirect_uri)
+            .append_pair("scope", &self.config.scopes)
+            .append_pair("state", state)
+            .append_pair("nonce", nonce)
+            .append_pair("code_challenge", &code_challenge(code_verifier))
+            .append_pair("code_challenge_method", "S256");
+
+        Ok(url.to_string())
+    }
+
+    /// Redeems the authorization code and returns the verified claims of the ID token.
+    pub async fn exchange_code(&self, code: &str, code_verifier: &str, nonce: &str) -> Result<IdTokenClaims, OidcError> {
+        let metadata = self.metadata().await?;
+        let params = [
+            ("grant_type", "authorization_code"),
+            ("code", code),
+            ("redirect_uri", &self.config.redirect_uri),
+            ("client_id", &self.config.client_id),
+            ("client_secret", &self.config.client_secret),
+            ("code_verifier", code_verifier),
+        ];
+
+        let response: TokenEndpointResponse = self.http.post(&metadata.token_endpoint)
+            .form(&params)
+            .send().await?
+            .error_for_status()?
+            .json().await?;
+
+        let id_token = response.id_token
+            .ok_or_else(|| OidcError::InvalidResponse(String::from("no id_token in token response")))?;
+        self.verify_id_token(&id_token, nonce).await
+    }
+
+    async fn verify_id_token(&self, id_token: &str, nonce: &str) -> Result<IdTokenClaims, OidcError> {
+        let header = decode_header(id_token)?;
+        if matches!(header.alg, Algorithm::HS256 | Algorithm::HS384 | Algorithm::HS512) {
+            return Err(OidcError::InvalidResponse(String::from("symmetric ID token signatures are not accepted")));
+        }
+        let kid = header.kid
+            .ok_or_else(|| OidcError::InvalidResponse(String::from("ID token has no kid")))?;
+
+        // an unknown kid usually means the provider rotated its keys
+        let jwk = match self.jwks(false).await?.find(&kid) {
+            Some(jwk) => jwk.clone(),
+            None => self.jwks(true).await?.find(&kid).cloned()
+                .ok_or_else(|| OidcError::InvalidResponse(format!("unknown signing key {}", kid)))?
+        };
+
+        let metadata = self.metadata().await?;
+        let mut validation = Validation::new(header.alg);
+        validation.set_audience(&[&self.config.client_id]);
+        validation.set_issuer(&[&metadata.issuer]);
+        let claims = decode::<IdTokenClaims>(id_token, &DecodingKey::from_jwk(&jwk)?, &validation)?.claims;
+
+        if claims.nonce.as_deref() != Some(nonce) {
+            return Err(OidcError::InvalidResponse(String::from("nonce mismatch")));
+        }
+
+        Ok(claims)
+    }
+}
+
+fn code_challenge(code_verifier: &str) -> String {
+    URL_SAFE_NO_PAD.encode(Sha256::digest(code_verifier.as_bytes()))
+}
+
+#[cfg(test)]
+mod tests {
+    use jsonwebtoken::{encode, EncodingKey, Header};
+    use jsonwebtoken::jwk::{AlgorithmParameters, CommonParameters, EllipticCurve, Jwk, OctetKeyPairParameters,
+                            OctetKeyPairType};
+    use ring::rand::SystemRandom;
+    use ring::signature::{Ed25519KeyPair, KeyPair};
+    use serde_json::json;
+
+    use super::*;
+
+    const ISSUER: &str = "https://id.example.com/";
+    const CLIENT_ID: &str = "taskr";
+    const KID: &str = "test-key";
+
+    /// Client with the discovery document and an Ed25519 key already cached, so nothing
+    /// is fetched, and the PKCS#8 document of that key to sign ID tokens with.
+    async fn client() -> (OidcClient, Vec<u8>) {
+        let config = OidcConfig {
+            issuer: ISSUER.to_string(),
+            client_id: CLIENT_ID.to_string(),
+            client_secret: String::from("secret"),
+            redirect_uri: String::from("https://taskr.example.com/auth/oidc/callback"),
+            scopes: String::from("openid email"),
+            auto_provision: false,
+        };
+        let client = OidcClient::new(config);
+
+        let metadata = ProviderMetadata {
+            issuer: ISSUER.to_string(),
+            authorization_endpoint: String::from("https://id.example.com/authorize"),
+            token_endpoint: String::from("https://id.example.com/token"),
+            jwks_uri: String::from("https://id.example.com/jwks"),
+        };
+        *client.metadata.write().await = Some(Cached { value: metadata, fetched_at: Instant::now() });
+
+        let pkcs8 = Ed25519KeyPair::generate_pkcs8(&SystemRandom::new()).unwrap();
+        let key_pair = Ed25519KeyPair::from_pkcs8(pkcs8.as_ref()).unwrap();
+        let jwk = Jwk {
+            common: CommonParameters { key_id: Some(KID.to_string()), ..Default::default() },
+            algorithm: AlgorithmParameters::OctetKeyPair(OctetKeyPairParameters {
+                key_type: OctetKeyPairType::OctetKeyPair,
+                curve: EllipticCurve::Ed25519,
+                x: URL_SAFE_NO_PAD.encode(key_pair.public_key().as_ref()),
+            }),
+        };
+        *client.jwks.write().await = Some(Cached { value: JwkSet { keys: vec![jwk] }, fetched_at: Instant::now() });
+
+        (client, pkcs8.as_ref().to_vec())
+    }
+
+    fn id_token(pkcs8: &[u8], issuer: &str, audience: &str, nonce: &str) -> String {
+        let mut header = Header::new(Algorithm::EdDSA);
+        header.kid = Some(KID.to_string());
+        let claims = json!({
+            "iss": issuer,
+            "aud": audience,
+            "sub": "subject",
+            "email": "user@example.com",
+            "email_verified": true,
+            "nonce": nonce,
+            "exp": chrono::Utc::now().timestamp() + 300,
+        });
+        encode(&header, &claims, &EncodingKey::from_ed_der(pkcs8)).unwrap()
+    }
+
+    #[test]
+    fn code_challenge_matches_rfc_7636_example() {
+        assert_eq!(code_challenge("dBjftJeZ4CVP-mB92K27uhbUJU1p1r_wW1gFWFOEjXk"),
+                   "E9Melhoa2OwvFrEMTJguCHaoeK1t8URWbuGJSstw-cM");
+    }
+
+    #[tokio::test]
+    async fn authorization_url_carries_state_nonce_and_challenge() {
+        let (client, _) = client().await;
+        let url = Url::parse(&client.authorization_url("the-state", "the-nonce", "the-verifier").await.unwrap()).unwrap();
+        let query: Vec<(String, String)> = url.query_pairs().into_owned().collect();
+
+        assert!(query.contains(&(String::from("state"), String::from("the-state"))));
+        assert!(query.contains(&(String::from("nonce"), String::from("the-nonce"))));
+        assert!(query.contains(&(String::from("code_challenge"), code_challenge("the-verifier"))));
+        assert!(query.contains(&(String::from("code_challenge_method"), String::from("S256"))));
+    }
+
+    #[tokio::test]
+    async fn accepts_token_from_discovered_issuer() {
+        let (client, pkcs8) = client().await;
+        let token = id_token(&pkcs8, ISSUER, CLIENT_ID, "nonce");
+
+        let claims = client.verify_id_token(&token, "nonce").await.unwrap();
+        assert_eq!(claims.sub, "subject");
+        assert!(claims.email_verified);
+    }
+
+    #[tokio::test]
+    async fn issuer_must_match_exactly() {
+        let (client, pkcs8) = client().await;
+        let token = id_token(&pkcs8, ISSUER.trim_end_matches('/'), CLIENT_ID, "nonce");
+
+        assert!(client.verify_id_token(&token, "nonce").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn rejects_other_audience() {
+        let (client, pkcs8) = client().await;
+        let token = id_token(&pkcs8, ISSUER, "another-client", "nonce");
+
+        assert!(client.verify_id_token(&token, "nonce").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn rejects_nonce_of_another_sign_in() {
+        let (client, pkcs8) = client().await;
+        let token = id_token(&pkcs8, ISSUER, CLIENT_ID, "nonce");
+
+        assert!(matches!(client.verify_id_token(&token, "other-nonce").await, Err(OidcError::InvalidResponse(_))));
+    }
+
+    #[tokio::test]
+    async fn rejects_symmetric_signatures() {
+        let (client, _) = client().await;
+        let mut header = Header::new(Algorithm::HS256);
+        header.kid = Some(KID.to_string());
+        let token = encode(&header, &json!({ "iss": ISSUER, "aud": CLIENT_ID, "nonce": "nonce" }),
+                           &EncodingKey::from_secret(b"secret")).unwrap();
+
+        assert!(matches!(client.verify_id_token(&token, "nonce").await, Err(OidcError::InvalidResponse(_))));
+    }
+}
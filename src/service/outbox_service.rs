@@ -0,0 +1,168 @@
+use std::time::Duration;
+
+use actix_web::web::Data;
+use chrono::Utc;
+use log::{debug, error, info, warn};
+use mongodb::bson::{self, DateTime};
+use mongodb::bson::oid::ObjectId;
+use mongodb::error::Error as MongoError;
+use serde::Serialize;
+use tokio::sync::Notify;
+use tokio::time::sleep;
+
+use crate::model::outbox_model::{DeliveryStatus, MailKind, OutboxMessage};
+use crate::repository::outbox_repository::OutboxRepository;
+use crate::service::email_service::{link_ttl, send_email};
+use crate::service::mailer_service::Mailer;
+use crate::service::unsubscribe_service::UnsubscribeLinks;
+
+/// Attempts before a mail goes to the dead-letter state.
+const MAX_ATTEMPTS: u32 = 10;
+const FIRST_RETRY_DELAY_MINUTES: i64 = 1;
+const MAX_RETRY_DELAY_MINUTES: i64 = 6 * 60;
+/// How long an attempt may take before the mail is picked up again.
+const SEND_TIMEOUT_MINUTES: i64 = 5;
+/// Longest the worker sleeps without looking at the outbox, in case a wake-up got lost.
+const MAX_IDLE: Duration = Duration::from_secs(5 * 60);
+
+/// Mails are never sent from the code that wants them sent. They go to the outbox, which
+/// survives restarts, and the outbox worker sends them, retrying failed attempts.
+pub struct Outbox {
+    repo: OutboxRepository,
+    unsubscribe_links: UnsubscribeLinks,
+    wake: Notify,
+}
+
+impl Outbox {
+    pub async fn init(unsubscribe_links: UnsubscribeLinks) -> Self {
+        Outbox {
+            repo: OutboxRepository::init().await,
+            unsubscribe_links,
+            wake: Notify::new(),
+        }
+    }
+
+    pub async fn enqueue<T: Serialize>(&self, user_id: Option<&ObjectId>, kind: MailKind, to: &str, subject: &str,
+                                       template: &str, data: &T) -> Result<(), MongoError> {
+        // every notification links to where it can be turned off, in the body and the headers
+        let unsubscribe_url = user_id.zip(kind.notification())
+            .map(|(user_id, notification)| self.unsubscribe_links.url(user_id, notification));
+        let mut data = bson::to_document(data)?;
+        if let Some(url) = &unsubscribe_url {
+            data.insert("unsubscribe_url", url);
+        }
+
+        let now = Utc::now();
+        let message = OutboxMessage {
+            id: None,
+            user_id: user_id.copied(),
+            kind,
+            to: to.to_string(),
+            subject: subject.to_string(),
+            template: template.to_string(),
+            data,
+            unsubscribe_url,
+            status: DeliveryStatus::Pending,
+            attempts: 0,
+            next_attempt_at: DateTime::from_chrono(now),
+            last_error: None,
+            created_at: DateTime::from_chrono(now),
+            sent_at: None,
+            expires_at: link_ttl(kind).map(|ttl| DateTime::from_chrono(now + ttl)),
+        };
+        self.repo.enqueue(message).await?;
+        self.wake.notify_one();
+
+        Ok(())
+    }
+
+    /// Mails in the outbox, newest first, optionally only those of one user or in one state.
+    pub async fn delivery_log(&self, user_id: Option<&ObjectId>, status: Option<DeliveryStatus>,
+                              skip: u64, limit: i64) -> Result<Vec<OutboxMessage>, MongoError> {
+        self.repo.search(user_id, status, skip, limit).await
+    }
+}
+
+/// Sends whatever is due in the outbox, then sleeps until the next retry is due or
+/// something new is enqueued.
+pub async fn outbox_worker(outbox: Data<Outbox>, mailer: Data<Mailer>) {
+    info!("Outbox worker is active");
+
+    loop {
+        deliver_due(&outbox.repo, &mailer).await;
+
+        let wait = match outbox.repo.next_attempt_at().await {
+            Ok(Some(next)) => (next - Utc::now()).to_std().unwrap_or(Duration::ZERO).min(MAX_IDLE),
+            Ok(None) => MAX_IDLE,
+            Err(e) => {
+                error!("Error in outbox worker while looking for the next mail: {}", e);
+                MAX_IDLE
+            }
+        };
+
+        tokio::select! {
+            _ = sleep(wait) => (),
+            _ = outbox.wake.notified() => (),
+        }
+    }
+}
+
+async fn deliver_due(repo: &OutboxRepository, mailer: &Mailer) {
+    // mails whose attempts all crashed the worker, or whose link expired, are never claimed again
+    match repo.abandon_stale(Utc::now(), MAX_ATTEMPTS).await {
+        Ok(0) => (),
+        Ok(count) => warn!("Gave up on {} mails that ran out of attempts or expired", count),
+        Err(e) => error!("Error in outbox worker while dropping stale mails: {}", e),
+    }
+
+    loop {
+        let now = Utc::now();
+        let lease_until = now + chrono::Duration::minutes(SEND_TIMEOUT_MINUTES);
+        let message = match repo.claim_due(now, lease_until, MAX_ATTEMPTS).await {
+            Ok(Some(message)) => message,
+            Ok(None) => return,
+            Err(e) => {
+                error!("Error in outbox worker while fetching mails: {}", e);
+                return;
+            }
+        };
+
+        let result = match send_email(mailer, &message).await {
+            Ok(()) => {
+                debug!("{} mail {} sent to {}", message.kind, message.id.unwrap(), message.to);
+                repo.mark_sent(&message, Utc::now()).await
+            }
+            Err(e) => {
+                let retry_at = if e.is_permanent() {
+                    None
+                } else {
+                    retry_delay(message.attempts).map(|delay| Utc::now() + delay)
+                };
+                match retry_at {
+                    Some(retry_at) => warn!("Sending mail {} to {} failed, retrying at {}: {}",
+                                            message.id.unwrap(), message.to, retry_at, e),
+                    None => error!("Giving up on mail {} to {} after {} attempts: {}",
+                                   message.id.unwrap(), message.to, message.attempts, e),
+                }
+                repo.mark_failed(&message, &e.to_string(), retry_at).await
+            }
+        };
+
+        if let Err(e) = result {
+            error!("Error in outbox worker while recording the outcome of mail {}: {}", message.id.unwrap(), e);
+        }
+    }
+}
+
+/// Delay after the given number of failed attempts: one minute, doubling with every
+/// attempt up to six hours. `None` once the mail has had all its attempts.
+fn retry_delay(attempts: u32) -> Option<chrono::Duration> {
+    if attempts >= MAX_ATTEMPTS {
+        return None;
+    }
+    let minutes = FIRST_RETRY_DELAY_MINUTES
+        .saturating_mul(1 << attempts.saturating_sub(1).min(20))
+        .min(MAX_RETRY_DELAY_MINUTES);
+
+    Some(chrono::Duration::minutes(minutes))
+}
@@ -0,0 +1,35 @@
+use std::sync::OnceLock;
+
+use argonautica::{Hasher, Verifier};
+use argonautica::Error as HashError;
+
+pub fn hash_password(password: &str) -> Result<String, HashError> {
+    let hash_secret = std::env::var("HASH_SECRET").expect("HASH_SECRET not provided");
+    let mut hasher = Hasher::default();
+
+    hasher
+        .with_password(password)
+        .with_secret_key(hash_secret)
+        .hash()
+}
+
+pub fn verify_password(password_hash: &str, password: &str) -> Result<bool, HashError> {
+    let hash_secret = std::env::var("HASH_SECRET").expect("HASH_SECRET not provided");
+    let mut verifier = Verifier::default();
+
+    verifier
+        .with_hash(password_hash)
+        .with_password(password)
+        .with_secret_key(hash_secret)
+        .verify()
+}
+
+/// Checks `password` against the hash of a password nobody has, for sign-ins to accounts
+/// that don't exist. It takes as long as checking a real password, so the response time
+/// doesn't tell whether an account exists.
+pub fn verify_dummy_password(password: &str) {
+    static DUMMY_HASH: OnceLock<String> = OnceLock::new();
+    let hash = DUMMY_HASH.get_or_init(|| hash_password("no account has this password").unwrap_or_default());
+
+    let _ = verify_password(hash, password);
+}
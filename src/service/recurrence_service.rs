@@ -0,0 +1,143 @@
+use chrono::{Datelike, Duration, NaiveDate};
+
+use crate::model::task_model::{Frequency, Recurrence};
+
+/// Due date and rule of the occurrence after the one due on `due_date`, `None` once the
+/// series is over.
+pub fn next_occurrence(rule: &Recurrence, due_date: NaiveDate) -> Option<(NaiveDate, Recurrence)> {
+    if rule.count.is_some_and(|count| count <= 1) {
+        return None;
+    }
+
+    let interval = rule.interval.max(1);
+    let next_date = match rule.frequency {
+        Frequency::Daily => due_date.checked_add_signed(Duration::days(interval as i64))?,
+        Frequency::Weekly => next_weekly(due_date, &rule.weekdays, interval)?,
+        Frequency::Monthly => {
+            let day = rule.month_day.unwrap_or(due_date.day());
+            // a later day in the same month is still part of this interval
+            match clamped_date(due_date.year(), due_date.month(), day) {
+                Some(date) if date > due_date => date,
+                _ => {
+                    let months = due_date.year() * 12 + due_date.month0() as i32 + interval as i32;
+                    clamped_date(months.div_euclid(12), months.rem_euclid(12) as u32 + 1, day)?
+                }
+            }
+        }
+        Frequency::Yearly => {
+            let day = rule.month_day.unwrap_or(due_date.day());
+            clamped_date(due_date.year() + interval as i32, due_date.month(), day)?
+        }
+    };
+
+    if rule.until.is_some_and(|until| next_date > until) {
+        return None;
+    }
+
+    let mut next_rule = rule.clone();
+    next_rule.count = rule.count.map(|count| count - 1);
+    Some((next_date, next_rule))
+}
+
+/// First of the weekdays after `due_date` in the same week, or in the week `interval`
+/// weeks later. Weeks start on Monday.
+fn next_weekly(due_date: NaiveDate, weekdays: &[chrono::Weekday], interval: u32) -> Option<NaiveDate> {
+    let days: Vec<u32> = if weekdays.is_empty() {
+        vec![due_date.weekday().num_days_from_monday()]
+    } else {
+        weekdays.iter().map(|day| day.num_days_from_monday()).collect()
+    };
+
+    let current = due_date.weekday().num_days_from_monday();
+    if let Some(later) = days.iter().filter(|day| **day > current).min() {
+        return due_date.checked_add_signed(Duration::days((later - current) as i64));
+    }
+
+    let week_start = due_date - Duration::days(current as i64);
+    let first = *days.iter().min()?;
+    week_start.checked_add_signed(Duration::weeks(interval as i64) + Duration::days(first as i64))
+}
+
+/// The given day of the month, or the last day of months that are shorter.
+fn clamped_date(year: i32, month: u32, day: u32) -> Option<NaiveDate> {
+    (1..=day.min(31)).rev().find_map(|day| NaiveDate::from_ymd_opt(year, month, day))
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Weekday;
+
+    use super::*;
+
+    fn date(value: &str) -> NaiveDate {
+        NaiveDate::parse_from_str(value, "%Y-%m-%d").unwrap()
+    }
+
+    fn rule(frequency: Frequency, interval: u32) -> Recurrence {
+        Recurrence { frequency, interval, weekdays: Vec::new(), month_day: None, count: None, until: None }
+    }
+
+    fn next_date(rule: &Recurrence, due_date: &str) -> Option<NaiveDate> {
+        next_occurrence(rule, date(due_date)).map(|(next, _)| next)
+    }
+
+    #[test]
+    fn daily_adds_the_interval() {
+        assert_eq!(next_date(&rule(Frequency::Daily, 2), "2024-01-30"), Some(date("2024-02-01")));
+    }
+
+    #[test]
+    fn weekly_moves_to_the_next_weekday_then_skips_weeks() {
+        let mut rule = rule(Frequency::Weekly, 2);
+        rule.weekdays = vec![Weekday::Mon, Weekday::Wed, Weekday::Fri];
+
+        // Wednesday to Friday of the same week
+        assert_eq!(next_date(&rule, "2024-01-03"), Some(date("2024-01-05")));
+        // Friday to Monday two weeks after the week's Monday
+        assert_eq!(next_date(&rule, "2024-01-05"), Some(date("2024-01-15")));
+    }
+
+    #[test]
+    fn monthly_uses_the_last_day_of_short_months_without_drifting() {
+        let rule = rule(Frequency::Monthly, 1).anchored(date("2024-01-31"));
+
+        let (february, rule) = next_occurrence(&rule, date("2024-01-31")).unwrap();
+        assert_eq!(february, date("2024-02-29"));
+        assert_eq!(next_date(&rule, "2024-02-29"), Some(date("2024-03-31")));
+    }
+
+    #[test]
+    fn monthly_takes_a_later_day_in_the_same_month() {
+        let mut rule = rule(Frequency::Monthly, 3);
+        rule.month_day = Some(20);
+
+        assert_eq!(next_date(&rule, "2024-01-10"), Some(date("2024-01-20")));
+        assert_eq!(next_date(&rule, "2024-11-20"), Some(date("2025-02-20")));
+    }
+
+    #[test]
+    fn yearly_on_leap_day_falls_back_to_february_28th() {
+        let rule = rule(Frequency::Yearly, 1).anchored(date("2024-02-29"));
+
+        assert_eq!(next_date(&rule, "2024-02-29"), Some(date("2025-02-28")));
+    }
+
+    #[test]
+    fn count_is_used_up() {
+        let mut rule = rule(Frequency::Daily, 1);
+        rule.count = Some(2);
+
+        let (_, rule) = next_occurrence(&rule, date("2024-01-01")).unwrap();
+        assert_eq!(rule.count, Some(1));
+        assert!(next_occurrence(&rule, date("2024-01-02")).is_none());
+    }
+
+    #[test]
+    fn ends_after_until() {
+        let mut rule = rule(Frequency::Daily, 1);
+        rule.until = Some(date("2024-01-02"));
+
+        assert_eq!(next_date(&rule, "2024-01-01"), Some(date("2024-01-02")));
+        assert_eq!(next_date(&rule, "2024-01-02"), None);
+    }
+}
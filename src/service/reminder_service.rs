@@ -0,0 +1,120 @@
+use chrono::{DateTime, Datelike, Duration, NaiveDate, NaiveTime, Utc};
+use chrono_tz::Tz;
+
+use crate::model::user_model::{DigestFrequency, ReminderSettings};
+use crate::service::time_zone_service::{due_instant, local_instant};
+
+/// Days past the due date at which an open task is brought up again, each more urgently.
+pub const OVERDUE_ALERT_DAYS: [i64; 3] = [1, 3, 7];
+
+/// The first digest time after `after`, `None` if the settings name no day at all.
+pub fn next_digest_at(settings: &ReminderSettings, time_zone: Tz, after: DateTime<Utc>) -> Option<DateTime<Utc>> {
+    let local_today = after.with_timezone(&time_zone).date_naive();
+
+    // a week and a day always contain the next occurrence of every weekday
+    (0..=7)
+        .map(|offset| local_today + Duration::days(offset))
+        .filter(|date| settings.digest_days.contains(&date.weekday()))
+        .map(|date| local_instant(date, settings.digest_time, time_zone))
+        .find(|instant| *instant > after)
+}
+
+/// First and last due date covered by a digest sent on `today`.
+pub fn digest_window(settings: &ReminderSettings, today: NaiveDate) -> (NaiveDate, NaiveDate) {
+    let days_ahead = match settings.digest_frequency {
+        DigestFrequency::Daily => settings.days_ahead,
+        DigestFrequency::Weekly => settings.days_ahead.max(6),
+    };
+    (today, today + Duration::days(days_ahead as i64))
+}
+
+pub fn reminder_instant(due_at: DateTime<Utc>, remind_before_hours: u32) -> DateTime<Utc> {
+    due_at - Duration::hours(remind_before_hours as i64)
+}
+
+/// When the next overdue alert of a task goes out: the first of `OVERDUE_ALERT_DAYS` after
+/// its due date, counted in the user's zone, that is still after `now`. `None` once the
+/// last one has passed.
+pub fn next_overdue_alert(due_date: NaiveDate, due_time: Option<NaiveTime>, time_zone: Tz,
+                          now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+    OVERDUE_ALERT_DAYS.iter()
+        .map(|days| due_instant(due_date + Duration::days(*days), due_time, time_zone))
+        .find(|instant| *instant > now)
+}
+
+/// Whole days since the due date; 0 on the day itself.
+pub fn days_overdue(due_date: NaiveDate, today: NaiveDate) -> i64 {
+    (today - due_date).num_days().max(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Weekday;
+    use chrono_tz::Europe::Berlin;
+
+    use super::*;
+
+    fn date(value: &str) -> NaiveDate {
+        NaiveDate::parse_from_str(value, "%Y-%m-%d").unwrap()
+    }
+
+    fn instant(value: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(value).unwrap().with_timezone(&Utc)
+    }
+
+    fn mondays_at_six() -> ReminderSettings {
+        ReminderSettings {
+            digest_time: NaiveTime::from_hms_opt(6, 0, 0).unwrap(),
+            digest_days: vec![Weekday::Mon],
+            digest_frequency: DigestFrequency::Weekly,
+            days_ahead: 0,
+        }
+    }
+
+    #[test]
+    fn next_digest_is_later_the_same_day() {
+        // 05:00 in Berlin on a Monday
+        let next = next_digest_at(&mondays_at_six(), Berlin, instant("2024-01-01T04:00:00Z"));
+        assert_eq!(next, Some(instant("2024-01-01T05:00:00Z")));
+    }
+
+    #[test]
+    fn next_digest_is_a_week_later_once_the_time_has_passed() {
+        let next = next_digest_at(&mondays_at_six(), Berlin, instant("2024-01-01T05:00:00Z"));
+        assert_eq!(next, Some(instant("2024-01-08T05:00:00Z")));
+    }
+
+    #[test]
+    fn no_digest_without_days() {
+        let mut settings = mondays_at_six();
+        settings.digest_days.clear();
+
+        assert_eq!(next_digest_at(&settings, Berlin, instant("2024-01-01T04:00:00Z")), None);
+    }
+
+    #[test]
+    fn overdue_alerts_follow_the_alert_days() {
+        let due_date = date("2024-01-10");
+        let next = |now: &str| next_overdue_alert(due_date, None, Tz::UTC, instant(now));
+
+        assert_eq!(next("2024-01-10T12:00:00Z"), Some(instant("2024-01-11T23:59:59.999Z")));
+        assert_eq!(next("2024-01-12T00:00:00Z"), Some(instant("2024-01-13T23:59:59.999Z")));
+        assert_eq!(next("2024-01-16T00:00:00Z"), Some(instant("2024-01-17T23:59:59.999Z")));
+        assert_eq!(next("2024-01-18T00:00:00Z"), None);
+    }
+
+    #[test]
+    fn overdue_alerts_keep_the_due_time_in_the_zone() {
+        let due_time = NaiveTime::from_hms_opt(9, 30, 0);
+        let next = next_overdue_alert(date("2024-01-10"), due_time, Berlin, instant("2024-01-10T12:00:00Z"));
+
+        assert_eq!(next, Some(instant("2024-01-11T08:30:00Z")));
+    }
+
+    #[test]
+    fn days_overdue_counts_whole_days() {
+        assert_eq!(days_overdue(date("2024-01-10"), date("2024-01-09")), 0);
+        assert_eq!(days_overdue(date("2024-01-10"), date("2024-01-10")), 0);
+        assert_eq!(days_overdue(date("2024-01-10"), date("2024-01-13")), 3);
+    }
+}
@@ -0,0 +1,210 @@
+use std::time::Duration;
+
+use actix_web::web::Data;
+use chrono::{DateTime, Utc};
+use log::{debug, error, info};
+use tokio::sync::Notify;
+use tokio::time::sleep;
+
+use crate::model::task_model::Task;
+use crate::repository::task_repository::TaskRepository;
+use crate::repository::user_repository::UserRepository;
+use crate::service::email_service::{queue_digest_email, queue_overdue_alert_email, queue_reminder_email};
+use crate::service::outbox_service::Outbox;
+use crate::service::reminder_service::{digest_window, next_digest_at, next_overdue_alert};
+use crate::service::time_zone_service::{today, user_time_zone};
+
+/// Longest the scheduler sleeps without looking for work, so users that were never
+/// scheduled, e.g. because they just confirmed their address, are picked up soon.
+const MAX_IDLE: Duration = Duration::from_secs(15 * 60);
+
+/// Lets request handlers wake the scheduler when they change when something is due.
+#[derive(Default)]
+pub struct Scheduler {
+    wake: Notify,
+}
+
+impl Scheduler {
+    pub fn reschedule(&self) {
+        self.wake.notify_one();
+    }
+}
+
+/// Job queue for digests, task reminders and overdue alerts. Each user's next digest and
+/// each task's reminder and next overdue alert is stored with the time it is due; the
+/// scheduler queues the mails of whatever is due in the outbox and sleeps until the
+/// earliest of the remaining jobs.
+pub async fn notification_scheduler(user_repo: Data<UserRepository>, task_repo: Data<TaskRepository>,
+                                    outbox: Data<Outbox>, scheduler: Data<Scheduler>) {
+    info!("Scheduler is active");
+
+    loop {
+        let now = Utc::now();
+        send_due_digests(&user_repo, &task_repo, &outbox, now).await;
+        send_due_reminders(&user_repo, &task_repo, &outbox, now).await;
+        send_due_overdue_alerts(&user_repo, &task_repo, &outbox, now).await;
+
+        let next_digest = user_repo.next_digest_at().await.unwrap_or_else(|e| {
+            error!("Error in scheduler while looking for the next digest: {}", e);
+            None
+        });
+        let next_reminder = task_repo.next_reminder_at().await.unwrap_or_else(|e| {
+            error!("Error in scheduler while looking for the next reminder: {}", e);
+            None
+        });
+        let next_overdue_alert = task_repo.next_overdue_alert_at().await.unwrap_or_else(|e| {
+            error!("Error in scheduler while looking for the next overdue alert: {}", e);
+            None
+        });
+        let wait = [next_digest, next_reminder, next_overdue_alert].into_iter()
+            .flatten()
+            .min()
+            .map(|next_job| (next_job - Utc::now()).to_std().unwrap_or(Duration::ZERO))
+            .unwrap_or(MAX_IDLE)
+            .min(MAX_IDLE);
+
+        debug!("Scheduler finished. Next run in {}s", wait.as_secs());
+        tokio::select! {
+            _ = sleep(wait) => (),
+            _ = scheduler.wake.notified() => debug!("Scheduler woken up by a changed schedule"),
+        }
+    }
+}
+
+async fn send_due_digests(user_repo: &UserRepository, task_repo: &TaskRepository, outbox: &Outbox,
+                          now: DateTime<Utc>) {
+    let users = match user_repo.find_digest_due(now).await {
+        Ok(users) => users,
+        Err(e) => {
+            error!("Error in scheduler while fetching users: {}", e);
+            Vec::new()
+        }
+    };
+
+    for user in users {
+        let user_id = user.id.unwrap();
+        let time_zone = user_time_zone(&user);
+        let next = next_digest_at(&user.reminders, time_zone, now);
+
+        match user_repo.reschedule_digest(&user_id, user.next_digest_at, next).await {
+            Ok(true) => (),
+            // another run got to this user first
+            Ok(false) => continue,
+            Err(e) => {
+                error!("Error in scheduler while scheduling the digest of {}: {}", user.email, e);
+                continue;
+            }
+        }
+
+        // users seen for the first time only get their first digest scheduled
+        if user.next_digest_at.is_none() {
+            continue;
+        }
+
+        let (from, until) = digest_window(&user.reminders, today(time_zone));
+        let user_tasks = match task_repo.find_due_between(&user_id, from, until).await {
+            Ok(tasks) => tasks,
+            Err(e) => {
+                error!("Error in scheduler while fetching user\'s tasks: {}", e);
+                Vec::new()
+            }
+        };
+        let overdue_tasks = match task_repo.find_overdue(&user_id, now).await {
+            Ok(tasks) => tasks,
+            Err(e) => {
+                error!("Error in scheduler while fetching user\'s overdue tasks: {}", e);
+                Vec::new()
+            }
+        };
+        // tasks due earlier today are only listed as overdue
+        let user_tasks: Vec<Task> = user_tasks.into_iter()
+            .filter(|task| !overdue_tasks.iter().any(|overdue| overdue.id == task.id))
+            .collect();
+
+        if user_tasks.is_empty() && overdue_tasks.is_empty() {
+            continue;
+        }
+
+        match queue_digest_email(outbox, &user_id, &user.email, from, until, &user_tasks, &overdue_tasks).await {
+            Ok(_) => debug!("Digest queued for {} with {} tasks and {} overdue", user.email, user_tasks.len(),
+                            overdue_tasks.len()),
+            Err(e) => error!("Error queueing the digest of {}: {}", user.email, e)
+        };
+    }
+}
+
+async fn send_due_reminders(user_repo: &UserRepository, task_repo: &TaskRepository, outbox: &Outbox,
+                            now: DateTime<Utc>) {
+    loop {
+        let task = match task_repo.take_due_reminder(now).await {
+            Ok(Some(task)) => task,
+            Ok(None) => return,
+            Err(e) => {
+                error!("Error in scheduler while fetching reminders: {}", e);
+                return;
+            }
+        };
+
+        let user = match user_repo.find_by_id(&task.user_id).await {
+            Ok(Some(user)) if user.verified && !user.disabled && user.notifications.reminders => user,
+            Ok(_) => continue,
+            Err(e) => {
+                error!("Error in scheduler while fetching the user of a reminder: {}", e);
+                continue;
+            }
+        };
+
+        match queue_reminder_email(outbox, &user.email, &task, user_time_zone(&user)).await {
+            Ok(_) => debug!("Reminder queued for {} for task {}", user.email, task.id.unwrap()),
+            Err(e) => error!("Error queueing the reminder of {}: {}", user.email, e)
+        };
+    }
+}
+
+async fn send_due_overdue_alerts(user_repo: &UserRepository, task_repo: &TaskRepository, outbox: &Outbox,
+                                 now: DateTime<Utc>) {
+    loop {
+        let task = match task_repo.find_due_overdue_alert(now).await {
+            Ok(Some(task)) => task,
+            Ok(None) => return,
+            Err(e) => {
+                error!("Error in scheduler while fetching overdue alerts: {}", e);
+                return;
+            }
+        };
+
+        let user = match user_repo.find_by_id(&task.user_id).await {
+            Ok(user) => user,
+            Err(e) => {
+                error!("Error in scheduler while fetching the user of an overdue alert: {}", e);
+                return;
+            }
+        };
+
+        // the alert is replaced by the next one in a single write, so the escalation goes on
+        // whatever happens afterwards; the next alert is kept even while alerts are off, so
+        // turning them on picks it up
+        let next = user.as_ref()
+            .and_then(|user| next_overdue_alert(task.due_date, task.due_time, user_time_zone(user), now));
+        match task_repo.advance_overdue_alert(&task, next).await {
+            Ok(true) => (),
+            // another run got to the alert first
+            Ok(false) => continue,
+            Err(e) => {
+                error!("Error in scheduler while scheduling the next overdue alert: {}", e);
+                return;
+            }
+        }
+
+        let user = match user {
+            Some(user) if user.verified && !user.disabled && user.notifications.overdue_alerts => user,
+            _ => continue,
+        };
+        let time_zone = user_time_zone(&user);
+
+        match queue_overdue_alert_email(outbox, &user.email, &task, time_zone).await {
+            Ok(_) => debug!("Overdue alert queued for {} for task {}", user.email, task.id.unwrap()),
+            Err(e) => error!("Error queueing the overdue alert of {}: {}", user.email, e)
+        };
+    }
+}
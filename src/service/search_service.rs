@@ -0,0 +1,178 @@
+/// Characters of context kept around the first match when a text is cut down to a snippet.
+const SNIPPET_CONTEXT: usize = 60;
+const SNIPPET_MAX_CHARS: usize = 200;
+const ELLIPSIS: &str = "…";
+
+/// Words and phrases a Mongo `$text` query looks for, in the same syntax: double quoted
+/// phrases, `-` negated terms (dropped, they never show up in a match) and plain words.
+pub fn search_terms(query: &str) -> Vec<String> {
+    let mut terms = Vec::new();
+
+    for (index, part) in query.split('"').enumerate() {
+        if index % 2 == 1 {
+            if !part.trim().is_empty() {
+                terms.push(part.trim().to_lowercase());
+            }
+            continue;
+        }
+
+        terms.extend(part.split_whitespace()
+            .filter(|word| !word.starts_with('-'))
+            .map(|word| word.to_lowercase()));
+    }
+
+    terms
+}
+
+/// HTML-escaped excerpt of `text` with every occurrence of the terms wrapped in `<mark>`.
+/// Long texts are cut down to a window around the first match.
+pub fn highlight(text: &str, terms: &[String]) -> String {
+    let chars: Vec<char> = text.chars().collect();
+    let matches = find_matches(&chars, terms);
+
+    let (start, end) = if chars.len() <= SNIPPET_MAX_CHARS {
+        (0, chars.len())
+    } else {
+        let start = matches.first()
+            .map(|(match_start, _)| match_start.saturating_sub(SNIPPET_CONTEXT))
+            .unwrap_or(0);
+        let start = start.min(chars.len() - SNIPPET_MAX_CHARS);
+        (start, start + SNIPPET_MAX_CHARS)
+    };
+
+    let mut snippet = String::new();
+    if start > 0 {
+        snippet.push_str(ELLIPSIS);
+    }
+
+    let mut position = start;
+    for (match_start, match_end) in matches {
+        if match_end <= start || match_start >= end {
+            continue;
+        }
+        let (match_start, match_end) = (match_start.max(start), match_end.min(end));
+        push_escaped(&mut snippet, &chars[position..match_start]);
+        snippet.push_str("<mark>");
+        push_escaped(&mut snippet, &chars[match_start..match_end]);
+        snippet.push_str("</mark>");
+        position = match_end;
+    }
+    push_escaped(&mut snippet, &chars[position..end]);
+
+    if end < chars.len() {
+        snippet.push_str(ELLIPSIS);
+    }
+    snippet
+}
+
+/// Non-overlapping character ranges of all term occurrences, ignoring case.
+fn find_matches(chars: &[char], terms: &[String]) -> Vec<(usize, usize)> {
+    let lowered: Vec<char> = chars.iter()
+        .map(|c| c.to_lowercase().next().unwrap_or(*c))
+        .collect();
+
+    let mut matches = Vec::new();
+    for term in terms {
+        let term: Vec<char> = term.chars().collect();
+        if term.is_empty() || term.len() > lowered.len() {
+            continue;
+        }
+        for start in 0..=lowered.len() - term.len() {
+            if lowered[start..start + term.len()] == term[..] {
+                matches.push((start, start + term.len()));
+            }
+        }
+    }
+
+    // overlapping matches, e.g. of a word and a phrase containing it, become one
+    matches.sort_by(|a, b| a.0.cmp(&b.0).then(b.1.cmp(&a.1)));
+    let mut merged: Vec<(usize, usize)> = Vec::new();
+    for (start, end) in matches {
+        match merged.last_mut() {
+            Some(last) if start < last.1 => last.1 = last.1.max(end),
+            _ => merged.push((start, end)),
+        }
+    }
+    merged
+}
+
+fn push_escaped(out: &mut String, chars: &[char]) {
+    for c in chars {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&#39;"),
+            _ => out.push(*c),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn terms(terms: &[&str]) -> Vec<String> {
+        terms.iter().map(|term| term.to_string()).collect()
+    }
+
+    #[test]
+    fn search_terms_keep_phrases_and_drop_negated_words() {
+        assert_eq!(search_terms("\"Quick Brown\" fox -lazy"), terms(&["quick brown", "fox"]));
+        assert_eq!(search_terms("  \"\" Dog  "), terms(&["dog"]));
+    }
+
+    #[test]
+    fn short_texts_are_kept_whole() {
+        assert_eq!(highlight("Buy milk and bread", &terms(&["milk"])), "Buy <mark>milk</mark> and bread");
+    }
+
+    #[test]
+    fn long_texts_are_cut_around_the_first_match() {
+        let text = format!("{} needle {}", "a".repeat(300), "b".repeat(300));
+        let snippet = highlight(&text, &terms(&["needle"]));
+
+        assert!(snippet.starts_with(ELLIPSIS));
+        assert!(snippet.ends_with(ELLIPSIS));
+        assert!(snippet.contains(&format!("{} <mark>needle</mark> ", "a".repeat(SNIPPET_CONTEXT - 1))));
+        assert_eq!(snippet.replace("<mark>", "").replace("</mark>", "").chars().count(), SNIPPET_MAX_CHARS + 2);
+    }
+
+    #[test]
+    fn window_stays_inside_the_text() {
+        let text = format!("{}needle", "a".repeat(300));
+        let snippet = highlight(&text, &terms(&["needle"]));
+
+        assert!(snippet.starts_with(ELLIPSIS));
+        assert!(snippet.ends_with("<mark>needle</mark>"));
+
+        let snippet = highlight(&"a".repeat(300), &terms(&["needle"]));
+        assert_eq!(snippet, format!("{}{}", "a".repeat(SNIPPET_MAX_CHARS), ELLIPSIS));
+    }
+
+    #[test]
+    fn overlapping_matches_are_merged() {
+        let text = "The quick brown fox";
+
+        assert_eq!(highlight(text, &terms(&["quick brown", "brown fox"])), "The <mark>quick brown fox</mark>");
+        assert_eq!(highlight(text, &terms(&["brown", "quick brown"])), "The <mark>quick brown</mark> fox");
+    }
+
+    #[test]
+    fn text_and_matches_are_escaped() {
+        assert_eq!(highlight("<b>Tom & Jerry</b>", &terms(&["tom"])),
+                   "&lt;b&gt;<mark>Tom</mark> &amp; Jerry&lt;/b&gt;");
+        assert_eq!(highlight("x a&b 'y'", &terms(&["a&b"])), "x <mark>a&amp;b</mark> &#39;y&#39;");
+    }
+
+    #[test]
+    fn non_ascii_is_matched_and_cut_by_characters() {
+        assert_eq!(highlight("Größe prüfen vor Übergabe", &terms(&["über"])),
+                   "Größe prüfen vor <mark>Über</mark>gabe");
+
+        let text = format!("{}needle{}", "ü".repeat(250), "ü".repeat(250));
+        let snippet = highlight(&text, &terms(&["needle"]));
+        assert!(snippet.starts_with(&format!("{}{}<mark>", ELLIPSIS, "ü".repeat(SNIPPET_CONTEXT))));
+    }
+}
@@ -0,0 +1,279 @@
+use std::collections::HashMap;
+use std::ffi::OsStr;
+use std::fmt;
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+
+use base64::Engine;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use chrono::Utc;
+use jsonwebtoken::{Algorithm, DecodingKey, EncodingKey, Header};
+use jsonwebtoken::jwk::{AlgorithmParameters, CommonParameters, EllipticCurve, Jwk, JwkSet, OctetKeyPairParameters,
+                        OctetKeyPairType, PublicKeyUse, RSAKeyParameters, RSAKeyType};
+use log::{info, warn};
+use ring::rand::SystemRandom;
+use ring::signature::{Ed25519KeyPair, KeyPair, RsaKeyPair};
+use simple_asn1::{ASN1Block, from_der};
+
+const DEFAULT_KEY_DIR: &str = "keys";
+const RSA_ENCRYPTION_OID: [u64; 7] = [1, 2, 840, 113549, 1, 1, 1];
+const ED25519_OID: [u64; 4] = [1, 3, 101, 112];
+
+#[derive(Debug)]
+pub enum KeyError {
+    Io(std::io::Error),
+    Pem(pem::PemError),
+    Unsupported(String),
+}
+
+impl From<std::io::Error> for KeyError {
+    fn from(error: std::io::Error) -> Self {
+        KeyError::Io(error)
+    }
+}
+
+impl From<pem::PemError> for KeyError {
+    fn from(error: pem::PemError) -> Self {
+        KeyError::Pem(error)
+    }
+}
+
+impl fmt::Display for KeyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            KeyError::Io(e) => write!(f, "Could not read key: {}", e),
+            KeyError::Pem(e) => write!(f, "Invalid PEM file: {}", e),
+            KeyError::Unsupported(message) => write!(f, "Unsupported key: {}", message),
+        }
+    }
+}
+
+/// Public half of a key, enough to check signatures and to publish it.
+struct VerificationKey {
+    algorithm: Algorithm,
+    decoding_key: DecodingKey,
+    jwk: Jwk,
+}
+
+struct SigningKey {
+    kid: String,
+    algorithm: Algorithm,
+    encoding_key: EncodingKey,
+}
+
+/// Keys used for our own JWTs, loaded once at startup from `JWT_KEY_DIR`.
+///
+/// Every `<kid>.pem` file in the directory is a key, named by its `kid`. Private keys
+/// (PKCS#8 RSA or Ed25519, PKCS#1 RSA) can sign and verify, public keys (SPKI) only verify.
+/// Rotating means adding the new private key, pointing `JWT_SIGNING_KEY_ID` at it and
+/// keeping the old key around until the tokens it signed have expired.
+pub struct SigningKeys {
+    signing: SigningKey,
+    verification: HashMap<String, VerificationKey>,
+}
+
+impl SigningKeys {
+    pub fn load() -> Self {
+        let key_dir = std::env::var("JWT_KEY_DIR").unwrap_or_else(|_| String::from(DEFAULT_KEY_DIR));
+        let key_dir = Path::new(&key_dir);
+        fs::create_dir_all(key_dir).expect("JWT_KEY_DIR can't be created");
+
+        let mut private_keys = HashMap::new();
+        let mut verification = HashMap::new();
+        for entry in fs::read_dir(key_dir).expect("JWT_KEY_DIR can't be read") {
+            let path = entry.expect("JWT_KEY_DIR can't be read").path();
+            if path.extension() != Some(OsStr::new("pem")) {
+                continue;
+            }
+            let kid = path.file_stem().unwrap().to_string_lossy().to_string();
+
+            match load_key(&kid, &path) {
+                Ok((verification_key, signing_key)) => {
+                    info!("Loaded JWT key {} ({:?})", kid, verification_key.algorithm);
+                    if let Some(signing_key) = signing_key {
+                        private_keys.insert(kid.clone(), signing_key);
+                    }
+                    verification.insert(kid, verification_key);
+                }
+                Err(e) => panic!("JWT key {} can't be loaded: {}", path.display(), e),
+            }
+        }
+
+        // a fresh install gets a key generated so it works without any setup
+        if private_keys.is_empty() {
+            let kid = Utc::now().format("%Y%m%d%H%M%S").to_string();
+            let path = key_dir.join(format!("{}.pem", kid));
+            warn!("No JWT signing key found, generating {}", path.display());
+            generate_ed25519_key(&path).expect("JWT signing key can't be generated");
+
+            let (verification_key, signing_key) = load_key(&kid, &path).unwrap();
+            private_keys.insert(kid.clone(), signing_key.unwrap());
+            verification.insert(kid, verification_key);
+        }
+
+        let signing_kid = match std::env::var("JWT_SIGNING_KEY_ID") {
+            Ok(kid) => kid,
+            Err(_) if private_keys.len() == 1 => private_keys.keys().next().unwrap().clone(),
+            Err(_) => panic!("JWT_SIGNING_KEY_ID must be set when there are several private keys"),
+        };
+        let signing = private_keys.remove(&signing_kid)
+            .unwrap_or_else(|| panic!("No private key found for JWT_SIGNING_KEY_ID {}", signing_kid));
+
+        SigningKeys { signing, verification }
+    }
+
+    /// Header for newly signed tokens, naming the key that signs them.
+    pub fn header(&self) -> Header {
+        let mut header = Header::new(self.signing.algorithm);
+        header.kid = Some(self.signing.kid.clone());
+        header
+    }
+
+    pub fn encoding_key(&self) -> &EncodingKey {
+        &self.signing.encoding_key
+    }
+
+    /// Looks up the key named by `kid` together with the algorithm it has to be used with,
+    /// so the algorithm in the token header is never trusted on its own.
+    pub fn decoding_key(&self, kid: &str) -> Option<(Algorithm, &DecodingKey)> {
+        self.verification.get(kid).map(|key| (key.algorithm, &key.decoding_key))
+    }
+
+    pub fn jwks(&self) -> JwkSet {
+        JwkSet { keys: self.verification.values().map(|key| key.jwk.clone()).collect() }
+    }
+}
+
+fn load_key(kid: &str, path: &Path) -> Result<(VerificationKey, Option<SigningKey>), KeyError> {
+    let file = fs::read(path)?;
+    let pem = pem::parse(&file)?;
+
+    match pem.tag.as_str() {
+        "PRIVATE KEY" => {
+            if let Ok(key_pair) = Ed25519KeyPair::from_pkcs8_maybe_unchecked(&pem.contents) {
+                let signing_key = SigningKey {
+                    kid: kid.to_string(),
+                    algorithm: Algorithm::EdDSA,
+                    encoding_key: EncodingKey::from_ed_der(&pem.contents),
+                };
+                return Ok((ed25519_key(kid, key_pair.public_key().as_ref()), Some(signing_key)));
+            }
+
+            let key_pair = RsaKeyPair::from_pkcs8(&pem.contents)
+                .map_err(|e| KeyError::Unsupported(e.to_string()))?;
+            let public_key = key_pair.public_key();
+            let signing_key = SigningKey {
+                kid: kid.to_string(),
+                algorithm: Algorithm::RS256,
+                encoding_key: EncodingKey::from_rsa_pem(&file).map_err(|e| KeyError::Unsupported(e.to_string()))?,
+            };
+            let verification_key = rsa_key(kid, public_key.modulus().big_endian_without_leading_zero(),
+                                           public_key.exponent().big_endian_without_leading_zero());
+            Ok((verification_key, Some(signing_key)))
+        }
+        "RSA PRIVATE KEY" => {
+            let key_pair = RsaKeyPair::from_der(&pem.contents)
+                .map_err(|e| KeyError::Unsupported(e.to_string()))?;
+            let public_key = key_pair.public_key();
+            let signing_key = SigningKey {
+                kid: kid.to_string(),
+                algorithm: Algorithm::RS256,
+                encoding_key: EncodingKey::from_rsa_der(&pem.contents),
+            };
+            let verification_key = rsa_key(kid, public_key.modulus().big_endian_without_leading_zero(),
+                                           public_key.exponent().big_endian_without_leading_zero());
+            Ok((verification_key, Some(signing_key)))
+        }
+        "PUBLIC KEY" => Ok((public_key(kid, &pem.contents)?, None)),
+        tag => Err(KeyError::Unsupported(format!("unexpected PEM block {}", tag))),
+    }
+}
+
+/// Reads a SubjectPublicKeyInfo structure holding an RSA or Ed25519 key.
+fn public_key(kid: &str, der: &[u8]) -> Result<VerificationKey, KeyError> {
+    let invalid = || KeyError::Unsupported(String::from("malformed public key"));
+
+    let blocks = from_der(der).map_err(|_| invalid())?;
+    let (algorithm, key) = match blocks.first() {
+        Some(ASN1Block::Sequence(_, items)) => match items.as_slice() {
+            [ASN1Block::Sequence(_, algorithm), ASN1Block::BitString(_, _, key)] => (algorithm, key),
+            _ => return Err(invalid()),
+        },
+        _ => return Err(invalid()),
+    };
+    let oid = match algorithm.first() {
+        Some(ASN1Block::ObjectIdentifier(_, oid)) => oid.as_vec::<u64>().map_err(|_| invalid())?,
+        _ => return Err(invalid()),
+    };
+
+    if oid == ED25519_OID {
+        return Ok(ed25519_key(kid, key));
+    }
+    if oid != RSA_ENCRYPTION_OID {
+        return Err(KeyError::Unsupported(String::from("only RSA and Ed25519 keys are supported")));
+    }
+
+    // the bit string holds an RSAPublicKey, a sequence of modulus and exponent
+    match from_der(key).map_err(|_| invalid())?.first() {
+        Some(ASN1Block::Sequence(_, items)) => match items.as_slice() {
+            [ASN1Block::Integer(_, modulus), ASN1Block::Integer(_, exponent)] =>
+                Ok(rsa_key(kid, &modulus.to_bytes_be().1, &exponent.to_bytes_be().1)),
+            _ => Err(invalid()),
+        },
+        _ => Err(invalid()),
+    }
+}
+
+fn rsa_key(kid: &str, modulus: &[u8], exponent: &[u8]) -> VerificationKey {
+    VerificationKey {
+        algorithm: Algorithm::RS256,
+        decoding_key: DecodingKey::from_rsa_raw_components(modulus, exponent),
+        jwk: Jwk {
+            common: jwk_common(kid, Algorithm::RS256),
+            algorithm: AlgorithmParameters::RSA(RSAKeyParameters {
+                key_type: RSAKeyType::RSA,
+                n: URL_SAFE_NO_PAD.encode(modulus),
+                e: URL_SAFE_NO_PAD.encode(exponent),
+            }),
+        },
+    }
+}
+
+fn ed25519_key(kid: &str, public_key: &[u8]) -> VerificationKey {
+    VerificationKey {
+        algorithm: Algorithm::EdDSA,
+        decoding_key: DecodingKey::from_ed_der(public_key),
+        jwk: Jwk {
+            common: jwk_common(kid, Algorithm::EdDSA),
+            algorithm: AlgorithmParameters::OctetKeyPair(OctetKeyPairParameters {
+                key_type: OctetKeyPairType::OctetKeyPair,
+                curve: EllipticCurve::Ed25519,
+                x: URL_SAFE_NO_PAD.encode(public_key),
+            }),
+        },
+    }
+}
+
+fn jwk_common(kid: &str, algorithm: Algorithm) -> CommonParameters {
+    CommonParameters {
+        public_key_use: Some(PublicKeyUse::Signature),
+        algorithm: Some(algorithm),
+        key_id: Some(kid.to_string()),
+        ..Default::default()
+    }
+}
+
+fn generate_ed25519_key(path: &Path) -> Result<(), KeyError> {
+    let pkcs8 = Ed25519KeyPair::generate_pkcs8(&SystemRandom::new())
+        .map_err(|_| KeyError::Unsupported(String::from("key generation failed")))?;
+    let pem = pem::Pem { tag: String::from("PRIVATE KEY"), contents: pkcs8.as_ref().to_vec() };
+
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    options.open(path)?.write_all(pem::encode(&pem).as_bytes())?;
+
+    Ok(())
+}
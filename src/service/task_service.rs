@@ -0,0 +1,240 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use chrono::{NaiveDate, Utc};
+use chrono_tz::Tz;
+use mongodb::bson::DateTime;
+use mongodb::bson::oid::ObjectId;
+use mongodb::error::Error as MongoError;
+
+use crate::model::task_model::{ChecklistItem, Recurrence, Task, TaskNode, TaskStatus};
+use crate::repository::task_repository::TaskRepository;
+use crate::service::recurrence_service::next_occurrence;
+use crate::service::reminder_service::{next_overdue_alert, reminder_instant};
+use crate::service::time_zone_service::{due_instant, today};
+
+/// Moves a task with `auto_complete` to `Done` once every checklist item is checked and
+/// returns the task as it is afterwards. A task that is still blocked stays where it is.
+pub async fn complete_if_checked(task_repo: &TaskRepository, task: Task, time_zone: Tz) -> Result<Task, MongoError> {
+    let all_checked = !task.checklist.is_empty() && !task.has_open_checklist_items();
+    if !task.auto_complete || !all_checked || task.status == TaskStatus::Done {
+        return Ok(task);
+    }
+    if !unfinished_blockers(task_repo, &task).await?.is_empty() {
+        return Ok(task);
+    }
+
+    let task_id = task.id.unwrap().to_hex();
+    match task_repo.update_status(&task_id, &task.user_id, &TaskStatus::Done, true).await? {
+        Some(completed) => {
+            create_next_occurrence(task_repo, &completed, time_zone).await?;
+            Ok(completed)
+        }
+        None => Ok(task),
+    }
+}
+
+/// Blockers of the task that are not done yet.
+pub async fn unfinished_blockers(task_repo: &TaskRepository, task: &Task) -> Result<Vec<Task>, MongoError> {
+    if task.blocked_by.is_empty() {
+        return Ok(Vec::new());
+    }
+    task_repo.find_unfinished(&task.user_id, &task.blocked_by).await
+}
+
+/// Whether making `task_id` blocked by `blocker_id` closes a cycle, i.e. the blocker
+/// already waits on the task, directly or through other tasks.
+pub fn creates_cycle(nodes: &[TaskNode], task_id: &ObjectId, blocker_id: &ObjectId) -> bool {
+    let edges: HashMap<&ObjectId, &Vec<ObjectId>> = nodes.iter()
+        .map(|node| (&node.id, &node.blocked_by))
+        .collect();
+
+    let mut visited = HashSet::new();
+    let mut pending = vec![blocker_id];
+    while let Some(current) = pending.pop() {
+        if current == task_id {
+            return true;
+        }
+        if !visited.insert(current) {
+            continue;
+        }
+        if let Some(blockers) = edges.get(current) {
+            pending.extend(blockers.iter());
+        }
+    }
+    false
+}
+
+/// Open tasks ordered so every task comes after the open tasks blocking it. Tasks without
+/// open blockers come first, in the order they were given in, and every other task follows
+/// as soon as its last blocker is listed. Tasks on a cycle come last in the given order.
+pub fn topological_order(nodes: &[TaskNode]) -> Vec<ObjectId> {
+    let open: Vec<&TaskNode> = nodes.iter().filter(|node| node.status != TaskStatus::Done).collect();
+    let open_ids: HashSet<&ObjectId> = open.iter().map(|node| &node.id).collect();
+
+    let mut waiting_on: HashMap<&ObjectId, usize> = HashMap::new();
+    let mut blocking: HashMap<&ObjectId, Vec<&ObjectId>> = HashMap::new();
+    for node in &open {
+        let blockers: HashSet<&ObjectId> = node.blocked_by.iter().filter(|id| open_ids.contains(id)).collect();
+        waiting_on.insert(&node.id, blockers.len());
+        for blocker in blockers {
+            blocking.entry(blocker).or_default().push(&node.id);
+        }
+    }
+
+    let mut ready: VecDeque<&ObjectId> = open.iter()
+        .map(|node| &node.id)
+        .filter(|id| waiting_on[id] == 0)
+        .collect();
+    let mut order = Vec::with_capacity(open.len());
+    while let Some(id) = ready.pop_front() {
+        order.push(*id);
+        for blocked in blocking.get(id).into_iter().flatten() {
+            let count = waiting_on.get_mut(blocked).unwrap();
+            *count -= 1;
+            if *count == 0 {
+                ready.push_back(blocked);
+            }
+        }
+    }
+
+    // tasks on a cycle, or blocked by one, never become ready but still have to be listed
+    if order.len() < open.len() {
+        let listed: HashSet<ObjectId> = order.iter().copied().collect();
+        order.extend(open.iter().map(|node| node.id).filter(|id| !listed.contains(id)));
+    }
+    order
+}
+
+/// Creates the next occurrence of a recurring task that was just done. Occurrences that
+/// would already be in the past are skipped, they count towards the rule's `count` though.
+/// Returns `None` when the series has ended or was continued from this task already.
+pub async fn create_next_occurrence(task_repo: &TaskRepository, task: &Task,
+                                    time_zone: Tz) -> Result<Option<Task>, MongoError> {
+    let (task_id, rule) = match (task.id, &task.recurrence) {
+        (Some(task_id), Some(rule)) if task.status == TaskStatus::Done => (task_id, rule),
+        _ => return Ok(None),
+    };
+
+    let next = match next_upcoming(rule, task.due_date, today(time_zone)) {
+        Some((due_date, rule)) => {
+            let due_at = due_instant(due_date, task.due_time, time_zone);
+            Some(Task {
+                id: None,
+                status: TaskStatus::ToDo,
+                due_date,
+                due_at: DateTime::from_chrono(due_at),
+                reminder_at: task.remind_before_hours
+                    .map(|hours| DateTime::from_chrono(reminder_instant(due_at, hours))),
+                overdue_alert_at: next_overdue_alert(due_date, task.due_time, time_zone, Utc::now())
+                    .map(DateTime::from_chrono),
+                checklist: task.checklist.iter()
+                    .map(|item| ChecklistItem { id: ObjectId::new(), text: item.text.clone(), done: false })
+                    .collect(),
+                blocked_by: Vec::new(),
+                recurrence: Some(rule),
+                previous_occurrence: Some(task_id),
+                ..task.clone()
+            })
+        }
+        None => None,
+    };
+
+    // the next occurrence is stored before the rule comes off the done task, so a failure in
+    // between never ends the series; `previous_occurrence` keeps it from continuing twice
+    let created = match next {
+        Some(next) => task_repo.create_occurrence(next).await?,
+        None => None,
+    };
+    task_repo.clear_recurrence(&task_id, &task.user_id).await?;
+
+    Ok(created)
+}
+
+/// First occurrence after `due_date` that isn't before `today`, with the rule that
+/// continues the series from there.
+fn next_upcoming(rule: &Recurrence, due_date: NaiveDate, today: NaiveDate) -> Option<(NaiveDate, Recurrence)> {
+    let mut next = next_occurrence(rule, due_date)?;
+    while next.0 < today {
+        next = next_occurrence(&next.1, next.0)?;
+    }
+    Some(next)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::model::task_model::Frequency;
+
+    use super::*;
+
+    fn node(id: ObjectId, status: TaskStatus, blocked_by: &[ObjectId]) -> TaskNode {
+        TaskNode { id, title: id.to_hex(), status, blocked_by: blocked_by.to_vec() }
+    }
+
+    fn date(value: &str) -> NaiveDate {
+        NaiveDate::parse_from_str(value, "%Y-%m-%d").unwrap()
+    }
+
+    #[test]
+    fn blocking_a_blocker_creates_a_cycle() {
+        let (a, b, c) = (ObjectId::new(), ObjectId::new(), ObjectId::new());
+        // a waits on b, b waits on c
+        let nodes = vec![
+            node(a, TaskStatus::ToDo, &[b]),
+            node(b, TaskStatus::ToDo, &[c]),
+            node(c, TaskStatus::ToDo, &[]),
+        ];
+
+        assert!(creates_cycle(&nodes, &c, &a));
+        assert!(creates_cycle(&nodes, &b, &a));
+        assert!(creates_cycle(&nodes, &a, &a));
+        assert!(!creates_cycle(&nodes, &a, &c));
+        assert!(!creates_cycle(&nodes, &c, &ObjectId::new()));
+    }
+
+    #[test]
+    fn blockers_come_first_and_done_tasks_are_left_out() {
+        let (a, b, c, d, e) = (ObjectId::new(), ObjectId::new(), ObjectId::new(), ObjectId::new(), ObjectId::new());
+        let nodes = vec![
+            node(c, TaskStatus::ToDo, &[b]),
+            node(b, TaskStatus::InProgress, &[a]),
+            node(a, TaskStatus::ToDo, &[]),
+            node(d, TaskStatus::Done, &[]),
+            node(e, TaskStatus::ToDo, &[d]),
+        ];
+
+        assert_eq!(topological_order(&nodes), vec![a, e, b, c]);
+    }
+
+    #[test]
+    fn tasks_on_a_cycle_come_last() {
+        let (x, y, z, w) = (ObjectId::new(), ObjectId::new(), ObjectId::new(), ObjectId::new());
+        let nodes = vec![
+            node(x, TaskStatus::ToDo, &[y]),
+            node(y, TaskStatus::ToDo, &[x]),
+            node(z, TaskStatus::ToDo, &[x]),
+            node(w, TaskStatus::ToDo, &[]),
+        ];
+
+        assert_eq!(topological_order(&nodes), vec![w, x, y, z]);
+    }
+
+    #[test]
+    fn next_upcoming_skips_past_occurrences() {
+        let rule = Recurrence { frequency: Frequency::Daily, interval: 1, weekdays: Vec::new(), month_day: None,
+                                count: None, until: None };
+
+        let (due_date, _) = next_upcoming(&rule, date("2024-01-01"), date("2024-01-05")).unwrap();
+        assert_eq!(due_date, date("2024-01-05"));
+    }
+
+    #[test]
+    fn skipped_occurrences_count_towards_the_rule() {
+        let rule = Recurrence { frequency: Frequency::Daily, interval: 1, weekdays: Vec::new(), month_day: None,
+                                count: Some(3), until: None };
+
+        let (due_date, rule) = next_upcoming(&rule, date("2024-01-01"), date("2024-01-03")).unwrap();
+        assert_eq!(due_date, date("2024-01-03"));
+        assert_eq!(rule.count, Some(1));
+        assert!(next_upcoming(&rule, date("2024-01-01"), date("2024-01-05")).is_none());
+    }
+}
@@ -0,0 +1,111 @@
+use std::path::Path;
+
+use handlebars::{handlebars_helper, no_escape, Handlebars, RenderError};
+use serde::Serialize;
+
+/// The built-in templates, in `templates/`. Each name has an HTML and a plain text variant
+/// and every one but the layout wraps itself in the layout of its variant.
+const DEFAULT_TEMPLATES: [(&str, &str, &str); 6] = [
+    ("layout", include_str!("../../templates/layout.html.hbs"), include_str!("../../templates/layout.txt.hbs")),
+    ("digest", include_str!("../../templates/digest.html.hbs"), include_str!("../../templates/digest.txt.hbs")),
+    ("reminder", include_str!("../../templates/reminder.html.hbs"), include_str!("../../templates/reminder.txt.hbs")),
+    ("overdue_alert", include_str!("../../templates/overdue_alert.html.hbs"),
+     include_str!("../../templates/overdue_alert.txt.hbs")),
+    ("password_reset", include_str!("../../templates/password_reset.html.hbs"),
+     include_str!("../../templates/password_reset.txt.hbs")),
+    ("verify_email", include_str!("../../templates/verify_email.html.hbs"),
+     include_str!("../../templates/verify_email.txt.hbs")),
+];
+
+handlebars_helper!(underline: |text: String| "=".repeat(text.chars().count()));
+
+/// Renders the mails of the application. The HTML variant escapes every `{{value}}`, the
+/// text variant leaves values as they are. A `<name>.html.hbs` or `<name>.txt.hbs` file in
+/// `TEMPLATE_DIR` replaces the built-in template of that name.
+pub struct Templates {
+    html: Handlebars<'static>,
+    text: Handlebars<'static>,
+}
+
+/// Both variants of a rendered mail body.
+pub struct RenderedBody {
+    pub html: String,
+    pub text: String,
+}
+
+impl Templates {
+    pub fn from_env() -> Self {
+        let dir = std::env::var("TEMPLATE_DIR").ok();
+        let dir = dir.as_deref().map(Path::new);
+
+        let mut html = Handlebars::new();
+        html.set_strict_mode(true);
+
+        let mut text = Handlebars::new();
+        text.set_strict_mode(true);
+        text.register_escape_fn(no_escape);
+        text.register_helper("underline", Box::new(underline));
+
+        for (name, html_source, text_source) in DEFAULT_TEMPLATES {
+            register(&mut html, dir, name, "html", html_source);
+            register(&mut text, dir, name, "txt", text_source);
+        }
+
+        Templates { html, text }
+    }
+
+    pub fn render<T: Serialize>(&self, name: &str, data: &T) -> Result<RenderedBody, RenderError> {
+        Ok(RenderedBody {
+            html: self.html.render(name, data)?,
+            text: self.text.render(name, data)?,
+        })
+    }
+}
+
+fn register(registry: &mut Handlebars<'static>, dir: Option<&Path>, name: &str,
+            extension: &str, default_source: &str) {
+    let path = dir.map(|dir| dir.join(format!("{}.{}.hbs", name, extension)));
+    let result = match path {
+        Some(path) if path.is_file() => registry.register_template_file(name, path),
+        _ => registry.register_template_string(name, default_source),
+    };
+    if let Err(e) = result {
+        panic!("Invalid email template {}.{}: {}", name, extension, e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn reminder(task_title: &str) -> RenderedBody {
+        let data = json!({
+            "title": "Task reminder",
+            "base_url": "https://taskr.example",
+            "task_title": task_title,
+            "description": "",
+            "due": "Monday, 2024-01-01 at 09:00",
+            "time_zone": "Europe/Berlin",
+            "unsubscribe_url": null,
+        });
+        Templates::from_env().render("reminder", &data).unwrap()
+    }
+
+    #[test]
+    fn html_escapes_values() {
+        let body = reminder("<script>alert(1)</script> & co");
+
+        assert!(body.html.contains("&lt;script&gt;alert(1)&lt;/script&gt; &amp; co"));
+        assert!(!body.html.contains("<script>"));
+    }
+
+    #[test]
+    fn text_leaves_values_as_they_are() {
+        let body = reminder("<script>alert(1)</script> & co");
+
+        assert!(body.text.contains("<script>alert(1)</script> & co is due on"));
+        assert!(body.text.starts_with("Task reminder\n=============\n"));
+    }
+}
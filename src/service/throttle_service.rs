@@ -0,0 +1,118 @@
+use actix_web::{HttpRequest, HttpResponse};
+use actix_web::http::header::RETRY_AFTER;
+use chrono::{Duration, Utc};
+use mongodb::bson::DateTime;
+use mongodb::error::Error as MongoError;
+
+use crate::repository::login_attempt_repository::LoginAttemptRepository;
+use crate::repository::rate_limit_repository::RateLimitRepository;
+
+/// Failures allowed for one account before backoff kicks in.
+const ACCOUNT_FREE_ATTEMPTS: i32 = 5;
+/// Failures allowed from one address, which may be shared by several users.
+const IP_FREE_ATTEMPTS: i32 = 20;
+const BASE_LOCKOUT_SECONDS: i64 = 30;
+const MAX_LOCKOUT_SECONDS: i64 = 15 * 60;
+/// How long failure counters are kept after the last failure.
+const FAILURE_RETENTION_HOURS: i64 = 24;
+
+pub struct RateLimit {
+    pub name: &'static str,
+    pub max_requests: i32,
+    pub window_seconds: i64,
+}
+
+pub const SIGN_IN_RATE_LIMIT: RateLimit = RateLimit { name: "sign-in", max_requests: 10, window_seconds: 60 };
+pub const SIGN_UP_RATE_LIMIT: RateLimit = RateLimit { name: "sign-up", max_requests: 5, window_seconds: 60 * 60 };
+
+/// Counts the request in the current window. Returns the seconds until the window resets
+/// if the client went over the limit.
+pub async fn check_rate_limit(rate_limit_repo: &RateLimitRepository, limit: &RateLimit,
+                              client_ip: &str) -> Result<Option<i64>, MongoError> {
+    let now = Utc::now().timestamp();
+    let window_start = now - now % limit.window_seconds;
+    let window_end = window_start + limit.window_seconds;
+
+    let key = format!("{}:{}:{}", limit.name, client_ip, window_start);
+    let count = rate_limit_repo.hit(&key, DateTime::from_millis(window_end * 1000)).await?;
+
+    if count > limit.max_requests {
+        Ok(Some(window_end - now))
+    } else {
+        Ok(None)
+    }
+}
+
+pub fn account_key(email: &str) -> String {
+    format!("account:{}", email.to_lowercase())
+}
+
+pub fn ip_key(client_ip: &str) -> String {
+    format!("ip:{}", client_ip)
+}
+
+/// Returns the seconds left on the longest active lockout of the given keys.
+pub async fn remaining_lockout(login_attempt_repo: &LoginAttemptRepository,
+                               keys: &[String]) -> Result<Option<i64>, MongoError> {
+    let now = Utc::now().timestamp_millis();
+    let mut remaining: Option<i64> = None;
+
+    for key in keys {
+        let locked_until = match login_attempt_repo.find(key).await? {
+            Some(attempt) => attempt.locked_until,
+            None => None
+        };
+
+        if let Some(locked_until) = locked_until {
+            let seconds = (locked_until.timestamp_millis() - now + 999) / 1000;
+            if seconds > remaining.unwrap_or(0) {
+                remaining = Some(seconds);
+            }
+        }
+    }
+
+    Ok(remaining)
+}
+
+/// Records a failed sign-in against the account and the client address and locks them
+/// with an exponentially growing delay once they run out of free attempts.
+pub async fn register_failed_sign_in(login_attempt_repo: &LoginAttemptRepository, email: &str,
+                                     client_ip: &str) -> Result<(), MongoError> {
+    let keys = [(account_key(email), ACCOUNT_FREE_ATTEMPTS), (ip_key(client_ip), IP_FREE_ATTEMPTS)];
+    let now = Utc::now();
+    let expires_at = DateTime::from_chrono(now + Duration::hours(FAILURE_RETENTION_HOURS));
+
+    for (key, free_attempts) in keys {
+        let failures = match login_attempt_repo.record_failure(&key, expires_at).await? {
+            Some(attempt) => attempt.failures,
+            None => continue
+        };
+
+        if failures >= free_attempts {
+            let exponent = (failures - free_attempts).min(16) as u32;
+            let seconds = (BASE_LOCKOUT_SECONDS * 2i64.pow(exponent)).min(MAX_LOCKOUT_SECONDS);
+            login_attempt_repo.lock(&key, DateTime::from_chrono(now + Duration::seconds(seconds))).await?;
+        }
+    }
+
+    Ok(())
+}
+
+pub async fn clear_failed_sign_ins(login_attempt_repo: &LoginAttemptRepository, email: &str) -> Result<(), MongoError> {
+    login_attempt_repo.clear(&account_key(email)).await.map(|_| ())
+}
+
+/// Address of the directly connected peer. Forwarding headers are ignored on purpose
+/// since clients could set them to dodge the limits.
+pub fn client_ip(req: &HttpRequest) -> String {
+    match req.peer_addr() {
+        Some(addr) => addr.ip().to_string(),
+        None => String::from("unknown")
+    }
+}
+
+pub fn too_many_requests(retry_after_seconds: i64) -> HttpResponse {
+    HttpResponse::TooManyRequests()
+        .insert_header((RETRY_AFTER, retry_after_seconds.to_string()))
+        .json("Too many requests, try again later")
+}
@@ -0,0 +1,82 @@
+use chrono::{DateTime, Duration, LocalResult, NaiveDate, NaiveTime, Offset, TimeZone, Utc};
+use chrono_tz::Tz;
+
+use crate::model::user_model::User;
+
+/// Tasks without a time of day are due at the end of their day.
+pub const END_OF_DAY: &str = "23:59:59.999";
+
+/// Zone of users who haven't picked one, `DEFAULT_TIME_ZONE` or UTC.
+pub fn default_time_zone() -> Tz {
+    match std::env::var("DEFAULT_TIME_ZONE") {
+        Ok(name) => name.parse().expect("DEFAULT_TIME_ZONE is not an IANA time zone"),
+        Err(_) => Tz::UTC,
+    }
+}
+
+pub fn user_time_zone(user: &User) -> Tz {
+    user.time_zone.as_deref()
+        .and_then(|name| name.parse().ok())
+        .unwrap_or_else(default_time_zone)
+}
+
+pub fn today(time_zone: Tz) -> NaiveDate {
+    Utc::now().with_timezone(&time_zone).date_naive()
+}
+
+/// The instant a task is due: the given time, or the end of the day, on the due date in
+/// the user's zone.
+pub fn due_instant(due_date: NaiveDate, due_time: Option<NaiveTime>, time_zone: Tz) -> DateTime<Utc> {
+    let time = due_time.unwrap_or_else(|| NaiveTime::parse_from_str(END_OF_DAY, "%H:%M:%S%.f").unwrap());
+    local_instant(due_date, time, time_zone)
+}
+
+/// A wall-clock time in the given zone. Times skipped by a change of the offset are read
+/// with the offset from before the change, so they move forward by the size of the gap,
+/// repeated ones are taken the first time round. This is how `$dateFromString` reads them
+/// too, see `due_instant_pipeline`.
+pub fn local_instant(date: NaiveDate, time: NaiveTime, time_zone: Tz) -> DateTime<Utc> {
+    let local = date.and_time(time);
+
+    match time_zone.from_local_datetime(&local) {
+        LocalResult::Single(instant) | LocalResult::Ambiguous(instant, _) => instant.with_timezone(&Utc),
+        LocalResult::None => {
+            // no zone skips more than a day, so two days earlier the old offset applied
+            let offset = time_zone.offset_from_utc_datetime(&(local - Duration::days(2))).fix();
+            Utc.from_utc_datetime(&(local - Duration::seconds(offset.local_minus_utc().into())))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn local(time_zone: Tz, date: &str, time: &str) -> String {
+        let date = NaiveDate::parse_from_str(date, "%Y-%m-%d").unwrap();
+        let time = NaiveTime::parse_from_str(time, "%H:%M").unwrap();
+        local_instant(date, time, time_zone).to_rfc3339()
+    }
+
+    #[test]
+    fn reads_regular_time_in_zone() {
+        assert_eq!(local(chrono_tz::Europe::Berlin, "2024-07-01", "09:00"), "2024-07-01T07:00:00+00:00");
+    }
+
+    #[test]
+    fn skipped_time_moves_forward_by_the_gap() {
+        // 02:30 doesn't exist in Berlin on that day, it is read as 02:30 CET
+        assert_eq!(local(chrono_tz::Europe::Berlin, "2024-03-31", "02:30"), "2024-03-31T01:30:00+00:00");
+    }
+
+    #[test]
+    fn repeated_time_is_taken_the_first_time() {
+        assert_eq!(local(chrono_tz::Europe::Berlin, "2024-10-27", "02:30"), "2024-10-27T00:30:00+00:00");
+    }
+
+    #[test]
+    fn skipped_day_does_not_panic() {
+        // Samoa moved across the date line and skipped 30 December 2011
+        assert_eq!(local(chrono_tz::Pacific::Apia, "2011-12-30", "12:00"), "2011-12-30T22:00:00+00:00");
+    }
+}
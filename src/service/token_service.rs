@@ -0,0 +1,162 @@
+use base64::Engine;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use chrono::{Duration, Utc};
+use jsonwebtoken::{decode, decode_header, encode, Validation};
+use mongodb::bson::DateTime;
+use mongodb::bson::oid::ObjectId;
+use mongodb::error::Error as MongoError;
+use rand::RngCore;
+use serde::de::DeserializeOwned;
+use sha2::{Digest, Sha256};
+
+use crate::dto::mfa_pending_claims::MfaPendingClaims;
+use crate::dto::token_claims::TokenClaims;
+use crate::dto::token_response::TokenResponse;
+use crate::model::one_time_token_model::{OneTimeToken, TokenPurpose};
+use crate::model::refresh_token_model::RefreshToken;
+use crate::model::user_model::User;
+use crate::repository::one_time_token_repository::OneTimeTokenRepository;
+use crate::repository::refresh_token_repository::RefreshTokenRepository;
+use crate::service::signing_key_service::SigningKeys;
+
+const DEFAULT_ACCESS_TOKEN_TTL_MINUTES: i64 = 15;
+const DEFAULT_REFRESH_TOKEN_TTL_DAYS: i64 = 30;
+const MFA_TOKEN_TTL_MINUTES: i64 = 5;
+/// Audience of our access tokens. Every kind of JWT we sign has its own audience and
+/// verification insists on it, as all of them are signed with the published keys.
+const ACCESS_TOKEN_AUDIENCE: &str = "taskr:access";
+/// Audience of the tokens proving the password step of a sign-in.
+const MFA_TOKEN_AUDIENCE: &str = "taskr:mfa";
+/// Marks personal access tokens so they can be told apart from JWTs.
+pub const ACCESS_TOKEN_PREFIX: &str = "taskr_pat_";
+
+pub fn access_token_ttl() -> Duration {
+    let minutes = std::env::var("ACCESS_TOKEN_TTL_MINUTES").ok()
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(DEFAULT_ACCESS_TOKEN_TTL_MINUTES);
+    Duration::minutes(minutes)
+}
+
+pub fn refresh_token_ttl() -> Duration {
+    let days = std::env::var("REFRESH_TOKEN_TTL_DAYS").ok()
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(DEFAULT_REFRESH_TOKEN_TTL_DAYS);
+    Duration::days(days)
+}
+
+/// Issues a new access token and a new refresh token for the user. Passing the family of
+/// the refresh token that is being rotated keeps the new token in the same family; `None`
+/// starts a new one.
+pub async fn issue_tokens(refresh_repo: &RefreshTokenRepository, keys: &SigningKeys, user: &User,
+                          family_id: Option<String>) -> Result<TokenResponse, MongoError> {
+    let family_id = family_id.unwrap_or_else(|| ObjectId::new().to_hex());
+    let now = Utc::now();
+
+    let access_ttl = access_token_ttl();
+    let claims = TokenClaims {
+        sub: user.id.unwrap().to_hex(),
+        aud: ACCESS_TOKEN_AUDIENCE.to_string(),
+        jti: ObjectId::new().to_hex(),
+        sid: family_id.clone(),
+        iat: now.timestamp(),
+        exp: (now + access_ttl).timestamp(),
+    };
+    let access_token = encode(&keys.header(), &claims, keys.encoding_key()).unwrap();
+
+    let refresh_token = generate_token();
+    let stored_token = RefreshToken {
+        id: None,
+        user_id: user.id.unwrap(),
+        family_id,
+        token_hash: hash_token(&refresh_token),
+        used: false,
+        revoked: false,
+        created_at: DateTime::from_chrono(now),
+        expires_at: DateTime::from_chrono(now + refresh_token_ttl()),
+    };
+    refresh_repo.create(stored_token).await?;
+
+    Ok(TokenResponse {
+        access_token,
+        refresh_token,
+        token_type: String::from("Bearer"),
+        expires_in: access_ttl.num_seconds(),
+    })
+}
+
+/// Signs the token that proves the password step of a sign-in was passed. Returns the
+/// token and its lifetime in seconds.
+pub fn sign_mfa_token(keys: &SigningKeys, user: &User) -> (String, i64) {
+    let now = Utc::now();
+    let ttl = Duration::minutes(MFA_TOKEN_TTL_MINUTES);
+    let claims = MfaPendingClaims {
+        sub: user.id.unwrap().to_hex(),
+        aud: MFA_TOKEN_AUDIENCE.to_string(),
+        mfa_pending: true,
+        iat: now.timestamp(),
+        exp: (now + ttl).timestamp(),
+    };
+
+    (encode(&keys.header(), &claims, keys.encoding_key()).unwrap(), ttl.num_seconds())
+}
+
+pub fn verify_mfa_token(keys: &SigningKeys, token: &str) -> Option<MfaPendingClaims> {
+    let claims: MfaPendingClaims = verify_jwt(keys, token, MFA_TOKEN_AUDIENCE)?;
+
+    if claims.mfa_pending {
+        Some(claims)
+    } else {
+        None
+    }
+}
+
+pub fn verify_access_token(keys: &SigningKeys, token: &str) -> Option<TokenClaims> {
+    verify_jwt(keys, token, ACCESS_TOKEN_AUDIENCE)
+}
+
+/// Checks signature, expiry and audience of one of our JWTs. The key is picked by the
+/// `kid` header and dictates the algorithm.
+fn verify_jwt<T: DeserializeOwned>(keys: &SigningKeys, token: &str, audience: &str) -> Option<T> {
+    let kid = decode_header(token).ok()?.kid?;
+    let (algorithm, decoding_key) = keys.decoding_key(&kid)?;
+
+    let mut validation = Validation::new(algorithm);
+    validation.leeway = 0;
+    validation.set_audience(&[audience]);
+    decode::<T>(token, decoding_key, &validation).ok().map(|data| data.claims)
+}
+
+/// Replaces any outstanding token of the same purpose with a new one and returns the
+/// plain token so it can be mailed to the user.
+pub async fn issue_one_time_token(token_repo: &OneTimeTokenRepository, user_id: &ObjectId,
+                                  purpose: TokenPurpose, email: Option<String>,
+                                  ttl: Duration) -> Result<String, MongoError> {
+    token_repo.delete_for_user(user_id, &purpose).await?;
+
+    let token = generate_token();
+    let now = Utc::now();
+    let stored_token = OneTimeToken {
+        id: None,
+        user_id: *user_id,
+        purpose,
+        token_hash: hash_token(&token),
+        email,
+        created_at: DateTime::from_chrono(now),
+        expires_at: DateTime::from_chrono(now + ttl),
+    };
+    token_repo.create(stored_token).await?;
+
+    Ok(token)
+}
+
+/// Generates a random, url-safe opaque token.
+pub fn generate_token() -> String {
+    let mut bytes = [0u8; 32];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    URL_SAFE_NO_PAD.encode(bytes)
+}
+
+/// Opaque tokens are only ever stored as their SHA-256 hash.
+pub fn hash_token(token: &str) -> String {
+    URL_SAFE_NO_PAD.encode(Sha256::digest(token.as_bytes()))
+}
@@ -0,0 +1,97 @@
+use base64::Engine;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use hmac::{Hmac, Mac};
+use mongodb::bson::oid::ObjectId;
+use sha2::Sha256;
+
+use crate::model::user_model::NotificationKind;
+use crate::service::email_service::app_base_url;
+
+const MIN_SECRET_LENGTH: usize = 32;
+
+/// Signs the links that turn a notification off for the user without signing in, so
+/// nobody can unsubscribe other users. Links stay valid as long as the secret does.
+#[derive(Clone)]
+pub struct UnsubscribeLinks {
+    secret: Vec<u8>,
+}
+
+impl UnsubscribeLinks {
+    /// Reads `UNSUBSCRIBE_SECRET`. It has to be a secret of its own, since the links are
+    /// handed out to everybody and must not leak anything about the password hashing secret.
+    pub fn from_env() -> Self {
+        let secret = std::env::var("UNSUBSCRIBE_SECRET").expect("UNSUBSCRIBE_SECRET not provided");
+        if secret.len() < MIN_SECRET_LENGTH {
+            panic!("UNSUBSCRIBE_SECRET must be at least {} characters long", MIN_SECRET_LENGTH);
+        }
+
+        UnsubscribeLinks { secret: secret.into_bytes() }
+    }
+
+    pub fn url(&self, user_id: &ObjectId, kind: NotificationKind) -> String {
+        let signature = URL_SAFE_NO_PAD.encode(self.signature(&user_id.to_hex(), kind).finalize().into_bytes());
+        format!("{}/notifications/unsubscribe?user={}&list={}&sig={}", app_base_url(), user_id.to_hex(), kind, signature)
+    }
+
+    /// Whether `signature` is the one `url` put in the link for the user and kind.
+    pub fn verify(&self, user_id: &str, kind: NotificationKind, signature: &str) -> bool {
+        match URL_SAFE_NO_PAD.decode(signature) {
+            Ok(bytes) => self.signature(user_id, kind).verify_slice(&bytes).is_ok(),
+            Err(_) => false,
+        }
+    }
+
+    fn signature(&self, user_id: &str, kind: NotificationKind) -> Hmac<Sha256> {
+        // HMAC takes keys of any length
+        let mut mac = Hmac::<Sha256>::new_from_slice(&self.secret).unwrap();
+        mac.update(format!("unsubscribe:{}:{}", user_id, kind).as_bytes());
+        mac
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use url::Url;
+
+    use super::*;
+
+    fn links(secret: &str) -> UnsubscribeLinks {
+        UnsubscribeLinks { secret: secret.as_bytes().to_vec() }
+    }
+
+    /// The `user`, `list` and `sig` parameters of the link.
+    fn params(url: &str) -> (String, String, String) {
+        let url = Url::parse(url).unwrap();
+        let param = |name: &str| url.query_pairs().find(|(key, _)| key == name).unwrap().1.to_string();
+        (param("user"), param("list"), param("sig"))
+    }
+
+    #[test]
+    fn signed_link_verifies() {
+        let links = links("first secret");
+        let user_id = ObjectId::new();
+        let (user, list, sig) = params(&links.url(&user_id, NotificationKind::OverdueAlerts));
+
+        assert_eq!(user, user_id.to_hex());
+        assert_eq!(list, "overdue_alerts");
+        assert!(links.verify(&user, NotificationKind::OverdueAlerts, &sig));
+    }
+
+    #[test]
+    fn signature_is_bound_to_user_and_kind() {
+        let links = links("first secret");
+        let (user, _, sig) = params(&links.url(&ObjectId::new(), NotificationKind::Digest));
+
+        assert!(!links.verify(&user, NotificationKind::Reminders, &sig));
+        assert!(!links.verify(&ObjectId::new().to_hex(), NotificationKind::Digest, &sig));
+    }
+
+    #[test]
+    fn rejects_other_secret_and_garbage() {
+        let (user, _, sig) = params(&links("first secret").url(&ObjectId::new(), NotificationKind::Digest));
+
+        assert!(!links("second secret").verify(&user, NotificationKind::Digest, &sig));
+        assert!(!links("first secret").verify(&user, NotificationKind::Digest, "not base64!"));
+        assert!(!links("first secret").verify(&user, NotificationKind::Digest, ""));
+    }
+}
@@ -1,42 +1,134 @@
 use actix_web::{Error, HttpMessage, HttpResponse};
-use actix_web::dev::ServiceRequest;
-use actix_web::web::{Data, Json};
+use actix_web::dev::{Service, ServiceRequest, ServiceResponse};
+use actix_web::error::ErrorForbidden;
+use actix_web::http::Method;
+use actix_web::web::{Data, Json, Query};
 use actix_web_httpauth::extractors::{AuthenticationError, bearer};
 use actix_web_httpauth::extractors::bearer::BearerAuth;
-use hmac::digest::KeyInit;
-use hmac::Hmac;
-use jwt::VerifyWithKey;
+use futures::future::{Either, ready, Ready};
+use mongodb::bson::DateTime;
+use mongodb::bson::oid::ObjectId;
 use serde::de::DeserializeOwned;
-use sha2::Sha256;
-use validator::Validate;
+use validator::{Validate, ValidationError, ValidationErrors};
 
 use crate::dto::token_claims::TokenClaims;
-use crate::model::user_model::User;
+use crate::model::access_token_model::TokenScope;
+use crate::model::user_model::{Role, User};
+use crate::repository::access_token_repository::AccessTokenRepository;
+use crate::repository::refresh_token_repository::RefreshTokenRepository;
+use crate::repository::revoked_token_repository::RevokedTokenRepository;
 use crate::repository::user_repository::UserRepository;
+use crate::service::signing_key_service::SigningKeys;
+use crate::service::token_service::{ACCESS_TOKEN_PREFIX, hash_token, verify_access_token};
 
 pub async fn jwt_validator(req: ServiceRequest, credentials: BearerAuth) -> Result<ServiceRequest, (Error, ServiceRequest)> {
-    let jwt_secret = std::env::var("JWT_SECRET").expect("JWT_SECRET not provided!");
-    let key: Hmac<Sha256> = Hmac::new_from_slice(jwt_secret.as_bytes()).unwrap();
     let bearer_token = credentials.token();
     let config = req.app_data::<bearer::Config>().cloned().unwrap_or_default().scope("");
 
-    let token_claims = match bearer_token.verify_with_key(&key) {
-        Ok(claims) => claims,
-        Err(_) => return Err((AuthenticationError::from(config).into(), req)),
+    if bearer_token.starts_with(ACCESS_TOKEN_PREFIX) {
+        return access_token_validator(req, bearer_token, config).await;
+    }
+
+    let keys = req.app_data::<Data<SigningKeys>>().unwrap();
+    let token_claims: TokenClaims = match verify_access_token(keys, bearer_token) {
+        Some(claims) => claims,
+        None => return Err((AuthenticationError::from(config).into(), req)),
+    };
+
+    let refresh_repo = req.app_data::<Data<RefreshTokenRepository>>().unwrap();
+    match refresh_repo.is_family_revoked(&token_claims.sid).await {
+        Ok(false) => (),
+        _ => return Err((AuthenticationError::from(config).into(), req)),
+    };
+
+    let revoked_repo = req.app_data::<Data<RevokedTokenRepository>>().unwrap();
+    match revoked_repo.is_revoked(&token_claims.jti).await {
+        Ok(false) => (),
+        _ => return Err((AuthenticationError::from(config).into(), req)),
+    };
+
+    let db = req.app_data::<Data<UserRepository>>().unwrap();
+    let user = match find_user_by_claims(db, &token_claims).await {
+        Ok(Some(user)) if can_authenticate(&user) => user,
+        _ => return Err((AuthenticationError::from(config).into(), req)),
     };
 
+    req.extensions_mut().insert(user);
+    req.extensions_mut().insert(token_claims);
+    Ok(req)
+}
+
+/// Authenticates a personal access token and checks that its scopes cover the route.
+async fn access_token_validator(req: ServiceRequest, token: &str,
+                                config: bearer::Config) -> Result<ServiceRequest, (Error, ServiceRequest)> {
+    let token_repo = req.app_data::<Data<AccessTokenRepository>>().unwrap();
+    let access_token = match token_repo.find_by_hash(&hash_token(token)).await {
+        Ok(Some(access_token)) => access_token,
+        _ => return Err((AuthenticationError::from(config).into(), req)),
+    };
+
+    if access_token.expires_at.is_some_and(|expires_at| expires_at <= DateTime::now()) {
+        return Err((AuthenticationError::from(config).into(), req));
+    }
+
+    if !has_required_scope(&req, &access_token.scopes) {
+        return Err((ErrorForbidden("Insufficient token scope"), req));
+    }
+
     let db = req.app_data::<Data<UserRepository>>().unwrap();
-    let user = match find_user_by_claims(&db, &token_claims).await {
-        Ok(Some(user)) => user,
+    let user = match db.find_by_id(&access_token.user_id).await {
+        Ok(Some(user)) if can_authenticate(&user) => user,
         _ => return Err((AuthenticationError::from(config).into(), req)),
     };
 
+    let _ = token_repo.touch(&access_token.id.unwrap()).await;
+
     req.extensions_mut().insert(user);
     Ok(req)
 }
 
+/// Disabled users and users an admin sent through the password reset lose the tokens
+/// they already hold, not only the ability to sign in.
+fn can_authenticate(user: &User) -> bool {
+    !user.disabled && !user.password_reset_required
+}
+
+/// Access tokens only reach the task routes. Reading needs any task scope, everything
+/// else needs `TasksWrite`. Account management always requires a signed-in session.
+fn has_required_scope(req: &ServiceRequest, scopes: &[TokenScope]) -> bool {
+    let path = req.path();
+    if path != "/task" && !path.starts_with("/task/") {
+        return false;
+    }
+
+    if req.method() == Method::GET || req.method() == Method::HEAD {
+        scopes.contains(&TokenScope::TasksRead) || scopes.contains(&TokenScope::TasksWrite)
+    } else {
+        scopes.contains(&TokenScope::TasksWrite)
+    }
+}
+
+/// Lets the request through only if the bearer middleware authenticated an admin.
+/// Meant to be used with `wrap_fn` on scopes nested in the authenticated scope.
+pub fn admin_guard<S, B>(req: ServiceRequest, srv: &S) -> Either<S::Future, Ready<Result<ServiceResponse<B>, Error>>>
+    where S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> {
+    let is_admin = req.extensions().get::<User>()
+        .is_some_and(|user| user.role == Role::Admin);
+
+    if is_admin {
+        Either::Left(srv.call(req))
+    } else {
+        Either::Right(ready(Err(ErrorForbidden("Admin role required"))))
+    }
+}
+
 async fn find_user_by_claims(db: &Data<UserRepository>, claims: &TokenClaims) -> Result<Option<User>, ()> {
-    match db.find_by_email(&claims.email).await {
+    let user_id = match ObjectId::parse_str(&claims.sub) {
+        Ok(id) => id,
+        Err(_) => return Err(()),
+    };
+
+    match db.find_by_id(&user_id).await {
         Ok(user_option) => Ok(user_option),
         Err(_) => Err(()),
     }
@@ -49,4 +141,21 @@ pub async fn validate_request_body<T>(body: Json<T>) -> Result<T, HttpResponse>
         Ok(_) => Ok(value),
         Err(e) => Err(HttpResponse::BadRequest().body(format!("{}", e)))
     }
-}
\ No newline at end of file
+}
+
+/// Bad request for a check that needs more than the request body, in the same format as
+/// the errors of `validate_request_body`.
+pub fn validation_error(field: &'static str, error: ValidationError) -> HttpResponse {
+    let mut errors = ValidationErrors::new();
+    errors.add(field, error);
+    HttpResponse::BadRequest().body(format!("{}", errors))
+}
+
+pub async fn validate_query<T>(query: Query<T>) -> Result<T, HttpResponse>
+    where T: DeserializeOwned + Validate + 'static {
+    let value = query.into_inner();
+    match value.validate() {
+        Ok(_) => Ok(value),
+        Err(e) => Err(HttpResponse::BadRequest().body(format!("{}", e)))
+    }
+}